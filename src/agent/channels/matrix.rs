@@ -0,0 +1,347 @@
+#![allow(dead_code)]
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use crate::agent::Agent;
+use crate::agent::markdown;
+use crate::agent::scheduler::Scheduler;
+use crate::tools::{Attachment, AttachmentData};
+use super::{Channel, Inbound};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// How long a `/sync` waits for events. It is also the worst-case delay on shutdown.
+const SYNC_TIMEOUT_MS: u64 = 20_000;
+/// Matrix allows 64 KB per event; long replies are split well below that.
+const MAX_MESSAGE_CHARS: usize = 16_000;
+
+/// A Matrix bot account driven through the client-server API: `/sync` long polls for
+/// room messages and replies are sent as `m.text` with an HTML body. The bot joins
+/// rooms it is invited to by an allowed user, and won't start without an allow list.
+pub struct MatrixChannel {
+    homeserver: String,
+    token: String,
+    allowed_users: Vec<String>,
+    tool_profile: Option<String>,
+    max_concurrent: usize,
+    scheduler: Option<Scheduler>,
+    /// Where the `/sync` position is kept between restarts.
+    state_file: Option<PathBuf>,
+    /// Filled from `whoami` on connect; the bot's own messages are skipped.
+    user_id: String,
+    since: Mutex<Option<String>>,
+    txn: AtomicU64,
+}
+
+impl MatrixChannel {
+    pub fn new(homeserver: &str, access_token: &str, allowed_users: Vec<String>) -> Self {
+        Self {
+            homeserver: homeserver.trim_end_matches('/').to_string(),
+            token: access_token.to_string(),
+            allowed_users,
+            tool_profile: None,
+            max_concurrent: 4,
+            scheduler: None,
+            state_file: None,
+            user_id: String::new(),
+            since: Mutex::new(None),
+            txn: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_tool_profile(mut self, profile: Option<String>) -> Self {
+        self.tool_profile = profile;
+        self
+    }
+
+    pub fn with_max_concurrent(mut self, max: usize) -> Self {
+        self.max_concurrent = max;
+        self
+    }
+
+    /// Fires due scheduled jobs in the rooms they were created in.
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Persists the sync position, so a restart neither repeats nor skips messages.
+    pub fn with_state_file(mut self, path: PathBuf) -> Self {
+        self.state_file = Some(path);
+        self
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    /// Calls the client-server API and returns the JSON response, turning Matrix
+    /// `errcode` responses into errors.
+    fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value> {
+        let mut command = Command::new("curl");
+        command.arg("-s")
+            .arg("-X").arg(method)
+            .arg("--connect-timeout").arg("10")
+            .arg("--max-time").arg((SYNC_TIMEOUT_MS / 1000 + 30).to_string())
+            .arg("-H").arg(format!("Authorization: Bearer {}", self.token))
+            .arg(format!("{}{}", self.homeserver, path));
+        if body.is_some() {
+            command.arg("-H").arg("Content-Type: application/json").arg("--data-binary").arg("@-");
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        if let (Some(mut stdin), Some(body)) = (child.stdin.take(), body) {
+            serde_json::to_writer(&mut stdin, body)?;
+        }
+        let output = child.wait_with_output()?;
+        parse_response(method, path, &output.stdout)
+    }
+
+    /// Uploads a file to the media repository and returns its `mxc://` URI.
+    fn upload(&self, attachment: &Attachment) -> Result<String> {
+        let data = match &attachment.data {
+            AttachmentData::Path(p) => format!("@{}", p.display()),
+            AttachmentData::Bytes(_) => "@-".to_string(),
+        };
+        let path = format!("/_matrix/media/v3/upload?filename={}", encode(&attachment.name));
+        let mut child = Command::new("curl")
+            .arg("-s")
+            .arg("-X").arg("POST")
+            .arg("--connect-timeout").arg("10")
+            .arg("--max-time").arg("120")
+            .arg("-H").arg(format!("Authorization: Bearer {}", self.token))
+            .arg("-H").arg(format!("Content-Type: {}", attachment.mime))
+            .arg("--data-binary").arg(data)
+            .arg(format!("{}{}", self.homeserver, path))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            if let AttachmentData::Bytes(bytes) = &attachment.data {
+                stdin.write_all(bytes)?;
+            }
+        }
+        let output = child.wait_with_output()?;
+        let response = parse_response("POST", &path, &output.stdout)?;
+        response["content_uri"].as_str()
+            .map(|s| s.to_string())
+            .ok_or_else(|| anyhow!("Matrix upload returned no content URI"))
+    }
+
+    fn send_event(&self, room: &str, content: &Value) -> Result<()> {
+        // Transaction ids make retried sends idempotent; they only need to be unique per token
+        let txn = format!("redclaw-{}-{}", time::OffsetDateTime::now_utc().unix_timestamp_nanos(), self.txn.fetch_add(1, Ordering::SeqCst));
+        let path = format!("/_matrix/client/v3/rooms/{}/send/m.room.message/{}", encode(room), txn);
+        self.request("PUT", &path, Some(content))?;
+        Ok(())
+    }
+
+    fn allows(&self, id: &str) -> bool {
+        self.allowed_users.iter().any(|a| a == id)
+    }
+
+    /// Joins rooms an allowed user invited the bot to.
+    fn accept_invites(&self, invites: &Value) {
+        for (room, invite) in invites.as_object().into_iter().flatten() {
+            let inviter = invite["invite_state"]["events"].as_array().into_iter().flatten()
+                .find(|e| e["type"] == "m.room.member" && e["state_key"] == self.user_id.as_str())
+                .and_then(|e| e["sender"].as_str())
+                .unwrap_or_default();
+            if !self.allows(inviter) && !self.allows(room) {
+                println!("Ignoring invite to {} from {}", room, inviter);
+                continue;
+            }
+            match self.request("POST", &format!("/_matrix/client/v3/rooms/{}/join", encode(room)), Some(&serde_json::json!({}))) {
+                Ok(_) => println!("Joined {} (invited by {})", room, inviter),
+                Err(e) => eprintln!("Failed to join {}: {}", room, e),
+            }
+        }
+    }
+
+    /// A text message from someone else, as an inbound message.
+    fn inbound(&self, room: &str, event: &Value) -> Option<Inbound> {
+        let sender = event["sender"].as_str()?;
+        if event["type"] != "m.room.message" || sender == self.user_id || event["content"]["msgtype"] != "m.text" {
+            return None;
+        }
+        Some(Inbound {
+            conversation: room.to_string(),
+            user_id: sender.to_string(),
+            user_name: sender.to_string(),
+            text: event["content"]["body"].as_str()?.trim().to_string(),
+            attachments: Vec::new(),
+            raw: event.clone(),
+        })
+    }
+}
+
+impl Channel for MatrixChannel {
+    fn name(&self) -> &str {
+        "matrix"
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        if self.allowed_users.is_empty() {
+            return Err(anyhow!("channels.matrix.allow_from is empty; list the users or rooms the bot may answer"));
+        }
+        let me = self.request("GET", "/_matrix/client/v3/account/whoami", None)?;
+        self.user_id = me["user_id"].as_str().ok_or_else(|| anyhow!("Matrix whoami returned no user id"))?.to_string();
+        let saved = self.state_file.as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str::<Value>(&s).ok())
+            .and_then(|v| v["since"].as_str().map(|s| s.to_string()));
+        *self.since.lock().unwrap_or_else(|e| e.into_inner()) = saved;
+        println!("Matrix connected as {}", self.user_id);
+        Ok(())
+    }
+
+    fn receive(&self) -> Result<Vec<Inbound>> {
+        let since = self.since.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let path = match &since {
+            Some(s) => format!("/_matrix/client/v3/sync?timeout={}&since={}", SYNC_TIMEOUT_MS, encode(s)),
+            None => "/_matrix/client/v3/sync?timeout=0".to_string(),
+        };
+        let sync = self.request("GET", &path, None)?;
+        let next = sync["next_batch"].as_str().ok_or_else(|| anyhow!("Matrix sync returned no next_batch"))?;
+
+        self.accept_invites(&sync["rooms"]["invite"]);
+        let mut messages = Vec::new();
+        // The first sync only marks where history ends; old messages aren't answered
+        if since.is_some() {
+            for (room, joined) in sync["rooms"]["join"].as_object().into_iter().flatten() {
+                for event in joined["timeline"]["events"].as_array().into_iter().flatten() {
+                    messages.extend(self.inbound(room, event));
+                }
+            }
+        }
+
+        *self.since.lock().unwrap_or_else(|e| e.into_inner()) = Some(next.to_string());
+        if let Some(path) = &self.state_file {
+            if let Err(e) = crate::utils::write_atomic(path, serde_json::json!({ "since": next }).to_string().as_bytes()) {
+                eprintln!("Failed to save the Matrix sync position: {}", e);
+            }
+        }
+        Ok(messages)
+    }
+
+    /// `!abc:example.org` becomes `matrix-abc_3Aexample.org`: the leading `!` every room id
+    /// has is dropped, and other bytes a key can't hold, `_` included, become `_XX` hex,
+    /// so distinct rooms never share a session.
+    fn session_key(&self, conversation: &str) -> String {
+        let room = conversation.strip_prefix('!').unwrap_or(conversation);
+        let mut key = String::from("matrix-");
+        for b in room.bytes() {
+            if b.is_ascii_alphanumeric() || b == b'-' || b == b'.' {
+                key.push(b as char);
+            } else {
+                key.push_str(&format!("_{:02X}", b));
+            }
+        }
+        key
+    }
+
+    fn is_allowed(&self, msg: &Inbound) -> bool {
+        self.allows(&msg.user_id) || self.allows(&msg.conversation)
+    }
+
+    fn send_text(&self, conversation: &str, text: &str) -> Result<()> {
+        for chunk in markdown::split_markdown(text, MAX_MESSAGE_CHARS) {
+            self.send_event(conversation, &serde_json::json!({
+                "msgtype": "m.text",
+                "body": chunk,
+                "format": "org.matrix.custom.html",
+                "formatted_body": to_matrix_html(&chunk),
+            }))?;
+        }
+        Ok(())
+    }
+
+    fn send_attachment(&self, conversation: &str, attachment: &Attachment) -> Result<()> {
+        let uri = self.upload(attachment)?;
+        let msgtype = if attachment.mime.starts_with("image/") { "m.image" } else { "m.file" };
+        self.send_event(conversation, &serde_json::json!({
+            "msgtype": msgtype,
+            "body": attachment.name,
+            "url": uri,
+            "info": { "mimetype": attachment.mime },
+        }))
+    }
+
+    fn typing(&self, conversation: &str) -> Result<()> {
+        let path = format!("/_matrix/client/v3/rooms/{}/typing/{}", encode(conversation), encode(&self.user_id));
+        self.request("PUT", &path, Some(&serde_json::json!({ "typing": true, "timeout": 30000 })))?;
+        Ok(())
+    }
+
+    fn tool_profile(&self) -> Option<&str> {
+        self.tool_profile.as_deref()
+    }
+
+    fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    fn scheduler(&self) -> Option<&Scheduler> {
+        self.scheduler.as_ref()
+    }
+
+    fn run(self: Arc<Self>, agent: Agent) -> Result<()> {
+        super::serve(self, agent)
+    }
+}
+
+fn parse_response(method: &str, path: &str, body: &[u8]) -> Result<Value> {
+    // Tokens can appear in query strings; keep them out of error messages
+    let path = path.split('?').next().unwrap_or_default();
+    if body.is_empty() {
+        return Err(anyhow!("Matrix {} {} failed: no response", method, path));
+    }
+    let value: Value = serde_json::from_slice(body)
+        .map_err(|_| anyhow!("Matrix {} {} failed: {}", method, path, String::from_utf8_lossy(body)))?;
+    if let Some(code) = value["errcode"].as_str() {
+        return Err(anyhow!("Matrix {} {} failed: {} {}", method, path, code, value["error"].as_str().unwrap_or_default()));
+    }
+    Ok(value)
+}
+
+/// The Telegram HTML subset is valid Matrix HTML, except that line breaks outside
+/// code blocks must be explicit.
+fn to_matrix_html(text: &str) -> String {
+    let html = markdown::to_telegram_html(text);
+    let mut out = String::with_capacity(html.len());
+    let mut in_pre = false;
+    let mut rest = html.as_str();
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("<pre") {
+            in_pre = true;
+        } else if rest.starts_with("</pre>") {
+            in_pre = false;
+        }
+        if c == '\n' && !in_pre {
+            out.push_str("<br>");
+        } else {
+            out.push(c);
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// Percent-encodes a path segment or query value.
+fn encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
@@ -0,0 +1,471 @@
+#![allow(dead_code)]
+pub mod telegram;
+pub mod matrix;
+
+pub use telegram::{TelegramBot, Webhook, read_offset, session_key, strip_mention, transcribe, write_offset};
+pub use matrix::MatrixChannel;
+
+use anyhow::{Result, anyhow};
+use crate::agent::{Agent, Progress};
+use crate::agent::consolidate::ConsolidateOptions;
+use crate::agent::scheduler::{Job, JobAction, Scheduler};
+use crate::agent::workers::ChatWorkers;
+use crate::tools::Attachment;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long shutdown waits for queued and running replies.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(120);
+/// How often due scheduled jobs are queued.
+const JOB_TICK: Duration = Duration::from_secs(20);
+
+/// A message received on a channel, reduced to what the agent needs.
+#[derive(Debug, Clone)]
+pub struct Inbound {
+    /// Where the reply goes: a chat id, a room id.
+    pub conversation: String,
+    pub user_id: String,
+    /// Handle or display name, for logs.
+    pub user_name: String,
+    pub text: String,
+    /// Files already saved in the workspace.
+    pub attachments: Vec<Attachment>,
+    /// The service's own event, for adapter hooks that need more than the fields above.
+    pub raw: serde_json::Value,
+}
+
+/// A `receive` error that retrying won't fix, like another instance holding the bot
+/// token. `serve` stops the channel with it.
+#[derive(Debug)]
+pub struct Fatal(pub String);
+
+impl std::fmt::Display for Fatal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Fatal {}
+
+/// An answer in the works. Adapters that show more than a typing indicator, like a
+/// status message edited as the run goes, return one from `Channel::start_reply`.
+pub trait Reply: Send + Sync {
+    /// Reports what the run is doing.
+    fn progress(&self, event: &Progress);
+
+    /// Delivers the answer, e.g. in place of the status message.
+    fn send_text(&self, text: &str) -> Result<()>;
+
+    fn send_attachment(&self, attachment: &Attachment) -> Result<()>;
+}
+
+/// A chat service the agent can be reached on. An adapter turns the service's events
+/// into `Inbound` messages and delivers replies; `serve` does the rest: one queue per
+/// conversation, the allow list, agent runs and scheduled jobs.
+pub trait Channel: Send + Sync {
+    /// Short lowercase name, used as the session origin and the scheduler's channel.
+    fn name(&self) -> &str;
+
+    /// Called once before the channel runs, e.g. to learn its own identity.
+    fn connect(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Waits for new messages, up to a long-poll timeout. May return none.
+    fn receive(&self) -> Result<Vec<Inbound>>;
+
+    /// Session key of a conversation; must pass `SessionStore::validate_key`.
+    fn session_key(&self, conversation: &str) -> String;
+
+    /// Whether a message is meant for the bot at all, e.g. in a group where it should
+    /// only answer when mentioned. Other messages are dropped without a reply.
+    fn is_addressed(&self, _msg: &Inbound) -> bool {
+        true
+    }
+
+    fn is_allowed(&self, msg: &Inbound) -> bool;
+
+    /// Fetches files the message refers to into `inbox`, adding them to `attachments`
+    /// and describing them in `text`. Runs on the conversation's queue, as it may be slow.
+    fn ingest(&self, _msg: &mut Inbound, _inbox: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// Answers a command without the agent. Returns `None` for anything the agent
+    /// should see instead.
+    fn command(&self, agent: &mut Agent, name: &str, _args: &str) -> Result<Option<String>> {
+        builtin_command(agent, name)
+    }
+
+    /// Sends a Markdown reply, split and formatted as the service requires.
+    fn send_text(&self, conversation: &str, text: &str) -> Result<()>;
+
+    /// Replies to `msg` directly, e.g. quoting it where that makes clear who is answered.
+    fn answer(&self, msg: &Inbound, text: &str) -> Result<()> {
+        self.send_text(&msg.conversation, text)
+    }
+
+    fn send_attachment(&self, conversation: &str, attachment: &Attachment) -> Result<()>;
+
+    /// Starts an agent reply in `conversation`, to `msg` unless a scheduled job is
+    /// running. `None`, the default, means a typing indicator and then `send_text`.
+    fn start_reply(&self, _conversation: &str, _msg: Option<&Inbound>) -> Option<Box<dyn Reply>> {
+        None
+    }
+
+    /// Shows that a reply is being worked on, where the service can.
+    fn typing(&self, _conversation: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Reports what a run is doing. By default each tool call renews the typing indicator.
+    fn progress(&self, conversation: &str, event: &Progress) {
+        if let Progress::Tool { .. } = event {
+            let _ = self.typing(conversation);
+        }
+    }
+
+    fn tool_profile(&self) -> Option<&str> {
+        None
+    }
+
+    /// Conversations whose runs may execute at the same time.
+    fn max_concurrent(&self) -> usize {
+        4
+    }
+
+    fn scheduler(&self) -> Option<&Scheduler> {
+        None
+    }
+
+    /// Runs until shutdown. Adapters normally hand themselves to `serve`.
+    fn run(self: Arc<Self>, agent: Agent) -> Result<()>;
+}
+
+/// Work queued for one conversation.
+enum Task {
+    Message(Inbound),
+    Job(Job),
+}
+
+/// Cancel flags of the runs in progress, by session key.
+type Running = Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>;
+
+/// The receive loop shared by adapters: messages are answered in order within a
+/// conversation and concurrently across conversations, each on a clone of `agent`.
+pub fn serve<C: Channel + 'static>(channel: Arc<C>, agent: Agent) -> Result<()> {
+    let running: Running = Arc::default();
+    let workers = {
+        let channel = channel.clone();
+        let running = running.clone();
+        ChatWorkers::new(channel.max_concurrent(), move |_key: &str, task: Task| {
+            let mut agent = agent.clone();
+            let result = match task {
+                Task::Message(msg) => handle_message(&channel, &mut agent, &running, msg),
+                Task::Job(job) => run_job(&channel, &mut agent, &running, job),
+            };
+            if let Err(e) = result {
+                eprintln!("Error handling {} message: {}", channel.name(), e);
+            }
+        })
+    };
+    if channel.scheduler().is_some() {
+        let channel = channel.clone();
+        let workers = workers.clone();
+        std::thread::Builder::new()
+            .name(format!("{}-jobs", channel.name()))
+            .stack_size(256 * 1024)
+            .spawn(move || {
+                while !crate::utils::sleep_unless_shutdown(JOB_TICK) {
+                    queue_due_jobs(&*channel, &workers);
+                }
+            })?;
+    }
+    println!("{} channel started ({} concurrent conversations).", channel.name(), channel.max_concurrent());
+
+    let mut outcome = Ok(());
+    let mut failures = 0;
+    while !crate::utils::shutdown_requested() {
+        match channel.receive() {
+            Ok(messages) => {
+                failures = 0;
+                for msg in messages {
+                    if !channel.is_addressed(&msg) {
+                        continue;
+                    }
+                    if !channel.is_allowed(&msg) {
+                        println!("Unauthorized user: {}", msg.user_name);
+                        continue;
+                    }
+                    let key = channel.session_key(&msg.conversation);
+                    if cancel_run(&running, &key, &msg) {
+                        if let Err(e) = channel.answer(&msg, "Stopping the current reply...") {
+                            eprintln!("Failed to send message: {}", e);
+                        }
+                        continue;
+                    }
+                    if let Err(e) = workers.submit(&key, Task::Message(msg)) {
+                        eprintln!("Failed to start worker for {}: {}", key, e);
+                    }
+                }
+            }
+            Err(e) if e.is::<Fatal>() => {
+                outcome = Err(e);
+                break;
+            }
+            Err(e) => {
+                failures += 1;
+                eprintln!("Receiving from {} failed: {}; retrying in {}s", channel.name(), e, backoff_delay(failures).as_secs());
+            }
+        }
+        if failures > 0 {
+            crate::utils::sleep_unless_shutdown(backoff_delay(failures));
+        }
+    }
+    finish_runs(&workers, channel.name());
+    outcome
+}
+
+/// Runs every channel on its own thread until shutdown. A channel that fails doesn't
+/// stop the others; the first error is returned once all have stopped.
+pub fn run_all(channels: Vec<Box<dyn Channel>>, agent: Agent) -> Result<()> {
+    let mut handles = Vec::new();
+    for mut channel in channels {
+        let agent = agent.clone();
+        let name = channel.name().to_string();
+        let handle = std::thread::Builder::new()
+            .name(format!("channel-{}", name))
+            .spawn(move || {
+                channel.connect()?;
+                Arc::<dyn Channel>::from(channel).run(agent)
+            })?;
+        handles.push((name, handle));
+    }
+
+    let mut first_error = None;
+    for (name, handle) in handles {
+        let result = handle.join().unwrap_or_else(|_| Err(anyhow!("the channel thread panicked")));
+        if let Err(e) = result {
+            eprintln!("{} channel stopped: {}", name, e);
+            first_error.get_or_insert(e);
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+/// The commands every channel answers itself.
+pub fn builtin_command(agent: &mut Agent, name: &str) -> Result<Option<String>> {
+    match name {
+        "reset" => {
+            agent.reset_session()?;
+            Ok(Some("Started a new conversation. The previous one is archived.".to_string()))
+        }
+        // Only reached when no run was active when /cancel arrived
+        "cancel" => Ok(Some("Nothing to cancel.".to_string())),
+        _ => Ok(None),
+    }
+}
+
+fn handle_message<C: Channel + 'static>(channel: &Arc<C>, agent: &mut Agent, running: &Running, mut msg: Inbound) -> Result<()> {
+    let key = channel.session_key(&msg.conversation);
+    agent.set_session(&key);
+    agent.set_origin(channel.name(), &msg.conversation, Some(&msg.user_id));
+    agent.set_tool_profile(channel.tool_profile());
+
+    if let Some((name, args)) = parse_command(&msg.text) {
+        if let Some(text) = channel.command(agent, &name, args)? {
+            return channel.answer(&msg, &text);
+        }
+    }
+
+    // Downloads and transcription can take a while too, so the reply starts here
+    let reply = start_reply(channel, &msg.conversation, Some(&msg));
+    // Each conversation's files are kept apart, so tools can't read someone else's
+    if let Err(e) = channel.ingest(&mut msg, &agent.workspace().join("inbox").join(&key)) {
+        return reply.send_text(&format!("Couldn't process the attachment: {}", e));
+    }
+    if msg.text.is_empty() && msg.attachments.is_empty() {
+        return reply.send_text("There is nothing in that message I can read. Please send me a question or command.");
+    }
+
+    crate::utils::print_box_top(&format!("User ({})", msg.user_name));
+    crate::utils::print_box_line(&msg.text);
+    println!();
+
+    let result = run_with_reply(running, &key, agent, &reply, |agent| agent.run_with_attachments(&msg.text, &msg.attachments));
+    deliver(&*reply, agent, result)
+}
+
+/// The adapter's reply, or by default the typing indicator followed by plain messages.
+fn start_reply<C: Channel + 'static>(channel: &Arc<C>, conversation: &str, msg: Option<&Inbound>) -> Arc<dyn Reply> {
+    match channel.start_reply(conversation, msg) {
+        Some(reply) => Arc::from(reply),
+        None => {
+            let _ = channel.typing(conversation);
+            Arc::new(PlainReply { channel: channel.clone(), conversation: conversation.to_string() })
+        }
+    }
+}
+
+struct PlainReply<C> {
+    channel: Arc<C>,
+    conversation: String,
+}
+
+impl<C: Channel> Reply for PlainReply<C> {
+    fn progress(&self, event: &Progress) {
+        self.channel.progress(&self.conversation, event);
+    }
+
+    fn send_text(&self, text: &str) -> Result<()> {
+        self.channel.send_text(&self.conversation, text)
+    }
+
+    fn send_attachment(&self, attachment: &Attachment) -> Result<()> {
+        self.channel.send_attachment(&self.conversation, attachment)
+    }
+}
+
+/// Runs the agent with its progress going to `reply` and a cancel flag registered
+/// under session `key`, so `/cancel` can stop it.
+fn run_with_reply<F: FnOnce(&mut Agent) -> Result<String>>(running: &Running, key: &str, agent: &mut Agent, reply: &Arc<dyn Reply>, run: F) -> Result<String> {
+    let flag = Arc::new(AtomicBool::new(false));
+    lock(running).insert(key.to_string(), flag.clone());
+    let progress = {
+        let reply = reply.clone();
+        Arc::new(move |event: &Progress| reply.progress(event))
+    };
+    agent.set_progress(Some(progress));
+    agent.set_cancel_flag(Some(flag.clone()));
+    let result = run(agent);
+    agent.set_cancel_flag(None);
+    agent.set_progress(None);
+    let mut running = lock(running);
+    if running.get(key).is_some_and(|f| Arc::ptr_eq(f, &flag)) {
+        running.remove(key);
+    }
+    result
+}
+
+/// `/cancel` can't wait in the conversation's queue behind the run it means to stop, so
+/// it is handled as soon as it arrives. Returns whether a run was signalled.
+fn cancel_run(running: &Running, key: &str, msg: &Inbound) -> bool {
+    let is_cancel = parse_command(&msg.text).is_some_and(|(c, _)| c == "cancel");
+    match lock(running).get(key).filter(|_| is_cancel) {
+        Some(flag) => {
+            flag.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Queues due jobs behind their conversation's messages; system jobs get a queue of their own.
+fn queue_due_jobs<C: Channel>(channel: &C, workers: &ChatWorkers<Task>) {
+    let scheduler = match channel.scheduler() {
+        Some(s) => s,
+        None => return,
+    };
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let jobs = match scheduler.take_due(channel.name(), now) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            eprintln!("Failed to read schedule: {}", e);
+            return;
+        }
+    };
+    for job in jobs {
+        let key = if job.action.is_some() || job.session_key.is_empty() { job.id.clone() } else { job.session_key.clone() };
+        if let Err(e) = workers.submit(&key, Task::Job(job)) {
+            eprintln!("Failed to start worker for {}: {}", key, e);
+        }
+    }
+}
+
+fn run_job<C: Channel + 'static>(channel: &Arc<C>, agent: &mut Agent, running: &Running, job: Job) -> Result<()> {
+    if let Some(JobAction::ConsolidateMemory { archive }) = &job.action {
+        let opts = ConsolidateOptions { dry_run: false, archive: *archive };
+        let consolidated = agent.consolidate_memory(&opts)?;
+        println!("Consolidated {} day(s) into long-term memory", consolidated.iter().map(|s| s.days.len()).sum::<usize>());
+        return Ok(());
+    }
+    let conversation = match &job.chat_id {
+        Some(c) => c.clone(),
+        None => return Ok(()),
+    };
+    crate::utils::print_box_top(&format!("Scheduled ({})", job.id));
+    crate::utils::print_box_line(&job.prompt);
+    println!();
+
+    agent.set_session(&job.session_key);
+    agent.set_origin(channel.name(), &conversation, job.user_id.as_deref());
+    agent.set_tool_profile(channel.tool_profile());
+    let reply = start_reply(channel, &conversation, None);
+    let prompt = format!("[Scheduled job {}] {}", job.id, job.prompt);
+    let result = run_with_reply(running, &job.session_key, agent, &reply, |agent| agent.run(&prompt));
+    deliver(&*reply, agent, result)
+}
+
+/// Sends the outcome of a run, with any files the agent attached.
+fn deliver(reply: &dyn Reply, agent: &mut Agent, result: Result<String>) -> Result<()> {
+    match result {
+        Ok(response) => {
+            let response = if response.is_empty() { "I processed your request but have no text response.".to_string() } else { response };
+            println!("  Claw:");
+            crate::utils::print_box_line(&response);
+            crate::utils::print_box_bottom();
+            reply.send_text(&response)?;
+            for attachment in agent.take_attachments() {
+                if let Err(e) = reply.send_attachment(&attachment) {
+                    eprintln!("Failed to send attachment {}: {}", attachment.name, e);
+                }
+            }
+            Ok(())
+        }
+        Err(e) => {
+            println!("  Error:");
+            crate::utils::print_box_line(&format!("{}", e));
+            crate::utils::print_box_bottom();
+            reply.send_text(&format!("Agent Error: {}", e))
+        }
+    }
+}
+
+/// Lets the runs already queued finish before a channel stops.
+fn finish_runs<T: Send + 'static>(workers: &ChatWorkers<T>, name: &str) {
+    let pending = workers.pending();
+    if pending > 0 {
+        println!("Waiting for {} {} message(s) in progress...", pending, name);
+        if !workers.wait_idle(SHUTDOWN_GRACE) {
+            eprintln!("Gave up waiting after {}s", SHUTDOWN_GRACE.as_secs());
+        }
+    }
+    println!("{} channel stopped.", name);
+}
+
+/// Splits `/name@bot args` into the lowercased command name and its arguments.
+/// Returns `None` for text that isn't a command.
+pub fn parse_command(text: &str) -> Option<(String, &str)> {
+    let rest = text.trim_start().strip_prefix('/')?;
+    let (word, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let name = word.split('@').next().unwrap_or_default();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    Some((name.to_ascii_lowercase(), args.trim()))
+}
+
+/// Delay before retrying after `failures` consecutive failed polls: 1s, doubling up to a minute.
+pub fn backoff_delay(failures: u32) -> Duration {
+    if failures == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_secs(1u64 << (failures - 1).min(6)).min(Duration::from_secs(60))
+}
+
+fn lock<T>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
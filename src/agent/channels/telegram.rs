@@ -0,0 +1,1149 @@
+#![allow(dead_code)]
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use crate::agent::{Agent, Progress, ProgressFn};
+use crate::agent::scheduler::Scheduler;
+use crate::agent::{http, markdown};
+use super::{Channel, Fatal, Inbound, Reply, builtin_command, parse_command};
+use crate::config::WebhookConfig;
+use crate::tools::{Attachment, AttachmentData};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use std::process::{Child, Command, Stdio};
+
+#[derive(Deserialize)]
+struct TgMessage {
+    #[serde(default)]
+    message_id: i64,
+    message_thread_id: Option<i64>,
+    #[serde(default)]
+    is_topic_message: bool,
+    chat: TgChat,
+    text: Option<String>,
+    from: Option<TgUser>,
+    caption: Option<String>,
+    /// Available sizes of a photo, smallest first.
+    photo: Option<Vec<TgFile>>,
+    document: Option<TgFile>,
+    voice: Option<TgFile>,
+    reply_to_message: Option<Box<TgMessage>>,
+}
+
+impl TgMessage {
+    /// The message an inbound one was made from.
+    fn of(msg: &Inbound) -> Option<Self> {
+        Self::deserialize(&msg.raw).ok()
+    }
+
+    fn is_group(&self) -> bool {
+        matches!(self.chat.kind.as_str(), "group" | "supergroup")
+    }
+
+    /// The forum topic the message was posted in. Other supergroup messages can carry a
+    /// thread id too (reply chains), but only topics are separate conversations.
+    fn topic(&self) -> Option<i64> {
+        self.message_thread_id.filter(|_| self.is_topic_message)
+    }
+
+    fn session_key(&self) -> String {
+        session_key(self.chat.id, self.topic())
+    }
+
+    /// Text, or the caption of a photo or document.
+    fn body(&self) -> &str {
+        self.text.as_deref().or(self.caption.as_deref()).unwrap_or_default()
+    }
+}
+
+/// The fields shared by photo sizes, documents and voice notes.
+#[derive(Deserialize)]
+struct TgFile {
+    file_id: String,
+    file_unique_id: String,
+    file_size: Option<u64>,
+    file_name: Option<String>,
+    mime_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TgChat {
+    id: i64,
+    /// `private`, `group`, `supergroup` or `channel`.
+    #[serde(rename = "type", default)]
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct TgUser {
+    id: i64,
+    username: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TgResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    error_code: Option<i64>,
+    description: Option<String>,
+}
+
+enum Poll {
+    /// `next_offset` is set when the batch wasn't empty, even if no update was readable.
+    Updates { next_offset: Option<i64>, updates: Vec<Value> },
+    /// Telegram's 409: another process is polling or a webhook is set.
+    Conflict(String),
+}
+
+/// A conversation is a private chat or group (`chat_id`), or one forum topic in a group
+/// (`chat_id-topic-N`), as produced by `session_key`.
+pub struct TelegramBot {
+    api: BotApi,
+    allowed_users: Vec<String>,
+    tool_profile: Option<String>,
+    scheduler: Option<Scheduler>,
+    max_concurrent: usize,
+    webhook: Option<WebhookConfig>,
+    transcriber: Option<String>,
+    offset_file: Option<PathBuf>,
+    /// Next update id to poll for.
+    offset: Mutex<i64>,
+    /// Consecutive 409 responses to polling.
+    conflicts: Mutex<u32>,
+    /// The long poll in flight, parked here so shutdown can kill it.
+    poll: Arc<Mutex<Option<Child>>>,
+    /// Bound in `connect` when updates come through a webhook.
+    listener: Option<Webhook>,
+    /// Filled from `getMe` at start; groups need it to tell who is being addressed.
+    bot_id: i64,
+    bot_username: String,
+}
+
+/// Where a reply goes: a chat, the forum topic it came from, and in groups the
+/// message being answered, so it's clear who the bot is talking to.
+#[derive(Clone, Copy)]
+struct Target {
+    chat_id: i64,
+    thread_id: Option<i64>,
+    reply_to: Option<i64>,
+}
+
+impl Target {
+    /// The chat and topic of a conversation.
+    fn conversation(conversation: &str) -> Result<Self> {
+        let (chat, topic) = match conversation.rsplit_once("-topic-") {
+            Some((chat, topic)) => (chat, Some(topic.parse().map_err(|_| anyhow!("'{}' is not a Telegram conversation", conversation))?)),
+            None => (conversation, None),
+        };
+        Ok(Self { chat_id: parse_chat_id(chat)?, thread_id: topic, reply_to: None })
+    }
+
+    fn reply(msg: &TgMessage) -> Self {
+        Self {
+            chat_id: msg.chat.id,
+            thread_id: msg.topic(),
+            reply_to: msg.is_group().then_some(msg.message_id),
+        }
+    }
+
+    /// A reply to `msg` if there is one, otherwise a new message in `conversation`.
+    fn of(conversation: &str, msg: Option<&Inbound>) -> Result<Self> {
+        match msg.and_then(TgMessage::of) {
+            Some(msg) => Ok(Self::reply(&msg)),
+            None => Self::conversation(conversation),
+        }
+    }
+
+    /// The same destination without quoting, for follow-up messages.
+    fn follow_up(self) -> Self {
+        Self { reply_to: None, ..self }
+    }
+
+    /// Adds the thread and reply fields to a Bot API payload.
+    fn apply(&self, payload: &mut Value) {
+        payload["chat_id"] = self.chat_id.into();
+        if let Some(thread) = self.thread_id {
+            payload["message_thread_id"] = thread.into();
+        }
+        if let Some(message) = self.reply_to {
+            payload["reply_to_message_id"] = message.into();
+            // Still deliver the answer if the question was deleted meanwhile
+            payload["allow_sending_without_reply"] = true.into();
+        }
+    }
+}
+
+/// Commands answered by the bot itself, as registered with `setMyCommands`.
+const COMMANDS: &[(&str, &str)] = &[
+    ("start", "Introduce the bot"),
+    ("help", "List commands"),
+    ("reset", "Start a new conversation"),
+    ("model", "Show the model in use"),
+    ("memory", "Show what memory is loaded"),
+    ("usage", "Show token usage of this conversation"),
+    ("tools", "List the tools available here"),
+    ("cancel", "Stop the current reply"),
+];
+
+/// Below Telegram's 4096-character limit for the text left after parsing markup.
+const MAX_MESSAGE_CHARS: usize = 4000;
+
+/// Bots may not download files larger than this through the Bot API.
+const MAX_DOWNLOAD_BYTES: u64 = 20 * 1024 * 1024;
+/// Consecutive 409 responses tolerated before giving up; a restarting instance may
+/// still hold the poll for a few seconds.
+const MAX_CONFLICTS: u32 = 5;
+
+impl TelegramBot {
+    pub fn new(token: String, allowed_users: Vec<String>) -> Self {
+        Self { api: BotApi::new(token), allowed_users, tool_profile: None, scheduler: None, max_concurrent: 4, webhook: None,
+            transcriber: None, offset_file: None, offset: Mutex::new(0), conflicts: Mutex::new(0), poll: Arc::default(),
+            listener: None, bot_id: 0, bot_username: String::new() }
+    }
+
+    pub fn with_tool_profile(mut self, profile: Option<String>) -> Self {
+        self.tool_profile = profile;
+        self
+    }
+
+    /// Fires due scheduled jobs in the chats and topics they were created in.
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Maximum agent runs in flight across all chats.
+    pub fn with_max_concurrent(mut self, max: usize) -> Self {
+        self.max_concurrent = max;
+        self
+    }
+
+    /// Receive updates through a webhook instead of long polling.
+    pub fn with_webhook(mut self, webhook: Option<WebhookConfig>) -> Self {
+        self.webhook = webhook;
+        self
+    }
+
+    /// Shell command that transcribes voice notes; see `transcribe`.
+    pub fn with_transcriber(mut self, command: Option<String>) -> Self {
+        self.transcriber = command;
+        self
+    }
+
+    /// Where polling keeps the next update id, so a restart neither repeats nor skips
+    /// updates. Without one, a restart gets whatever Telegram still holds.
+    pub fn with_offset_file(mut self, path: PathBuf) -> Self {
+        self.offset_file = Some(path);
+        self
+    }
+
+    /// Bot API server to use instead of api.telegram.org, e.g. a self-hosted one.
+    pub fn with_api_base(mut self, url: &str) -> Self {
+        self.api.base = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// One long poll. The child is parked in `self.poll` while it runs, so shutdown can kill it.
+    fn get_updates(&self, offset: i64) -> Result<Poll> {
+        let mut child = Command::new("curl")
+            .arg("-s")
+            .arg("-L")
+            .arg("--connect-timeout").arg("10")
+            .arg("--max-time").arg("45")
+            .arg(format!("{}?offset={}&timeout=30", self.api.url("getUpdates"), offset))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdout = child.stdout.take();
+        *lock(&self.poll) = Some(child);
+        // Checked after parking the child, so a shutdown in between can't miss it
+        if crate::utils::shutdown_requested() {
+            if let Some(child) = lock(&self.poll).as_mut() {
+                let _ = child.kill();
+            }
+        }
+
+        let mut raw = String::new();
+        let read = stdout.map(|mut out| out.read_to_string(&mut raw)).transpose();
+        let status = lock(&self.poll).take().map(|mut child| child.wait()).transpose()?;
+        read?;
+        if crate::utils::shutdown_requested() {
+            return Ok(Poll::Updates { next_offset: None, updates: Vec::new() });
+        }
+        if raw.trim().is_empty() {
+            return Err(anyhow!("no response ({})", status.map(|s| s.to_string()).unwrap_or_default()));
+        }
+
+        let response: TgResponse<Vec<Value>> = serde_json::from_str(&raw)
+            .map_err(|e| anyhow!("unreadable response: {}. Raw: '{}'", e, raw))?;
+        if !response.ok {
+            let description = response.description.unwrap_or(raw);
+            if response.error_code == Some(409) {
+                return Ok(Poll::Conflict(description));
+            }
+            return Err(anyhow!("Telegram API Error: {}", description));
+        }
+        let updates = response.result.unwrap_or_default();
+        let next_offset = updates.iter().filter_map(|u| u["update_id"].as_i64()).max().map(|id| id + 1);
+        Ok(Poll::Updates { next_offset, updates })
+    }
+
+    /// Polls once, saving the new offset. Telegram answers 409 while another instance
+    /// polls with the same token; a few in a row stop the channel.
+    fn poll_updates(&self) -> Result<Vec<Value>> {
+        let offset = *lock(&self.offset);
+        match self.get_updates(offset)? {
+            Poll::Updates { next_offset, updates } => {
+                *lock(&self.conflicts) = 0;
+                if let Some(next) = next_offset {
+                    *lock(&self.offset) = next;
+                    if let Some(path) = &self.offset_file {
+                        if let Err(e) = write_offset(path, next) {
+                            eprintln!("Failed to save the update offset: {}", e);
+                        }
+                    }
+                }
+                Ok(updates)
+            }
+            Poll::Conflict(description) => {
+                let mut conflicts = lock(&self.conflicts);
+                *conflicts += 1;
+                if *conflicts >= MAX_CONFLICTS {
+                    return Err(Fatal("Another instance kept polling with this bot token; only one may run at a time".to_string()).into());
+                }
+                Err(anyhow!("another instance is polling with this bot token: {}", description))
+            }
+        }
+    }
+
+    /// An update's message as an inbound message, with the bot's @mention taken out.
+    /// Updates are read one by one, so one this bot can't read doesn't block the rest.
+    fn inbound(&self, mut update: Value) -> Option<Inbound> {
+        let raw = update.get_mut("message")?.take();
+        let msg = match TgMessage::deserialize(&raw) {
+            Ok(msg) => msg,
+            Err(e) => {
+                eprintln!("Skipping unreadable update: {}", e);
+                return None;
+            }
+        };
+        let body = msg.body().trim();
+        Some(Inbound {
+            conversation: msg.session_key(),
+            user_id: msg.from.as_ref().map(|u| u.id.to_string()).unwrap_or_default(),
+            user_name: msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string()),
+            text: strip_mention(body, &self.bot_username).unwrap_or_else(|| body.to_string()),
+            attachments: Vec::new(),
+            raw,
+        })
+    }
+
+    fn identify(&mut self) -> Result<()> {
+        let me = self.api.call("getMe", &serde_json::json!({}))?;
+        self.bot_id = me["id"].as_i64().unwrap_or_default();
+        self.bot_username = me["username"].as_str().unwrap_or_default().to_string();
+        Ok(())
+    }
+
+    /// Registers `url` as the webhook; Telegram echoes `secret` in every delivery.
+    pub fn set_webhook(&self, url: &str, secret: &str) -> Result<()> {
+        self.api.call("setWebhook", &serde_json::json!({
+            "url": url,
+            "secret_token": secret,
+            "allowed_updates": ["message"],
+        }))?;
+        Ok(())
+    }
+
+    /// Unregisters the webhook, keeping pending updates for the next getUpdates.
+    pub fn delete_webhook(&self) -> Result<()> {
+        self.api.call("deleteWebhook", &serde_json::json!({ "drop_pending_updates": false }))?;
+        Ok(())
+    }
+
+    /// Publishes the command menu shown by Telegram clients.
+    pub fn register_commands(&self) -> Result<()> {
+        let commands: Vec<Value> = COMMANDS.iter()
+            .map(|(command, description)| serde_json::json!({ "command": command, "description": description }))
+            .collect();
+        self.api.call("setMyCommands", &serde_json::json!({ "commands": commands }))?;
+        Ok(())
+    }
+
+    /// Fetches a file through `getFile` into `dir` as `<unix time>-<name>`.
+    fn download(&self, file: &TgFile, dir: &Path, name: &str) -> Result<PathBuf> {
+        if file.file_size.unwrap_or(0) > MAX_DOWNLOAD_BYTES {
+            return Err(anyhow!("the file is larger than the 20 MB bots may download"));
+        }
+        let info = self.api.call("getFile", &serde_json::json!({ "file_id": file.file_id }))?;
+        let remote = info["file_path"].as_str().ok_or_else(|| anyhow!("Telegram returned no file path"))?;
+        std::fs::create_dir_all(dir)?;
+        let dest = dir.join(format!("{}-{}", time::OffsetDateTime::now_utc().unix_timestamp(), sanitize_file_name(name)));
+
+        let output = Command::new("curl")
+            .arg("-s")
+            .arg("-L")
+            .arg("--fail")
+            .arg("--connect-timeout").arg("10")
+            .arg("--max-time").arg("120")
+            .arg("--max-filesize").arg(MAX_DOWNLOAD_BYTES.to_string())
+            .arg("-o").arg(&dest)
+            .arg(format!("{}/file/bot{}/{}", self.api.base, self.api.token, remote))
+            .output()?;
+        if !output.status.success() {
+            std::fs::remove_file(&dest).ok();
+            return Err(anyhow!("download failed ({})", output.status));
+        }
+        Ok(std::fs::canonicalize(&dest).unwrap_or(dest))
+    }
+}
+
+/// The Bot API calls replies make, apart from the bot so a reply can outlive the call
+/// that started it.
+#[derive(Clone)]
+struct BotApi {
+    token: String,
+    base: String,
+}
+
+impl BotApi {
+    fn new(token: String) -> Self {
+        Self { token, base: "https://api.telegram.org".to_string() }
+    }
+
+    fn url(&self, method: &str) -> String {
+        format!("{}/bot{}/{}", self.base, self.token, method)
+    }
+
+    /// Calls a Bot API method with a JSON payload and returns its `result`.
+    fn call(&self, method: &str, payload: &Value) -> Result<Value> {
+        let mut child = Command::new("curl")
+            .arg("-s")
+            .arg("-L")
+            .arg("-X").arg("POST")
+            .arg("--connect-timeout").arg("10")
+            .arg("--max-time").arg("30")
+            .arg(self.url(method))
+            .arg("-H").arg("Content-Type: application/json")
+            .arg("-d").arg("@-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            serde_json::to_writer(&mut stdin, payload)?;
+        }
+
+        let output = child.wait_with_output()?;
+        let body: Value = serde_json::from_slice(&output.stdout)
+            .map_err(|_| anyhow!("Telegram {} failed: {}", method, String::from_utf8_lossy(&output.stdout)))?;
+        if body["ok"] != true {
+            return Err(anyhow!("Telegram {} failed: {}", method, body["description"].as_str().unwrap_or("unknown error")));
+        }
+        Ok(body["result"].clone())
+    }
+
+    /// Sends one chunk of Markdown rendered as Telegram HTML, falling back to the plain
+    /// text if Telegram rejects the markup.
+    fn send_raw_message(&self, target: Target, text: &str) -> Result<bool> {
+        let url = self.url("sendMessage");
+
+        let send = |text: &str, mode: Option<&str>| -> Result<bool> {
+            let mut payload = serde_json::json!({ "text": text });
+            target.apply(&mut payload);
+            if let Some(m) = mode {
+                if let Some(obj) = payload.as_object_mut() {
+                    obj.insert("parse_mode".to_string(), serde_json::json!(m));
+                }
+            }
+
+            let mut child = Command::new("curl")
+                .arg("-s")
+                .arg("-L")
+                .arg("-X").arg("POST")
+                .arg("--connect-timeout").arg("10")
+                .arg("--max-time").arg("30")
+                .arg(&url)
+                .arg("-H").arg("Content-Type: application/json")
+                .arg("-d").arg("@-")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?;
+
+            if let Some(mut stdin) = child.stdin.take() {
+                serde_json::to_writer(&mut stdin, &payload)?;
+            }
+
+            let output = child.wait_with_output()?;
+            if !output.status.success() || !String::from_utf8_lossy(&output.stdout).contains("\"ok\":true") {
+                if mode.is_none() {
+                    eprintln!("Telegram Final Failure: {}", String::from_utf8_lossy(&output.stdout));
+                } else {
+                    eprintln!("Telegram rejected formatted message, resending as plain text: {}", String::from_utf8_lossy(&output.stdout));
+                }
+                return Ok(false);
+            }
+            Ok(true)
+        };
+
+        if send(&markdown::to_telegram_html(text), Some("HTML"))? { return Ok(true); }
+        send(text, None)
+    }
+
+    /// Uploads an attachment as a photo when Telegram can display it, otherwise as a document.
+    fn send_attachment(&self, target: Target, attachment: &Attachment) -> Result<()> {
+        let (method, field) = match attachment.mime.as_str() {
+            "image/jpeg" | "image/png" | "image/webp" => ("sendPhoto", "photo"),
+            _ => ("sendDocument", "document"),
+        };
+        let form = match &attachment.data {
+            AttachmentData::Path(p) => format!("{}=@\"{}\";type={}", field, p.display(), attachment.mime),
+            AttachmentData::Bytes(_) => format!("{}=@-;filename=\"{}\";type={}", field, attachment.name, attachment.mime),
+        };
+
+        let mut child = Command::new("curl")
+            .arg("-s")
+            .arg("-L")
+            .arg("--connect-timeout").arg("10")
+            .arg("--max-time").arg("120")
+            .arg(self.url(method))
+            .arg("-F").arg(format!("chat_id={}", target.chat_id))
+            .args(target.thread_id.map(|t| ["-F".to_string(), format!("message_thread_id={}", t)]).into_iter().flatten())
+            .arg("-F").arg(form)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            if let AttachmentData::Bytes(bytes) = &attachment.data {
+                use std::io::Write;
+                stdin.write_all(bytes)?;
+            }
+        }
+
+        let output = child.wait_with_output()?;
+        let body = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success() || !body.contains("\"ok\":true") {
+            return Err(anyhow::anyhow!("Telegram {} failed: {}", method, body));
+        }
+        Ok(())
+    }
+
+    /// Sends a Markdown reply, split into messages Telegram accepts. Pieces are cut
+    /// between lines, and a code block spanning two messages is fenced in both.
+    fn send_message(&self, target: Target, text: &str) -> Result<()> {
+        self.send_reply(target, text, None)
+    }
+
+    /// Like `send_message`, but the first piece replaces the status message `status`
+    /// instead of arriving as a new one.
+    fn send_reply(&self, target: Target, text: &str, status: Option<i64>) -> Result<()> {
+        // Only the first piece quotes the message being answered
+        for (i, chunk) in markdown::split_markdown(text, MAX_MESSAGE_CHARS).iter().enumerate() {
+            if let (0, Some(id)) = (i, status) {
+                if self.edit_message(target.chat_id, id, chunk) {
+                    continue;
+                }
+                self.delete_message(target.chat_id, id);
+            }
+            self.send_raw_message(if i == 0 { target } else { target.follow_up() }, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites a message with a chunk of Markdown, as HTML or failing that plain text.
+    fn edit_message(&self, chat_id: i64, message_id: i64, text: &str) -> bool {
+        let edit = |text: &str, mode: Option<&str>| {
+            let mut payload = serde_json::json!({ "chat_id": chat_id, "message_id": message_id, "text": text });
+            if let Some(m) = mode {
+                payload["parse_mode"] = m.into();
+            }
+            match self.call("editMessageText", &payload) {
+                Ok(_) => true,
+                // The status already showed exactly this text
+                Err(e) if e.to_string().contains("message is not modified") => true,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    false
+                }
+            }
+        };
+        edit(&markdown::to_telegram_html(text), Some("HTML")) || edit(text, None)
+    }
+
+    fn delete_message(&self, chat_id: i64, message_id: i64) {
+        if let Err(e) = self.call("deleteMessage", &serde_json::json!({ "chat_id": chat_id, "message_id": message_id })) {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+/// An answer shown as a live status message until the final text replaces it.
+struct TelegramReply {
+    api: BotApi,
+    target: Target,
+    status: Mutex<Option<LiveStatus>>,
+    progress: ProgressFn,
+}
+
+impl TelegramReply {
+    /// Stops the status updates; returns the placeholder's message id, if one was posted.
+    fn finish_status(&self) -> Option<i64> {
+        lock(&self.status).take().and_then(LiveStatus::finish)
+    }
+}
+
+impl Reply for TelegramReply {
+    fn progress(&self, event: &Progress) {
+        (self.progress)(event);
+    }
+
+    fn send_text(&self, text: &str) -> Result<()> {
+        let status = self.finish_status();
+        self.api.send_reply(self.target, text, status)
+    }
+
+    fn send_attachment(&self, attachment: &Attachment) -> Result<()> {
+        self.api.send_attachment(self.target.follow_up(), attachment)
+    }
+}
+
+impl Drop for TelegramReply {
+    /// A reply that never got its text must not leave the placeholder behind.
+    fn drop(&mut self) {
+        if let Some(id) = self.finish_status() {
+            self.api.delete_message(self.target.chat_id, id);
+        }
+    }
+}
+
+/// Shortest gap between edits of a status message. Telegram allows about one message
+/// a second in a private chat and 20 a minute in a group, and edits count.
+const EDIT_INTERVAL: Duration = Duration::from_millis(1200);
+const GROUP_EDIT_INTERVAL: Duration = Duration::from_secs(3);
+/// A chat action shows for about five seconds, so it is resent a little sooner.
+const TYPING_INTERVAL: Duration = Duration::from_secs(4);
+const STATUS_PLACEHOLDER: &str = "⏳ Working…";
+/// Tool lines and streamed characters kept in a status message.
+const STATUS_TOOL_LINES: usize = 5;
+const STATUS_TEXT_CHARS: usize = 3500;
+
+/// A placeholder message kept up to date while the agent works, with the typing
+/// indicator refreshed alongside. Telegram calls happen on a helper thread, so the
+/// run never waits on them.
+struct LiveStatus {
+    shared: Arc<(Mutex<LiveState>, Condvar)>,
+    handle: Option<std::thread::JoinHandle<Option<i64>>>,
+}
+
+#[derive(Default)]
+struct LiveState {
+    tools: Vec<String>,
+    text: String,
+    changed: bool,
+    done: bool,
+}
+
+impl LiveStatus {
+    fn start(api: BotApi, target: Target, group: bool) -> Self {
+        let shared = Arc::new((Mutex::new(LiveState::default()), Condvar::new()));
+        let state = shared.clone();
+        let interval = if group { GROUP_EDIT_INTERVAL } else { EDIT_INTERVAL };
+        let handle = std::thread::Builder::new()
+            .name("tg-status".to_string())
+            .stack_size(256 * 1024)
+            .spawn(move || update_status(&api, target, interval, &state))
+            .map_err(|e| eprintln!("Failed to start status updates: {}", e))
+            .ok();
+        Self { shared, handle }
+    }
+
+    /// The agent's progress callback, feeding this status.
+    fn progress_fn(&self) -> ProgressFn {
+        let shared = self.shared.clone();
+        Arc::new(move |event: &Progress| {
+            let mut state = lock(&shared.0);
+            match event {
+                Progress::Tool { name, args } => {
+                    state.tools.push(format!("🔧 {}: {}", name, summarize_args(args)));
+                    // Text before a tool call is the model thinking aloud; the answer comes after
+                    state.text.clear();
+                }
+                Progress::Text(text) => state.text.push_str(text),
+            }
+            state.changed = true;
+            shared.1.notify_one();
+        })
+    }
+
+    /// Stops the updates and returns the placeholder's message id, if one was posted.
+    fn finish(mut self) -> Option<i64> {
+        lock(&self.shared.0).done = true;
+        self.shared.1.notify_one();
+        self.handle.take().and_then(|h| h.join().ok()).flatten()
+    }
+}
+
+fn update_status(api: &BotApi, target: Target, interval: Duration, shared: &(Mutex<LiveState>, Condvar)) -> Option<i64> {
+    let typing = || {
+        let mut payload = serde_json::json!({ "action": "typing" });
+        target.follow_up().apply(&mut payload);
+        let _ = api.call("sendChatAction", &payload);
+    };
+    typing();
+    let mut last_typing = Instant::now();
+
+    let mut payload = serde_json::json!({ "text": STATUS_PLACEHOLDER });
+    target.apply(&mut payload);
+    let message_id = match api.call("sendMessage", &payload) {
+        Ok(sent) => sent["message_id"].as_i64(),
+        Err(e) => {
+            eprintln!("Failed to post status message: {}", e);
+            None
+        }
+    };
+    let mut last_edit = Instant::now();
+    let mut shown = STATUS_PLACEHOLDER.to_string();
+
+    loop {
+        let mut state = lock(&shared.0);
+        let until_typing = TYPING_INTERVAL.saturating_sub(last_typing.elapsed());
+        let wait = if state.changed { interval.saturating_sub(last_edit.elapsed()).min(until_typing) } else { until_typing };
+        if !state.done && !wait.is_zero() {
+            state = shared.1.wait_timeout(state, wait).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        if state.done {
+            return message_id;
+        }
+        let render = (state.changed && last_edit.elapsed() >= interval).then(|| {
+            state.changed = false;
+            render_status(&state)
+        });
+        drop(state);
+
+        if last_typing.elapsed() >= TYPING_INTERVAL {
+            typing();
+            last_typing = Instant::now();
+        }
+        if let (Some(text), Some(id)) = (render, message_id) {
+            if text != shown {
+                let payload = serde_json::json!({ "chat_id": target.chat_id, "message_id": id, "text": text });
+                if let Err(e) = api.call("editMessageText", &payload) {
+                    eprintln!("Failed to update status message: {}", e);
+                }
+                shown = text;
+            }
+            last_edit = Instant::now();
+        }
+    }
+}
+
+/// Plain text, since a partial answer is rarely valid Markdown.
+fn render_status(state: &LiveState) -> String {
+    let mut lines: Vec<&str> = state.tools.iter().rev().take(STATUS_TOOL_LINES).rev().map(String::as_str).collect();
+    let skip = state.text.chars().count().saturating_sub(STATUS_TEXT_CHARS);
+    let tail = match state.text.char_indices().nth(skip) {
+        Some((i, _)) if skip > 0 => format!("…{}", &state.text[i..]),
+        _ => state.text.clone(),
+    };
+    let tail = tail.trim();
+    if tail.is_empty() {
+        lines.push(STATUS_PLACEHOLDER);
+    } else {
+        lines.push(tail);
+    }
+    lines.join("\n")
+}
+
+/// A tool call's first string argument (usually the command, path or query), or its
+/// raw arguments, shortened to one line.
+fn summarize_args(args: &str) -> String {
+    let value: Option<Value> = serde_json::from_str(args).ok();
+    let first = value.as_ref()
+        .and_then(|v| v.as_object())
+        .and_then(|o| o.values().find_map(|v| v.as_str()));
+    let line = first.unwrap_or(args).split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() > 60 {
+        format!("{}...", line.chars().take(60).collect::<String>())
+    } else {
+        line
+    }
+}
+
+impl Channel for TelegramBot {
+    fn name(&self) -> &str {
+        "telegram"
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        if let Err(e) = self.identify() {
+            eprintln!("Failed to read the bot's identity ({}); in groups only commands will be answered", e);
+        }
+        if let Err(e) = self.register_commands() {
+            eprintln!("Failed to register bot commands: {}", e);
+        }
+        match self.webhook.clone() {
+            Some(cfg) => {
+                let secret = cfg.secret.clone().unwrap_or_else(random_secret);
+                let webhook = Webhook::bind(&cfg.listen, &cfg.path, &secret)?;
+                self.set_webhook(&cfg.url, &secret)?;
+                // A connection of our own wakes the listener so it sees the shutdown flag
+                let addr = webhook.local_addr()?;
+                crate::utils::on_shutdown(move || {
+                    let _ = TcpStream::connect_timeout(&addr, Duration::from_secs(1));
+                });
+                println!("Telegram webhook listening on {}{}.", cfg.listen, cfg.path);
+                self.listener = Some(webhook);
+            }
+            None => {
+                // getUpdates is refused while a webhook is registered, e.g. after a webhook run
+                if let Err(e) = self.delete_webhook() {
+                    eprintln!("Failed to delete webhook: {}", e);
+                }
+                if let Some(path) = &self.offset_file {
+                    *lock(&self.offset) = read_offset(path);
+                }
+                // The long poll is killed on shutdown rather than waited out
+                let poll = self.poll.clone();
+                crate::utils::on_shutdown(move || {
+                    if let Some(child) = lock(&poll).as_mut() {
+                        let _ = child.kill();
+                    }
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Polls, or serves one webhook request. Telegram retries deliveries that aren't
+    /// acknowledged, so a webhook update is answered before it is handled.
+    fn receive(&self) -> Result<Vec<Inbound>> {
+        let updates = match &self.listener {
+            Some(webhook) => webhook.next_update::<Value>()?.into_iter().collect(),
+            None => self.poll_updates()?,
+        };
+        Ok(updates.into_iter().filter_map(|update| self.inbound(update)).collect())
+    }
+
+    /// One session per private chat, group, or forum topic.
+    fn session_key(&self, conversation: &str) -> String {
+        conversation.to_string()
+    }
+
+    /// In private chats every message is for the bot. In groups only commands meant for
+    /// it, messages that @mention it and replies to its own messages are.
+    fn is_addressed(&self, msg: &Inbound) -> bool {
+        let msg = match TgMessage::of(msg) {
+            Some(msg) => msg,
+            None => return false,
+        };
+        if !msg.is_group() {
+            return true;
+        }
+        let text = msg.body();
+        if parse_command(text).is_some() {
+            return match command_target(text) {
+                Some(target) => target.eq_ignore_ascii_case(&self.bot_username),
+                None => true,
+            };
+        }
+        if strip_mention(text, &self.bot_username).is_some() {
+            return true;
+        }
+        let replied_to = msg.reply_to_message.as_ref().and_then(|r| r.from.as_ref());
+        self.bot_id != 0 && replied_to.is_some_and(|u| u.id == self.bot_id)
+    }
+
+    /// `allow_from` lists user ids, usernames or chat ids; a group's id admits everyone in it.
+    fn is_allowed(&self, msg: &Inbound) -> bool {
+        let msg = match TgMessage::of(msg) {
+            Some(msg) => msg,
+            None => return false,
+        };
+        let user_id = msg.from.as_ref().map(|u| u.id.to_string()).unwrap_or_default();
+        let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_default();
+        self.allowed_users.is_empty()
+            || self.allowed_users.contains(&user_id)
+            || self.allowed_users.contains(&username)
+            || self.allowed_users.contains(&msg.chat.id.to_string())
+    }
+
+    /// Downloads a message's photo, document or voice note, telling the agent about each
+    /// and showing photos and documents to a vision model.
+    fn ingest(&self, msg: &mut Inbound, inbox: &Path) -> Result<()> {
+        let tg = match TgMessage::of(msg) {
+            Some(tg) => tg,
+            None => return Ok(()),
+        };
+        let mut notes = Vec::new();
+        let mut files = Vec::new();
+        if let Some(photo) = tg.photo.as_ref().and_then(|sizes| sizes.last()) {
+            let path = self.download(photo, inbox, &format!("photo-{}.jpg", photo.file_unique_id))?;
+            files.push(Attachment::from_path(path, "image/jpeg"));
+        }
+        if let Some(doc) = &tg.document {
+            let name = doc.file_name.clone().unwrap_or_else(|| format!("document-{}", doc.file_unique_id));
+            let path = self.download(doc, inbox, &name)?;
+            files.push(Attachment::from_path(path, doc.mime_type.as_deref().unwrap_or("application/octet-stream")));
+        }
+        if let Some(voice) = &tg.voice {
+            let command = self.transcriber.as_deref()
+                .ok_or_else(|| anyhow!("voice messages need channels.telegram.transcribe to be configured"))?;
+            let path = self.download(voice, inbox, &format!("voice-{}.ogg", voice.file_unique_id))?;
+            notes.push(format!("[Voice message transcript] {}", transcribe(command, &path)?));
+        }
+        notes.extend(files.iter().map(|f| f.describe()));
+        for note in notes {
+            if !msg.text.is_empty() {
+                msg.text.push('\n');
+            }
+            msg.text.push_str(&note);
+        }
+        msg.attachments.extend(files);
+        Ok(())
+    }
+
+    fn command(&self, agent: &mut Agent, name: &str, _args: &str) -> Result<Option<String>> {
+        let reply = match name {
+            "start" => format!("Hi, I'm RedClaw. Send me a question, a photo, a document or a voice note.\n\n{}", help_text()),
+            "help" => help_text(),
+            "model" => format!("Model: {}", agent.model()),
+            "memory" => agent.memory_stats().summary(),
+            "usage" => {
+                let (messages, usage) = agent.session_usage()?;
+                format!(
+                    "This conversation: {} messages, {} tokens ({} prompt, {} completion).",
+                    messages, usage.total_tokens, usage.prompt_tokens, usage.completion_tokens
+                )
+            }
+            "tools" => {
+                let tools = agent.available_tools();
+                if tools.is_empty() {
+                    "No tools are available in this chat.".to_string()
+                } else {
+                    tools.iter()
+                        .map(|t| format!("- {}: {}", t.function.name, first_sentence(&t.function.description)))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            }
+            _ => return builtin_command(agent, name),
+        };
+        Ok(Some(reply))
+    }
+
+    fn send_text(&self, conversation: &str, text: &str) -> Result<()> {
+        self.api.send_message(Target::conversation(conversation)?, text)
+    }
+
+    /// Quotes the message in groups.
+    fn answer(&self, msg: &Inbound, text: &str) -> Result<()> {
+        self.api.send_message(Target::of(&msg.conversation, Some(msg))?, text)
+    }
+
+    fn send_attachment(&self, conversation: &str, attachment: &Attachment) -> Result<()> {
+        self.api.send_attachment(Target::conversation(conversation)?, attachment)
+    }
+
+    /// Posts a status message that follows the run's tool calls and streamed text.
+    fn start_reply(&self, conversation: &str, msg: Option<&Inbound>) -> Option<Box<dyn Reply>> {
+        let target = Target::of(conversation, msg).map_err(|e| eprintln!("{}", e)).ok()?;
+        let status = LiveStatus::start(self.api.clone(), target, target.chat_id < 0);
+        Some(Box::new(TelegramReply {
+            api: self.api.clone(),
+            target,
+            progress: status.progress_fn(),
+            status: Mutex::new(Some(status)),
+        }))
+    }
+
+    fn typing(&self, conversation: &str) -> Result<()> {
+        let mut payload = serde_json::json!({ "action": "typing" });
+        Target::conversation(conversation)?.apply(&mut payload);
+        self.api.call("sendChatAction", &payload)?;
+        Ok(())
+    }
+
+    fn tool_profile(&self) -> Option<&str> {
+        self.tool_profile.as_deref()
+    }
+
+    fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    fn scheduler(&self) -> Option<&Scheduler> {
+        self.scheduler.as_ref()
+    }
+
+    fn run(self: Arc<Self>, agent: Agent) -> Result<()> {
+        super::serve(self, agent)
+    }
+}
+
+fn parse_chat_id(conversation: &str) -> Result<i64> {
+    conversation.parse().map_err(|_| anyhow!("'{}' is not a Telegram chat id", conversation))
+}
+
+/// Accepts updates POSTed by Telegram on one path. Requests without the expected
+/// `X-Telegram-Bot-Api-Secret-Token` are refused, since the URL itself is public.
+pub struct Webhook {
+    listener: TcpListener,
+    path: String,
+    secret: String,
+}
+
+impl Webhook {
+    pub fn bind(addr: &str, path: &str, secret: &str) -> Result<Self> {
+        let listener = TcpListener::bind(addr).map_err(|e| anyhow!("Failed to listen on {}: {}", addr, e))?;
+        Ok(Self { listener, path: path.to_string(), secret: secret.to_string() })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serves the next connection. Returns the update if the request carried one; any
+    /// other request is answered with an error status and yields `None`.
+    pub fn next_update<T: DeserializeOwned>(&self) -> Result<Option<T>> {
+        let (mut stream, _) = self.listener.accept()?;
+        // A stalled client must not hold up the listener for long
+        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+
+        let request = match http::read_request(&mut BufReader::new(&stream)) {
+            Ok(r) => r,
+            Err(_) => {
+                let _ = http::write_response(&mut stream, 400, "text/plain", b"bad request");
+                return Ok(None);
+            }
+        };
+        let (status, update) = if request.path != self.path {
+            (404, None)
+        } else if request.method != "POST" {
+            (405, None)
+        } else if !http::secret_matches(request.header("X-Telegram-Bot-Api-Secret-Token").unwrap_or(""), &self.secret) {
+            (401, None)
+        } else {
+            match serde_json::from_slice::<T>(&request.body) {
+                Ok(update) => (200, Some(update)),
+                Err(_) => (400, None),
+            }
+        };
+        let _ = http::write_response(&mut stream, status, "text/plain", http::reason(status).as_bytes());
+        Ok(update)
+    }
+}
+
+/// The saved polling offset, or 0 (everything Telegram still holds) if there is none.
+pub fn read_offset(path: &Path) -> i64 {
+    std::fs::read_to_string(path).ok()
+        .and_then(|s| serde_json::from_str::<Value>(&s).ok())
+        .and_then(|v| v["offset"].as_i64())
+        .unwrap_or(0)
+}
+
+pub fn write_offset(path: &Path, offset: i64) -> std::io::Result<()> {
+    crate::utils::write_atomic(path, serde_json::json!({ "offset": offset }).to_string().as_bytes())
+}
+
+/// Session key of a private chat or group, or of one forum topic in a group. Topic keys
+/// use `-topic-` because session keys can't contain `:`.
+pub fn session_key(chat_id: i64, topic: Option<i64>) -> String {
+    match topic {
+        Some(topic) => format!("{}-topic-{}", chat_id, topic),
+        None => chat_id.to_string(),
+    }
+}
+
+/// `text` without its `@username` mention, or `None` if it doesn't mention `username`.
+pub fn strip_mention(text: &str, username: &str) -> Option<String> {
+    if username.is_empty() {
+        return None;
+    }
+    let needle = format!("@{}", username.to_ascii_lowercase());
+    let lower = text.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find(&needle).map(|p| p + from) {
+        let end = pos + needle.len();
+        // `@redclaw_bot` must not match inside `@redclaw_botnet`
+        if !lower[end..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+            let stripped = format!("{} {}", text[..pos].trim_end(), text[end..].trim_start());
+            return Some(stripped.trim().to_string());
+        }
+        from = end;
+    }
+    None
+}
+
+/// The bot a command is addressed to, as in `/help@redclaw_bot`.
+fn command_target(text: &str) -> Option<&str> {
+    let word = text.trim_start().strip_prefix('/')?.split_whitespace().next()?;
+    word.split_once('@').map(|(_, bot)| bot)
+}
+
+fn help_text() -> String {
+    let mut text = "Commands:".to_string();
+    for (command, description) in COMMANDS {
+        text.push_str(&format!("\n/{} - {}", command, description));
+    }
+    text.push_str("\nAnything else goes to the assistant.");
+    text
+}
+
+fn first_sentence(text: &str) -> &str {
+    let line = text.lines().next().unwrap_or_default();
+    match line.find(". ") {
+        Some(i) => &line[..=i],
+        None => line,
+    }
+}
+
+fn lock<T>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Runs `command` through `sh -c` with `{file}` replaced by the quoted `path` and
+/// returns its trimmed stdout as the transcript.
+pub fn transcribe(command: &str, path: &Path) -> Result<String> {
+    let quoted = format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"));
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command.replace("{file}", &quoted))
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!("transcription failed ({}): {}", output.status, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let transcript = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if transcript.is_empty() {
+        return Err(anyhow!("transcription produced no text"));
+    }
+    Ok(transcript)
+}
+
+/// Keeps user-supplied file names to a safe subset and a sane length.
+fn sanitize_file_name(name: &str) -> String {
+    let clean: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .take(80)
+        .collect();
+    clean.trim_start_matches('.').to_string()
+}
+
+/// 32 hex characters from the system RNG, for webhooks configured without a secret.
+fn random_secret() -> String {
+    let mut bytes = [0u8; 16];
+    if std::fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes)).is_err() {
+        let seed = time::OffsetDateTime::now_utc().unix_timestamp_nanos() ^ std::process::id() as i128;
+        bytes = seed.to_le_bytes();
+    }
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,171 @@
+#![allow(dead_code)]
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use crate::agent::Agent;
+use crate::agent::llm::Message;
+use crate::agent::memory::{apply_entry, MemoryEntry, MemoryStore, Remembered};
+
+/// Largest slice of a raw day note sent for distillation; longer days keep their tail.
+const MAX_DAY_BYTES: usize = 32 * 1024;
+
+const CONSOLIDATE_PROMPT: &str = "You are consolidating an assistant's memory. Below are the current long-term memory and the raw conversation log of one finished day.\n\
+1. Write a digest of the day in at most 5 short bullet points.\n\
+2. Then list durable facts about the user or their ongoing work that are worth keeping and not already in long-term memory, one per line exactly as:\n\
+FACT: <section> | <key> | <value>\n\
+Write no FACT lines if there is nothing lasting.";
+
+#[derive(Debug, Clone, Default)]
+pub struct ConsolidateOptions {
+    /// Only report what would change.
+    pub dry_run: bool,
+    /// Gzip each consolidated day's raw note.
+    pub archive: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct DayDigest {
+    /// `YYYY-MM-DD`.
+    pub date: String,
+    pub digest: String,
+    pub facts: Vec<MemoryEntry>,
+}
+
+/// What a consolidation run did, or would do in dry-run mode, for one memory scope.
+#[derive(Debug, Clone, Default)]
+pub struct Consolidation {
+    /// `None` for the global store.
+    pub scope: Option<String>,
+    pub days: Vec<DayDigest>,
+    pub memory_before: String,
+    pub memory_after: String,
+}
+
+impl Consolidation {
+    /// Lines removed from and added to MEMORY.md, as `-`/`+` lines.
+    pub fn memory_diff(&self) -> String {
+        let before: HashSet<&str> = self.memory_before.lines().collect();
+        let after: HashSet<&str> = self.memory_after.lines().collect();
+        let mut out = Vec::new();
+        for line in self.memory_before.lines().filter(|l| !after.contains(l) && !l.trim().is_empty()) {
+            out.push(format!("- {}", line));
+        }
+        for line in self.memory_after.lines().filter(|l| !before.contains(l) && !l.trim().is_empty()) {
+            out.push(format!("+ {}", line));
+        }
+        out.join("\n")
+    }
+}
+
+/// Splits a distillation reply into the digest text and its `FACT:` lines.
+pub fn parse_digest(reply: &str) -> (String, Vec<MemoryEntry>) {
+    let mut digest = Vec::new();
+    let mut facts = Vec::new();
+    for line in reply.lines() {
+        let trimmed = line.trim().trim_start_matches(['-', '*']).trim();
+        if let Some(rest) = trimmed.strip_prefix("FACT:") {
+            let parts: Vec<&str> = rest.split('|').map(|p| p.trim()).collect();
+            if let [section, key, value] = parts.as_slice() {
+                if !key.is_empty() && !value.is_empty() {
+                    facts.push(MemoryEntry {
+                        section: section.to_string(),
+                        key: key.to_string(),
+                        value: value.to_string(),
+                    });
+                }
+            }
+            continue;
+        }
+        digest.push(line);
+    }
+    (digest.join("\n").trim().to_string(), facts)
+}
+
+impl Agent {
+    /// Distills every finished day that has no digest yet into `YYYYMMDD.digest.md`,
+    /// adds the proposed facts to MEMORY.md and optionally archives the raw note.
+    /// Each memory scope is consolidated on its own, so facts never cross scopes.
+    pub fn consolidate_memory(&self, opts: &ConsolidateOptions) -> Result<Vec<Consolidation>> {
+        let mut stores = vec![self.memory.for_scope(None)];
+        stores.extend(self.memory.scopes().iter().map(|s| self.memory.scoped(s)));
+        let mut results = Vec::new();
+        for store in stores {
+            let result = self.consolidate_store(&store, opts)?;
+            if !result.days.is_empty() {
+                results.push(result);
+            }
+        }
+        Ok(results)
+    }
+
+    fn consolidate_store(&self, memory: &MemoryStore, opts: &ConsolidateOptions) -> Result<Consolidation> {
+        let today = memory.today_number();
+        let shared = memory.read_shared();
+        let memory_before = memory.read_long_term();
+        let mut long_term = memory_before.clone();
+        let mut days = Vec::new();
+
+        for (day, path) in memory.daily_files() {
+            if day >= today || memory.read_digest(day).is_some() {
+                continue;
+            }
+            let raw = fs::read_to_string(&path)?;
+            if raw.trim().is_empty() {
+                continue;
+            }
+            let known = format!("{}\n{}", shared, long_term);
+            let (digest, facts) = self.distill_day(day, &raw, &known)?;
+            // Without a digest the day would drop out of context and search; retry next run
+            if digest.trim().is_empty() {
+                eprintln!("The model returned no digest for {}; leaving the day as it is", format_day(day));
+                continue;
+            }
+
+            for fact in &facts {
+                let (content, outcome) = apply_entry(&long_term, Some(&fact.section), &fact.key, &fact.value);
+                if outcome != Remembered::Unchanged {
+                    long_term = content;
+                }
+            }
+            if !opts.dry_run {
+                memory.write_digest(day, &digest)?;
+                for fact in &facts {
+                    memory.remember(Some(&fact.section), &fact.key, &fact.value)?;
+                }
+                if opts.archive {
+                    memory.archive_day(day)?;
+                }
+            }
+            days.push(DayDigest { date: format_day(day), digest, facts });
+        }
+
+        Ok(Consolidation { scope: memory.scope().map(|s| s.to_string()), days, memory_before, memory_after: long_term })
+    }
+
+    fn distill_day(&self, day: u32, raw: &str, long_term: &str) -> Result<(String, Vec<MemoryEntry>)> {
+        let mut start = raw.len().saturating_sub(MAX_DAY_BYTES);
+        while !raw.is_char_boundary(start) {
+            start += 1;
+        }
+        let prompt = format!(
+            "{}\n\nLONG-TERM MEMORY:\n{}\n\nLOG OF {}:\n{}",
+            CONSOLIDATE_PROMPT,
+            if long_term.trim().is_empty() { "(empty)" } else { long_term.trim() },
+            format_day(day),
+            &raw[start..]
+        );
+        let messages = vec![Message {
+            role: "user".to_string(),
+            content: Some(prompt.into()),
+            name: None,
+            tool_call_id: None,
+            tool_calls: None,
+        }];
+        let response = self.client.chat(&messages, None)?;
+        Ok(parse_digest(&response.text()))
+    }
+}
+
+fn format_day(day: u32) -> String {
+    format!("{:04}-{:02}-{:02}", day / 10000, day / 100 % 100, day % 100)
+}
@@ -0,0 +1,134 @@
+#![allow(dead_code)]
+use anyhow::{Result, anyhow};
+use std::io::{BufRead, Read, Write};
+
+/// Largest header block accepted, in bytes.
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+/// Largest request body accepted, in bytes.
+pub const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// A parsed HTTP/1.1 request. Only `Content-Length` bodies are supported; every
+/// response closes the connection, so there is no keep-alive bookkeeping.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query string.
+    pub path: String,
+    pub query: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// First header named `name`, case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Reads one request. Errors mean the client sent something unusable; the caller
+/// answers with 400 (or just drops the connection).
+pub fn read_request<R: BufRead>(reader: &mut R) -> Result<Request> {
+    let mut header_bytes = 0;
+    let mut line = String::new();
+    let mut next_line = |reader: &mut R, line: &mut String| -> Result<()> {
+        line.clear();
+        let n = reader.by_ref().take((MAX_HEADER_BYTES + 1 - header_bytes) as u64).read_line(line)?;
+        header_bytes += n;
+        if n == 0 {
+            return Err(anyhow!("Connection closed before the request was complete"));
+        }
+        if header_bytes > MAX_HEADER_BYTES {
+            return Err(anyhow!("Request headers too large"));
+        }
+        Ok(())
+    };
+
+    next_line(reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(m), Some(t), Some(v)) if v.starts_with("HTTP/1.") => (m.to_string(), t.to_string()),
+        _ => return Err(anyhow!("Malformed request line")),
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((p, q)) => (p.to_string(), q.to_string()),
+        None => (target, String::new()),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        next_line(reader, &mut line)?;
+        let trimmed = line.trim_end_matches(['\r', '\n']);
+        if trimmed.is_empty() {
+            break;
+        }
+        let (name, value) = trimmed.split_once(':').ok_or_else(|| anyhow!("Malformed header"))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let mut request = Request { method, path, query, headers, body: Vec::new() };
+    if request.header("Transfer-Encoding").is_some() {
+        return Err(anyhow!("Chunked request bodies are not supported"));
+    }
+    let length = match request.header("Content-Length") {
+        Some(v) => v.parse::<usize>().map_err(|_| anyhow!("Invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_BYTES {
+        return Err(anyhow!("Request body too large"));
+    }
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body)?;
+    Ok(request)
+}
+
+/// Writes a complete response and asks the client to close the connection.
+pub fn write_response<W: Write>(writer: &mut W, status: u16, content_type: &str, body: &[u8]) -> std::io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason(status),
+        content_type,
+        body.len()
+    )?;
+    writer.write_all(body)?;
+    writer.flush()
+}
+
+/// Starts a response whose body runs until the connection closes, e.g. an event stream.
+pub fn write_head<W: Write>(writer: &mut W, status: u16, content_type: &str) -> std::io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        reason(status),
+        content_type
+    )?;
+    writer.flush()
+}
+
+/// Compares without exiting early, so response timing doesn't reveal the secret.
+pub fn secret_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given.bytes().zip(expected.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+pub fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,310 @@
+#![allow(dead_code)]
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Journal size that triggers a merge into the postings file.
+const COMPACT_THRESHOLD: u64 = 64 * 1024;
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "are", "but", "not", "you", "all", "any", "can", "had", "her", "was", "one",
+    "our", "out", "has", "his", "how", "its", "may", "who", "did", "yes", "this", "that", "with",
+    "have", "from", "they", "will", "would", "there", "their", "what", "about", "which", "when",
+    "user", "assistant", "is", "it", "to", "of", "in", "on", "at", "an", "or", "be", "as", "by",
+    "de", "da", "do", "que", "em", "um", "uma", "os", "as", "se", "no", "na",
+];
+
+/// Lowercased alphanumeric words of two or more characters, minus stopwords.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 2)
+        .map(|w| w.to_lowercase())
+        .filter(|w| !STOPWORDS.contains(&w.as_str()))
+        .collect()
+}
+
+fn term_counts(text: &str) -> (u32, BTreeMap<String, u32>) {
+    let mut counts = BTreeMap::new();
+    let tokens = tokenize(text);
+    for t in &tokens {
+        *counts.entry(t.clone()).or_insert(0) += 1;
+    }
+    (tokens.len() as u32, counts)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    /// Day as `YYYYMMDD`.
+    pub date: u32,
+    pub score: f64,
+}
+
+/// Inverted keyword index over daily notes, one document per day.
+///
+/// `postings.txt` starts with `#docs date:len,...` followed by `term\tdate:tf,...` lines.
+/// `journal.txt` holds recent appends as `date\tlen\tterm:tf,...` and is merged into the
+/// postings once it grows past `COMPACT_THRESHOLD`. Both are streamed, never fully loaded.
+/// `built` marks a completed `rebuild`; until then the index may be missing older notes.
+pub struct KeywordIndex {
+    postings: PathBuf,
+    journal: PathBuf,
+    built: PathBuf,
+}
+
+impl KeywordIndex {
+    pub fn new(memory_dir: &Path) -> Self {
+        let dir = memory_dir.join("index");
+        Self {
+            postings: dir.join("postings.txt"),
+            journal: dir.join("journal.txt"),
+            built: dir.join("built"),
+        }
+    }
+
+    /// Whether every note has been indexed. Appends alone don't count: a workspace
+    /// that predates the index gets a journal with its first note, not its history.
+    pub fn is_built(&self) -> bool {
+        self.built.exists()
+    }
+
+    /// Indexes `text` as an addition to the document for `date`.
+    pub fn add(&self, date: u32, text: &str) -> Result<()> {
+        let (len, counts) = term_counts(text);
+        if counts.is_empty() {
+            return Ok(());
+        }
+        if let Some(parent) = self.journal.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let terms: Vec<String> = counts.iter().map(|(t, c)| format!("{}:{}", t, c)).collect();
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.journal)?;
+        writeln!(file, "{}\t{}\t{}", date, len, terms.join(","))?;
+        drop(file);
+
+        if fs::metadata(&self.journal).map(|m| m.len()).unwrap_or(0) > COMPACT_THRESHOLD {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Drops the index and re-indexes every `(date, text)` document from scratch.
+    pub fn rebuild<I: IntoIterator<Item = (u32, String)>>(&self, docs: I) -> Result<()> {
+        fs::remove_file(&self.built).ok();
+        fs::remove_file(&self.postings).ok();
+        fs::remove_file(&self.journal).ok();
+        for (date, text) in docs {
+            self.add(date, &text)?;
+        }
+        self.compact()?;
+        if let Some(parent) = self.built.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        File::create(&self.built)?;
+        Ok(())
+    }
+
+    /// Removes a day from the index, e.g. after its note is archived and re-added as a digest.
+    pub fn remove(&self, date: u32) -> Result<()> {
+        self.compact()?;
+        self.rewrite(|d| d != date, &BTreeMap::new(), &BTreeMap::new())
+    }
+
+    /// Merges the journal into the postings file.
+    pub fn compact(&self) -> Result<()> {
+        let mut lens: BTreeMap<u32, u32> = BTreeMap::new();
+        let mut postings: BTreeMap<String, BTreeMap<u32, u32>> = BTreeMap::new();
+        if let Ok(file) = File::open(&self.journal) {
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                let mut cols = line.splitn(3, '\t');
+                let (date, len, terms) = match (cols.next(), cols.next(), cols.next()) {
+                    (Some(d), Some(l), Some(t)) => (d, l, t),
+                    _ => continue,
+                };
+                let date: u32 = match date.parse() {
+                    Ok(d) => d,
+                    Err(_) => continue,
+                };
+                *lens.entry(date).or_insert(0) += len.parse::<u32>().unwrap_or(0);
+                for (term, tf) in parse_postings(terms) {
+                    *postings.entry(term.to_string()).or_default().entry(date).or_insert(0) += tf;
+                }
+            }
+        } else {
+            return Ok(());
+        }
+        self.rewrite(|_| true, &lens, &postings)?;
+        fs::remove_file(&self.journal).ok();
+        Ok(())
+    }
+
+    /// Streams the postings file into a new one, keeping documents that satisfy `keep`
+    /// and merging in `new_lens` / `new_postings`.
+    fn rewrite<F: Fn(u32) -> bool>(
+        &self,
+        keep: F,
+        new_lens: &BTreeMap<u32, u32>,
+        new_postings: &BTreeMap<String, BTreeMap<u32, u32>>,
+    ) -> Result<()> {
+        if let Some(parent) = self.postings.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = self.postings.with_extension("txt.tmp");
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        let mut merged_terms: HashSet<&str> = HashSet::new();
+        let mut wrote_docs = false;
+
+        let write_docs = |out: &mut BufWriter<File>, existing: &str| -> Result<()> {
+            let mut lens: BTreeMap<u32, u32> = parse_postings(existing)
+                .filter_map(|(d, l)| d.parse().ok().map(|d: u32| (d, l)))
+                .filter(|(d, _)| keep(*d))
+                .collect();
+            for (d, l) in new_lens {
+                *lens.entry(*d).or_insert(0) += l;
+            }
+            let docs: Vec<String> = lens.iter().map(|(d, l)| format!("{}:{}", d, l)).collect();
+            writeln!(out, "#docs {}", docs.join(","))?;
+            Ok(())
+        };
+
+        if let Ok(file) = File::open(&self.postings) {
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                if let Some(docs) = line.strip_prefix("#docs ") {
+                    write_docs(&mut out, docs)?;
+                    wrote_docs = true;
+                    continue;
+                }
+                let (term, list) = match line.split_once('\t') {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let mut docs: BTreeMap<u32, u32> = parse_postings(list)
+                    .filter_map(|(d, tf)| d.parse().ok().map(|d: u32| (d, tf)))
+                    .filter(|(d, _)| keep(*d))
+                    .collect();
+                if let Some((t, extra)) = new_postings.get_key_value(term) {
+                    merged_terms.insert(t.as_str());
+                    for (d, tf) in extra {
+                        *docs.entry(*d).or_insert(0) += tf;
+                    }
+                }
+                if !docs.is_empty() {
+                    writeln!(out, "{}\t{}", term, format_postings(&docs))?;
+                }
+            }
+        }
+        if !wrote_docs {
+            write_docs(&mut out, "")?;
+        }
+        for (term, docs) in new_postings {
+            if !merged_terms.contains(&term.as_str()) {
+                writeln!(out, "{}\t{}", term, format_postings(docs))?;
+            }
+        }
+
+        let file = out.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        fs::rename(tmp, &self.postings)?;
+        Ok(())
+    }
+
+    /// Top `k` days for `query`, scored with BM25. Reads the postings and journal
+    /// separately, so callers keep writers out while it runs.
+    pub fn search(&self, query: &str, k: usize) -> Result<Vec<Hit>> {
+        let terms: Vec<String> = {
+            let mut t = tokenize(query);
+            t.sort();
+            t.dedup();
+            t
+        };
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut lens: HashMap<u32, u32> = HashMap::new();
+        let mut tfs: HashMap<&str, HashMap<u32, u32>> = HashMap::new();
+
+        if let Ok(file) = File::open(&self.postings) {
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                if let Some(docs) = line.strip_prefix("#docs ") {
+                    for (d, l) in parse_postings(docs) {
+                        if let Ok(d) = d.parse() {
+                            *lens.entry(d).or_insert(0) += l;
+                        }
+                    }
+                    continue;
+                }
+                if let Some((term, list)) = line.split_once('\t') {
+                    if let Some(t) = terms.iter().find(|t| t.as_str() == term) {
+                        let entry = tfs.entry(t.as_str()).or_default();
+                        for (d, tf) in parse_postings(list) {
+                            if let Ok(d) = d.parse() {
+                                *entry.entry(d).or_insert(0) += tf;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Ok(file) = File::open(&self.journal) {
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                let mut cols = line.splitn(3, '\t');
+                if let (Some(d), Some(l), Some(list)) = (cols.next(), cols.next(), cols.next()) {
+                    let d: u32 = match d.parse() {
+                        Ok(d) => d,
+                        Err(_) => continue,
+                    };
+                    *lens.entry(d).or_insert(0) += l.parse::<u32>().unwrap_or(0);
+                    for (term, tf) in parse_postings(list) {
+                        if let Some(t) = terms.iter().find(|t| t.as_str() == term) {
+                            *tfs.entry(t.as_str()).or_default().entry(d).or_insert(0) += tf;
+                        }
+                    }
+                }
+            }
+        }
+
+        let n = lens.len() as f64;
+        if n == 0.0 {
+            return Ok(Vec::new());
+        }
+        let avgdl = lens.values().map(|l| *l as f64).sum::<f64>() / n;
+
+        let mut scores: HashMap<u32, f64> = HashMap::new();
+        for docs in tfs.values() {
+            let df = docs.len() as f64;
+            let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+            for (d, tf) in docs {
+                let tf = *tf as f64;
+                let dl = *lens.get(d).unwrap_or(&0) as f64;
+                let norm = tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * dl / avgdl.max(1.0)));
+                *scores.entry(*d).or_insert(0.0) += idf * norm;
+            }
+        }
+
+        let mut hits: Vec<Hit> = scores.into_iter().map(|(date, score)| Hit { date, score }).collect();
+        // Ties go to the most recent day
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(b.date.cmp(&a.date)));
+        hits.truncate(k);
+        Ok(hits)
+    }
+}
+
+fn parse_postings(list: &str) -> impl Iterator<Item = (&str, u32)> {
+    list.split(',').filter_map(|p| {
+        let (key, n) = p.rsplit_once(':')?;
+        Some((key, n.parse().ok()?))
+    })
+}
+
+fn format_postings(docs: &BTreeMap<u32, u32>) -> String {
+    docs.iter().map(|(d, tf)| format!("{}:{}", d, tf)).collect::<Vec<_>>().join(",")
+}
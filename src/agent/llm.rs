@@ -8,7 +8,7 @@ use std::process::Command;
 pub struct Message {
     pub role: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub content: Option<String>,
+    pub content: Option<MessageContent>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -17,6 +17,57 @@ pub struct Message {
     pub tool_calls: Option<Value>,
 }
 
+impl Message {
+    /// Plain text of the message, with any image parts dropped.
+    pub fn text(&self) -> String {
+        self.content.as_ref().map(|c| c.text()).unwrap_or_default()
+    }
+}
+
+/// Message content on the wire: a plain string, or an array of parts for multimodal input.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+impl MessageContent {
+    pub fn text(&self) -> String {
+        match self {
+            MessageContent::Text(s) => s.clone(),
+            MessageContent::Parts(parts) => parts.iter().filter_map(|p| match p {
+                ContentPart::Text { text } => Some(text.as_str()),
+                ContentPart::ImageUrl { .. } => None,
+            }).collect::<Vec<_>>().join("\n"),
+        }
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(s: String) -> Self {
+        MessageContent::Text(s)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(s: &str) -> Self {
+        MessageContent::Text(s.to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageUrl {
+    pub url: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ToolDefinition {
     pub r#type: String,
@@ -33,6 +84,30 @@ pub struct FunctionDefinition {
 #[derive(Debug, Deserialize)]
 struct ChatResponse {
     choices: Vec<Choice>,
+    #[serde(default)]
+    usage: Option<Usage>,
+    #[serde(default)]
+    model: Option<String>,
+}
+
+/// Token counts reported by the provider.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+    #[serde(default)]
+    pub total_tokens: u64,
+}
+
+/// A reply plus what it cost: the model that actually answered, tokens and latency.
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub message: Message,
+    pub model: String,
+    pub usage: Option<Usage>,
+    pub duration_ms: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,6 +115,7 @@ struct Choice {
     message: Message,
 }
 
+#[derive(Clone)]
 pub struct LLMClient {
     pub api_key: String,
     pub api_base: String,
@@ -65,11 +141,79 @@ impl LLMClient {
     }
 
     pub fn chat(&self, messages: &[Message], tools: Option<&[ToolDefinition]>) -> Result<Message> {
+        Ok(self.complete(messages, tools)?.message)
+    }
+
+    pub fn complete(&self, messages: &[Message], tools: Option<&[ToolDefinition]>) -> Result<Completion> {
+        let started = std::time::Instant::now();
+        let body = self.request_body(messages, tools);
+        let child = self.send_request(&body, false)?;
+        let output = child.wait_with_output()?;
+        
+        if !output.status.success() {
+            let err = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow!("CURL Network Error: {}", err));
+        }
+
+        let stdout_str = String::from_utf8_lossy(&output.stdout);
+        
+        if stdout_str.trim().is_empty() {
+            return Err(anyhow!("LLM Provider returned an empty response. Check your API key and URL base: {}", self.api_base));
+        }
+
+        // Check for API errors before parsing as success
+        let val: Value = serde_json::from_str(&stdout_str)
+            .map_err(|e| anyhow!("Failed to parse JSON response: {}. Body: {}", e, stdout_str))?;
+        
+        if let Some(e) = api_error(&val) {
+            return Err(e);
+        }
+
+        // Handle potential non-JSON or error JSON responses
+        let chat_resp: ChatResponse = serde_json::from_value(val)
+            .map_err(|e| anyhow!("Failed to map LLM response to ChatResponse: {}. Body: {}", e, stdout_str))?;
+        
+        let message = chat_resp.choices.into_iter().next()
+            .map(|c| c.message)
+            .ok_or_else(|| anyhow!("No choices in LLM response: {}", stdout_str))?;
+        Ok(Completion {
+            message,
+            model: chat_resp.model.unwrap_or_else(|| self.model.clone()),
+            usage: chat_resp.usage,
+            duration_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Like `complete`, but asks for a server-sent event stream and calls `on_text`
+    /// with each piece of reply text as it arrives.
+    pub fn complete_streaming(&self, messages: &[Message], tools: Option<&[ToolDefinition]>, on_text: &mut dyn FnMut(&str)) -> Result<Completion> {
+        let started = std::time::Instant::now();
+        let mut body = self.request_body(messages, tools);
+        body["stream"] = true.into();
+        body["stream_options"] = serde_json::json!({ "include_usage": true });
+        let mut child = self.send_request(&body, true)?;
+
+        let stdout = child.stdout.take().ok_or_else(|| anyhow!("Failed to open stdout"))?;
+        let result = read_stream(std::io::BufReader::new(stdout), on_text);
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(anyhow!("CURL Network Error: {}", String::from_utf8_lossy(&output.stderr)));
+        }
+        let (message, model, usage) = result?;
+        Ok(Completion {
+            message,
+            model: model.unwrap_or_else(|| self.model.clone()),
+            usage,
+            duration_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+
+    fn request_body(&self, messages: &[Message], tools: Option<&[ToolDefinition]>) -> Value {
         // Gemini/OpenRouter compatibility: ensure no null content
         let sanitized_messages: Vec<Message> = messages.iter().map(|m| {
             let mut new_m = m.clone();
             if new_m.content.is_none() && new_m.tool_calls.is_none() {
-                new_m.content = Some("".to_string());
+                new_m.content = Some("".into());
             }
             new_m
         }).collect();
@@ -86,59 +230,125 @@ impl LLMClient {
                 }
             }
         }
+        body
+    }
 
+    fn send_request(&self, body: &Value, stream: bool) -> Result<std::process::Child> {
         // Use CURL with STDIN for safety and large payloads
         use std::process::Stdio;
-        let mut child = Command::new("curl")
+        let mut command = Command::new("curl");
+        command
             .arg("-s")
             .arg("-L") // Follow redirects
             .arg("-X").arg("POST")
             .arg("--connect-timeout").arg("15")
-            .arg("--max-time").arg("120")
-            .arg(&format!("{}/chat/completions", self.api_base))
-            .arg("-H").arg(&format!("Authorization: Bearer {}", self.api_key))
+            .arg("--max-time").arg(if stream { "300" } else { "120" })
+            .arg(format!("{}/chat/completions", self.api_base))
+            .arg("-H").arg(format!("Authorization: Bearer {}", self.api_key))
             .arg("-H").arg("Content-Type: application/json")
             .arg("-H").arg("HTTP-Referer: https://github.com/redclaw") // Required by OpenRouter
             .arg("-d").arg("@-")
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
+            .stderr(Stdio::piped());
+        if stream {
+            // Pass events on as they arrive instead of buffering
+            command.arg("-N");
+        }
+        let mut child = command.spawn()?;
 
         {
             let stdin = child.stdin.as_mut().ok_or_else(|| anyhow!("Failed to open stdin"))?;
-            serde_json::to_writer(stdin, &body)?;
+            serde_json::to_writer(stdin, body)?;
         }
+        // Close stdin so curl sends the request
+        drop(child.stdin.take());
+        Ok(child)
+    }
+}
 
-        let output = child.wait_with_output()?;
-        
-        if !output.status.success() {
-            let err = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow!("CURL Network Error: {}", err));
-        }
+fn api_error(val: &Value) -> Option<anyhow::Error> {
+    let error = val.get("error")?;
+    let msg = error.get("message").and_then(|m| m.as_str()).unwrap_or("Unknown API Error");
+    let code = error.get("code").map(|c| c.to_string()).unwrap_or_else(|| "no code".to_string());
+    Some(anyhow!("LLM Provider Error ({}): {}", code, msg))
+}
 
-        let stdout_str = String::from_utf8_lossy(&output.stdout);
-        
-        if stdout_str.trim().is_empty() {
-            return Err(anyhow!("LLM Provider returned an empty response. Check your API key and URL base: {}", self.api_base));
-        }
+/// Assembles a chat completion from an OpenAI-style SSE stream: text deltas are
+/// concatenated (and passed to `on_text`), tool call fragments are joined by index.
+/// Returns the message, the model that answered and the usage, when reported.
+pub fn read_stream<R: std::io::BufRead>(reader: R, on_text: &mut dyn FnMut(&str)) -> Result<(Message, Option<String>, Option<Usage>)> {
+    let mut text = String::new();
+    let mut calls: Vec<Value> = Vec::new();
+    let mut model = None;
+    let mut usage = None;
+    let mut other = String::new();
+    let mut saw_data = false;
 
-        // Check for API errors before parsing as success
-        let val: Value = serde_json::from_str(&stdout_str)
-            .map_err(|e| anyhow!("Failed to parse JSON response: {}. Body: {}", e, stdout_str))?;
-        
-        if let Some(error) = val.get("error") {
-            let msg = error.get("message").and_then(|m| m.as_str()).unwrap_or("Unknown API Error");
-            let code = error.get("code").map(|c| c.to_string()).unwrap_or_else(|| "no code".to_string());
-            return Err(anyhow!("LLM Provider Error ({}): {}", code, msg));
+    for line in reader.lines() {
+        let line = line?;
+        let data = match line.strip_prefix("data:") {
+            Some(d) => d.trim(),
+            None => {
+                // Providers answer errors with a plain JSON body instead of a stream
+                if !saw_data {
+                    other.push_str(&line);
+                }
+                continue;
+            }
+        };
+        saw_data = true;
+        if data == "[DONE]" {
+            break;
+        }
+        let chunk: Value = match serde_json::from_str(data) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        if let Some(e) = api_error(&chunk) {
+            return Err(e);
+        }
+        if let Some(m) = chunk["model"].as_str() {
+            model = Some(m.to_string());
+        }
+        if let Ok(u) = serde_json::from_value::<Usage>(chunk["usage"].clone()) {
+            usage = Some(u);
+        }
+        let delta = &chunk["choices"][0]["delta"];
+        if let Some(piece) = delta["content"].as_str().filter(|p| !p.is_empty()) {
+            text.push_str(piece);
+            on_text(piece);
         }
+        for fragment in delta["tool_calls"].as_array().into_iter().flatten() {
+            let index = fragment["index"].as_u64().unwrap_or(calls.len() as u64) as usize;
+            while calls.len() <= index {
+                calls.push(serde_json::json!({ "id": "", "type": "function", "function": { "name": "", "arguments": "" } }));
+            }
+            let call = &mut calls[index];
+            if let Some(id) = fragment["id"].as_str() {
+                call["id"] = id.into();
+            }
+            for field in ["name", "arguments"] {
+                if let Some(part) = fragment["function"][field].as_str() {
+                    let joined = format!("{}{}", call["function"][field].as_str().unwrap_or_default(), part);
+                    call["function"][field] = joined.into();
+                }
+            }
+        }
+    }
 
-        // Handle potential non-JSON or error JSON responses
-        let chat_resp: ChatResponse = serde_json::from_value(val)
-            .map_err(|e| anyhow!("Failed to map LLM response to ChatResponse: {}. Body: {}", e, stdout_str))?;
-        
-        chat_resp.choices.into_iter().next()
-            .map(|c| c.message)
-            .ok_or_else(|| anyhow!("No choices in LLM response: {}", stdout_str))
+    if !saw_data {
+        if let Some(e) = serde_json::from_str::<Value>(&other).ok().as_ref().and_then(api_error) {
+            return Err(e);
+        }
+        return Err(anyhow!("LLM Provider returned no event stream: {}", other));
     }
+    let message = Message {
+        role: "assistant".to_string(),
+        content: if text.is_empty() && !calls.is_empty() { None } else { Some(text.into()) },
+        name: None,
+        tool_call_id: None,
+        tool_calls: if calls.is_empty() { None } else { Some(Value::Array(calls)) },
+    };
+    Ok((message, model, usage))
 }
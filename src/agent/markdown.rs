@@ -0,0 +1,249 @@
+#![allow(dead_code)]
+
+/// Converts the Markdown models write to the HTML subset Telegram accepts with
+/// `parse_mode: HTML`. Every tag opened is closed within the same call, and anything
+/// that doesn't parse as formatting is kept as escaped text, so the result is always
+/// valid for Telegram even when the input is not valid Markdown.
+pub fn to_telegram_html(markdown: &str) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut code: Option<(String, Vec<&str>)> = None;
+    let mut quote: Vec<String> = Vec::new();
+
+    for line in markdown.lines() {
+        if let Some((lang, body)) = &mut code {
+            if is_fence(line) {
+                out.push(code_block(lang, body));
+                code = None;
+            } else {
+                body.push(line);
+            }
+            continue;
+        }
+        let trimmed = line.trim_start();
+        if is_fence(line) {
+            flush_quote(&mut out, &mut quote);
+            let lang: String = trimmed.trim_start_matches(['`', '~']).trim().chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '#' | '_'))
+                .collect();
+            code = Some((lang, Vec::new()));
+        } else if let Some(rest) = trimmed.strip_prefix('>') {
+            quote.push(block_line(rest.strip_prefix(' ').unwrap_or(rest)));
+        } else {
+            flush_quote(&mut out, &mut quote);
+            out.push(block_line(line));
+        }
+    }
+    // An unterminated fence still renders as code rather than leaking raw backticks
+    if let Some((lang, body)) = &code {
+        out.push(code_block(lang, body));
+    }
+    flush_quote(&mut out, &mut quote);
+    out.join("\n")
+}
+
+/// Splits Markdown into pieces of at most `max_chars` characters, at line breaks where
+/// possible. A code block cut in two is closed at the end of one piece and reopened
+/// with the same fence at the start of the next, so each piece renders on its own.
+pub fn split_markdown(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut fence: Option<String> = None;
+
+    for line in text.lines() {
+        // Room for reopening and closing a fence, so every piece makes progress
+        let reserve = fence.as_ref().map(|f| f.chars().count() + 1).unwrap_or(0) + 4;
+        for piece in split_line(line, max_chars.saturating_sub(reserve).max(16)) {
+            let closing = if fence.is_some() { 4 } else { 0 };
+            if !current.is_empty() && current.chars().count() + 1 + piece.chars().count() + closing > max_chars {
+                if fence.is_some() {
+                    current.push_str("\n```");
+                }
+                push_chunk(&mut chunks, &current);
+                current = fence.clone().unwrap_or_default();
+            }
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.push_str(piece);
+        }
+        if is_fence(line) {
+            fence = match fence {
+                Some(_) => None,
+                None => Some(line.trim().to_string()),
+            };
+        }
+    }
+    push_chunk(&mut chunks, &current);
+    chunks
+}
+
+fn push_chunk(chunks: &mut Vec<String>, chunk: &str) {
+    let chunk = chunk.trim_matches('\n');
+    if !chunk.trim().is_empty() {
+        chunks.push(chunk.to_string());
+    }
+}
+
+/// Breaks an over-long line at whitespace (or anywhere, failing that).
+fn split_line(line: &str, max_chars: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = line;
+    while rest.chars().count() > max_chars {
+        let limit = rest.char_indices().nth(max_chars).map(|(i, _)| i).unwrap_or(rest.len());
+        let cut = match rest[..limit].rfind(char::is_whitespace) {
+            Some(i) if i > limit / 2 => i,
+            _ => limit,
+        };
+        pieces.push(&rest[..cut]);
+        rest = rest[cut..].trim_start();
+    }
+    pieces.push(rest);
+    pieces
+}
+
+fn is_fence(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.starts_with("```") || trimmed.starts_with("~~~")
+}
+
+fn code_block(lang: &str, body: &[&str]) -> String {
+    let code = escape(&body.join("\n"));
+    if lang.is_empty() {
+        format!("<pre>{}</pre>", code)
+    } else {
+        format!("<pre><code class=\"language-{}\">{}</code></pre>", lang, code)
+    }
+}
+
+fn flush_quote(out: &mut Vec<String>, quote: &mut Vec<String>) {
+    if !quote.is_empty() {
+        out.push(format!("<blockquote>{}</blockquote>", quote.join("\n")));
+        quote.clear();
+    }
+}
+
+/// Headings become bold lines and bullets become `•`; Telegram has neither.
+fn block_line(line: &str) -> String {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+    let hashes = trimmed.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&hashes) && trimmed[hashes..].starts_with(' ') {
+        return format!("<b>{}</b>", inline(trimmed[hashes..].trim()));
+    }
+    if (trimmed.len() >= 3 && trimmed.chars().all(|c| c == '-')) || trimmed == "***" || trimmed == "___" {
+        return "──────────".to_string();
+    }
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(rest) = trimmed.strip_prefix(bullet) {
+            return format!("{}• {}", indent, inline(rest));
+        }
+    }
+    inline(line)
+}
+
+fn inline(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    render_inline(&chars)
+}
+
+fn render_inline(chars: &[char]) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' if chars.get(i + 1).is_some_and(|n| n.is_ascii_punctuation()) => {
+                push_escaped(&mut out, chars[i + 1]);
+                i += 2;
+                continue;
+            }
+            '`' => {
+                if let Some(end) = (i + 1..chars.len()).find(|&j| chars[j] == '`') {
+                    let code: String = chars[i + 1..end].iter().collect();
+                    out.push_str(&format!("<code>{}</code>", escape(&code)));
+                    i = end + 1;
+                    continue;
+                }
+            }
+            '[' => {
+                if let Some((label, url, end)) = link_at(chars, i) {
+                    out.push_str(&format!("<a href=\"{}\">{}</a>", escape(&url).replace('"', "&quot;"), render_inline(label)));
+                    i = end + 1;
+                    continue;
+                }
+            }
+            '*' | '_' | '~' => {
+                if let Some((tag, len, end)) = emphasis_at(chars, i) {
+                    out.push_str(&format!("<{}>{}</{}>", tag, render_inline(&chars[i + len..end]), tag));
+                    i = end + len;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        push_escaped(&mut out, c);
+        i += 1;
+    }
+    out
+}
+
+/// `[label](url)` starting at `start`, for URLs Telegram will accept.
+fn link_at(chars: &[char], start: usize) -> Option<(&[char], String, usize)> {
+    let close = (start + 1..chars.len()).find(|&j| chars[j] == ']' || chars[j] == '[')?;
+    if chars[close] != ']' || chars.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let end = (close + 2..chars.len()).find(|&j| chars[j] == ')')?;
+    let url: String = chars[close + 2..end].iter().collect::<String>().trim().to_string();
+    let allowed = ["http://", "https://", "tg://", "mailto:"].iter().any(|p| url.starts_with(p));
+    if !allowed || url.contains(char::is_whitespace) || close == start + 1 {
+        return None;
+    }
+    Some((&chars[start + 1..close], url, end))
+}
+
+/// Emphasis opened at `start`: `(tag, delimiter length, index of the closing delimiter)`.
+/// Delimiters must hug their text, and `_` must sit at word boundaries so names like
+/// `snake_case_name` stay untouched.
+fn emphasis_at(chars: &[char], start: usize) -> Option<(&'static str, usize, usize)> {
+    let d = chars[start];
+    let double = chars.get(start + 1) == Some(&d);
+    let (tag, len) = match (d, double) {
+        ('~', true) => ("s", 2),
+        ('~', false) => return None,
+        (_, true) => ("b", 2),
+        (_, false) => ("i", 1),
+    };
+    let word = |i: usize| chars.get(i).is_some_and(|c| c.is_alphanumeric());
+    if !chars.get(start + len).is_some_and(|c| !c.is_whitespace() && *c != d) {
+        return None;
+    }
+    if d == '_' && start > 0 && word(start - 1) {
+        return None;
+    }
+    (start + len + 1..chars.len()).find(|&j| {
+        chars[j..].len() >= len
+            && chars[j..j + len].iter().all(|&c| c == d)
+            && !chars[j - 1].is_whitespace()
+            && chars[j - 1] != d
+            && (len == 2 || chars.get(j + 1) != Some(&d))
+            && !(d == '_' && word(j + len))
+    }).map(|end| (tag, len, end))
+}
+
+fn push_escaped(out: &mut String, c: char) {
+    match c {
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        _ => out.push(c),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        push_escaped(&mut out, c);
+    }
+    out
+}
@@ -1,12 +1,216 @@
 #![allow(dead_code)]
 use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use anyhow::Result;
-use time::{OffsetDateTime, format_description};
+use time::{Date, OffsetDateTime, UtcOffset};
+use time::macros::format_description;
+use std::sync::{Mutex, MutexGuard};
+use crate::agent::index::{KeywordIndex, tokenize};
 
+/// Serialises writes to memory files and indexes across concurrent runs.
+static MEMORY_LOCK: Mutex<()> = Mutex::new(());
+
+fn lock_memory() -> MutexGuard<'static, ()> {
+    MEMORY_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Long-term memory and daily notes. The workspace store is global; `scoped` stores
+/// live in `memory/scopes/<scope>/` and see the global MEMORY.md as a read-only shared layer.
+#[derive(Debug, Clone)]
 pub struct MemoryStore {
+    workspace: PathBuf,
     memory_dir: PathBuf,
     memory_file: PathBuf,
+    shared_file: Option<PathBuf>,
+    scope: Option<String>,
+    budget: usize,
+    offset: UtcOffset,
+}
+
+/// Default size cap for memory injected into the system prompt (~2k tokens).
+pub const DEFAULT_MEMORY_BUDGET: usize = 8 * 1024;
+/// How many days of notes, counting today, are candidates for the context.
+const RECENT_DAYS: i64 = 3;
+
+/// What `build_memory_context` included, for debugging prompt size.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryContextStats {
+    pub budget: usize,
+    pub long_term_bytes: usize,
+    pub long_term_total: usize,
+    /// `(YYYY-MM-DD, bytes included, bytes on disk)`, newest first.
+    pub days: Vec<(String, usize, usize)>,
+    pub total_bytes: usize,
+}
+
+impl MemoryContextStats {
+    pub fn summary(&self) -> String {
+        let mut out = format!(
+            "Memory context: {} / {} bytes (~{} tokens)\n- Long-term: {} / {} bytes",
+            self.total_bytes, self.budget, self.total_bytes / 4, self.long_term_bytes, self.long_term_total
+        );
+        for (date, included, total) in &self.days {
+            out.push_str(&format!("\n- {}: {} / {} bytes", date, included, total));
+        }
+        out
+    }
+}
+
+/// A daily-notes excerpt found by `MemoryStore::search`.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    /// Day as `YYYY-MM-DD`.
+    pub date: String,
+    pub score: f64,
+    pub snippet: String,
+}
+
+const SNIPPET_CHARS: usize = 400;
+
+/// A curated fact in MEMORY.md, stored as `- **key**: value` under a `## Section`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryEntry {
+    pub section: String,
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Remembered {
+    Added,
+    Updated,
+    Unchanged,
+}
+
+const DEFAULT_SECTION: &str = "General";
+
+/// MEMORY.md split into sections. Lines that are not entries (free text the
+/// user wrote by hand) are kept verbatim so curation never destroys them.
+struct LongTermDoc {
+    preamble: Vec<String>,
+    sections: Vec<(String, Vec<String>)>,
+}
+
+fn has_content(lines: &[String]) -> bool {
+    lines.iter().any(|l| !l.trim().is_empty())
+}
+
+fn parse_entry(line: &str) -> Option<(&str, &str)> {
+    let rest = line.trim().strip_prefix("- **")?;
+    let (key, value) = rest.split_once("**:")?;
+    Some((key.trim(), value.trim()))
+}
+
+impl LongTermDoc {
+    fn parse(content: &str) -> Self {
+        let mut doc = LongTermDoc { preamble: Vec::new(), sections: Vec::new() };
+        for line in content.lines() {
+            if let Some(title) = line.strip_prefix("## ") {
+                doc.sections.push((title.trim().to_string(), Vec::new()));
+            } else if let Some((_, lines)) = doc.sections.last_mut() {
+                lines.push(line.to_string());
+            } else {
+                doc.preamble.push(line.to_string());
+            }
+        }
+        doc
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let preamble = self.preamble.join("\n");
+        if preamble.trim().is_empty() {
+            out.push_str("# Long-term Memory\n");
+        } else {
+            out.push_str(preamble.trim_end());
+            out.push('\n');
+        }
+        for (title, lines) in &self.sections {
+            let body = lines.join("\n");
+            if body.trim().is_empty() {
+                // A heading written ahead of its entries
+                out.push_str(&format!("\n## {}\n", title));
+            } else {
+                out.push_str(&format!("\n## {}\n\n{}\n", title, body.trim()));
+            }
+        }
+        out
+    }
+
+    fn entries(&self) -> Vec<MemoryEntry> {
+        self.sections.iter().flat_map(|(title, lines)| {
+            lines.iter().filter_map(move |l| parse_entry(l).map(|(k, v)| MemoryEntry {
+                section: title.clone(),
+                key: k.to_string(),
+                value: v.to_string(),
+            }))
+        }).collect()
+    }
+
+    /// Removes every entry with `key` (case-insensitive), returning the last value seen.
+    fn remove(&mut self, key: &str) -> Option<(String, String)> {
+        let mut removed = None;
+        for (title, lines) in self.sections.iter_mut() {
+            lines.retain(|l| match parse_entry(l) {
+                Some((k, v)) if k.eq_ignore_ascii_case(key) => {
+                    removed = Some((title.clone(), v.to_string()));
+                    false
+                }
+                _ => true,
+            });
+        }
+        removed
+    }
+
+    /// `remove`, also dropping the sections it left empty. Sections that were
+    /// already empty stay.
+    fn forget(&mut self, key: &str) -> bool {
+        let had_content: Vec<bool> = self.sections.iter().map(|(_, lines)| has_content(lines)).collect();
+        if self.remove(key).is_none() {
+            return false;
+        }
+        let mut had_content = had_content.into_iter();
+        self.sections.retain(|(_, lines)| !had_content.next().unwrap_or(false) || has_content(lines));
+        true
+    }
+
+    fn insert(&mut self, section: &str, key: &str, value: &str) {
+        let line = format!("- **{}**: {}", key, value);
+        let idx = match self.sections.iter().position(|(t, _)| t.eq_ignore_ascii_case(section)) {
+            Some(i) => i,
+            None => {
+                self.sections.push((section.to_string(), Vec::new()));
+                self.sections.len() - 1
+            }
+        };
+        let lines = &mut self.sections[idx].1;
+        // Keep entries together, ahead of trailing blank lines
+        let pos = lines.iter().rposition(|l| !l.trim().is_empty()).map(|i| i + 1).unwrap_or(0);
+        lines.insert(pos, line);
+    }
+}
+
+/// MEMORY.md content with `key` set to `value`, as `remember` would write it.
+pub fn apply_entry(content: &str, section: Option<&str>, key: &str, value: &str) -> (String, Remembered) {
+    let key = key.trim();
+    let value = value.trim().replace('\n', " ");
+    let mut doc = LongTermDoc::parse(content);
+    let existing = doc.entries().into_iter().find(|e| e.key.eq_ignore_ascii_case(key));
+    let section = section.map(|s| s.trim()).filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .or_else(|| existing.as_ref().map(|e| e.section.clone()))
+        .unwrap_or_else(|| DEFAULT_SECTION.to_string());
+
+    if let Some(e) = &existing {
+        if e.value == value && e.section.eq_ignore_ascii_case(&section) {
+            return (content.to_string(), Remembered::Unchanged);
+        }
+    }
+    doc.remove(key);
+    doc.insert(&section, key, &value);
+    let outcome = if existing.is_some() { Remembered::Updated } else { Remembered::Added };
+    (doc.render(), outcome)
 }
 
 impl MemoryStore {
@@ -19,23 +223,228 @@ impl MemoryStore {
         }
 
         Self {
+            workspace: workspace.to_path_buf(),
             memory_dir: memory_dir.clone(),
             memory_file,
+            shared_file: None,
+            scope: None,
+            budget: DEFAULT_MEMORY_BUDGET,
+            offset: UtcOffset::UTC,
+        }
+    }
+
+    /// Store private to `scope` (a user or session), backed by the global MEMORY.md as shared layer.
+    pub fn scoped(&self, scope: &str) -> Self {
+        let global = self.workspace.join("memory");
+        let memory_dir = global.join("scopes").join(encode_scope(scope));
+        Self {
+            workspace: self.workspace.clone(),
+            memory_file: memory_dir.join("MEMORY.md"),
+            memory_dir,
+            shared_file: Some(global.join("MEMORY.md")),
+            scope: Some(scope.to_string()),
+            budget: self.budget,
+            offset: self.offset,
+        }
+    }
+
+    /// `scoped(scope)`, or the global store for `None`.
+    pub fn for_scope(&self, scope: Option<&str>) -> Self {
+        match scope {
+            Some(s) => self.scoped(s),
+            None => Self::new(&self.workspace).with_budget(self.budget).with_offset(self.offset),
+        }
+    }
+
+    pub fn scope(&self) -> Option<&str> {
+        self.scope.as_deref()
+    }
+
+    /// Every scope that has memory on disk.
+    pub fn scopes(&self) -> Vec<String> {
+        let mut scopes: Vec<String> = fs::read_dir(self.workspace.join("memory").join("scopes"))
+            .into_iter().flatten().flatten()
+            .filter(|e| e.path().is_dir())
+            .filter_map(|e| e.file_name().to_str().and_then(decode_scope))
+            .collect();
+        scopes.sort();
+        scopes
+    }
+
+    /// Bootstrap file such as `USER.md`: the scope's own copy if present, else the workspace one.
+    pub fn bootstrap_file(&self, name: &str) -> Option<PathBuf> {
+        let own = self.memory_dir.join(name);
+        if self.scope.is_some() && own.exists() {
+            return Some(own);
         }
+        let global = self.workspace.join(name);
+        global.exists().then_some(global)
+    }
+
+    /// The shared MEMORY.md a scoped store reads but never writes.
+    pub fn read_shared(&self) -> String {
+        self.shared_file.as_ref().and_then(|f| fs::read_to_string(f).ok()).unwrap_or_default()
+    }
+
+    /// Caps the memory context at `bytes`.
+    pub fn with_budget(mut self, bytes: usize) -> Self {
+        self.budget = bytes;
+        self
+    }
+
+    /// Files notes by days and stamps entries in `offset` instead of UTC.
+    pub fn with_offset(mut self, offset: UtcOffset) -> Self {
+        self.offset = offset;
+        self
     }
 
     pub fn workspace(&self) -> PathBuf {
-        self.memory_dir.parent().unwrap_or(&self.memory_dir).to_path_buf()
+        self.workspace.clone()
+    }
+
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc().to_offset(self.offset)
     }
 
     fn get_today_file(&self) -> PathBuf {
-        let now = OffsetDateTime::now_utc();
-        let year = now.year();
-        let month = u8::from(now.month());
-        let day = now.day();
-        let today = format!("{:04}{:02}{:02}", year, month, day);
-        let month_dir = format!("{:04}{:02}", year, month);
-        self.memory_dir.join(month_dir).join(format!("{}.md", today))
+        self.day_file(self.now().date())
+    }
+
+    /// `memory/YYYYMM/YYYYMMDD.md`
+    fn day_file(&self, date: Date) -> PathBuf {
+        let month_dir = format!("{:04}{:02}", date.year(), u8::from(date.month()));
+        self.memory_dir.join(month_dir).join(format!("{}.md", day_number(date)))
+    }
+
+    /// `memory/YYYYMM/YYYYMMDD.digest.md`, written by consolidation.
+    fn digest_file(&self, date: Date) -> PathBuf {
+        self.day_file(date).with_extension("digest.md")
+    }
+
+    pub fn today_number(&self) -> u32 {
+        day_number(self.now().date())
+    }
+
+    pub fn read_digest(&self, day: u32) -> Option<String> {
+        fs::read_to_string(self.digest_file(date_from_number(day)?)).ok()
+    }
+
+    pub fn write_digest(&self, day: u32, digest: &str) -> Result<()> {
+        let date = date_from_number(day).ok_or_else(|| anyhow::anyhow!("Invalid day {}", day))?;
+        crate::utils::write_atomic(&self.digest_file(date), digest.as_bytes())?;
+        Ok(())
+    }
+
+    /// Compresses a consolidated day's raw note to `YYYYMMDD.md.gz` with the system
+    /// `gzip` and re-indexes the day from its digest so search still finds it.
+    pub fn archive_day(&self, day: u32) -> Result<()> {
+        let _guard = lock_memory();
+        let date = date_from_number(day).ok_or_else(|| anyhow::anyhow!("Invalid day {}", day))?;
+        let digest = self.read_digest(day)
+            .filter(|d| !d.trim().is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Refusing to archive {} before it has a digest", day))?;
+        let status = std::process::Command::new("gzip")
+            .arg("-9").arg("-f")
+            .arg(self.day_file(date))
+            .status()?;
+        if !status.success() {
+            return Err(anyhow::anyhow!("gzip failed for {}", day));
+        }
+        let index = self.index();
+        index.remove(day)?;
+        index.add(day, &digest)?;
+        Ok(())
+    }
+
+    fn index(&self) -> KeywordIndex {
+        KeywordIndex::new(&self.memory_dir)
+    }
+
+    /// Every daily note on disk as `(YYYYMMDD, path)`, oldest first.
+    pub fn daily_files(&self) -> Vec<(u32, PathBuf)> {
+        let mut files = Vec::new();
+        let months = match fs::read_dir(&self.memory_dir) {
+            Ok(m) => m,
+            Err(_) => return files,
+        };
+        for month in months.flatten() {
+            if !month.path().is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(month.path()).into_iter().flatten().flatten() {
+                let path = entry.path();
+                let day = path.file_name().and_then(|n| n.to_str())
+                    .and_then(|n| n.strip_suffix(".md"))
+                    .filter(|n| n.len() == 8)
+                    .and_then(|n| n.parse::<u32>().ok());
+                if let Some(day) = day {
+                    files.push((day, path));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    /// Days whose raw note was archived, leaving only `YYYYMMDD.md.gz` and the digest.
+    fn archived_days(&self) -> Vec<u32> {
+        let mut days = Vec::new();
+        for month in fs::read_dir(&self.memory_dir).into_iter().flatten().flatten() {
+            for entry in fs::read_dir(month.path()).into_iter().flatten().flatten() {
+                let day = entry.file_name().to_str()
+                    .and_then(|n| n.strip_suffix(".md.gz"))
+                    .filter(|n| n.len() == 8)
+                    .and_then(|n| n.parse::<u32>().ok());
+                days.extend(day);
+            }
+        }
+        days.sort();
+        days
+    }
+
+    /// Re-indexes every daily note, and the digest of every archived day.
+    pub fn rebuild_index(&self) -> Result<()> {
+        let _guard = lock_memory();
+        let notes = self.daily_files().into_iter()
+            .filter_map(|(day, path)| fs::read_to_string(path).ok().map(|text| (day, text)));
+        let digests = self.archived_days().into_iter()
+            .filter_map(|day| self.read_digest(day).map(|text| (day, text)));
+        self.index().rebuild(notes.chain(digests))
+    }
+
+    /// Top `k` daily-note excerpts for `query`, best first.
+    pub fn search(&self, query: &str, k: usize) -> Result<Vec<SearchHit>> {
+        let index = self.index();
+        if !index.is_built() {
+            // A scope with no notes yet gets no index, so searching doesn't create it
+            if self.daily_files().is_empty() && self.archived_days().is_empty() {
+                return Ok(Vec::new());
+            }
+            self.rebuild_index()?;
+        }
+        // Compaction and rebuilds swap the index files under the memory lock
+        let hits = {
+            let _guard = lock_memory();
+            index.search(query, k)?
+        };
+        let terms = tokenize(query);
+        let mut results = Vec::new();
+        for hit in hits {
+            let path = self.day_file(match date_from_number(hit.date) {
+                Some(d) => d,
+                None => continue,
+            });
+            let text = match fs::read_to_string(path).ok().or_else(|| self.read_digest(hit.date)) {
+                Some(t) => t,
+                None => continue,
+            };
+            results.push(SearchHit {
+                date: format!("{:04}-{:02}-{:02}", hit.date / 10000, hit.date / 100 % 100, hit.date % 100),
+                score: hit.score,
+                snippet: best_snippet(&text, &terms),
+            });
+        }
+        Ok(results)
     }
 
     pub fn read_long_term(&self) -> String {
@@ -43,10 +452,48 @@ impl MemoryStore {
     }
 
     pub fn write_long_term(&self, content: &str) -> Result<()> {
-        fs::write(&self.memory_file, content)?;
+        crate::utils::write_atomic(&self.memory_file, content.as_bytes())?;
         Ok(())
     }
 
+    /// Adds or updates the entry `key`. Keys are unique across sections; a repeated
+    /// fact is a no-op, and a known key given a new section moves there.
+    pub fn remember(&self, section: Option<&str>, key: &str, value: &str) -> Result<Remembered> {
+        let _guard = lock_memory();
+        let (content, outcome) = apply_entry(&self.read_long_term(), section, key, value);
+        if outcome != Remembered::Unchanged {
+            self.write_long_term(&content)?;
+        }
+        Ok(outcome)
+    }
+
+    /// Removes the entry `key`; returns whether it existed.
+    pub fn forget(&self, key: &str) -> Result<bool> {
+        let _guard = lock_memory();
+        let mut doc = LongTermDoc::parse(&self.read_long_term());
+        if !doc.forget(key.trim()) {
+            return Ok(false);
+        }
+        self.write_long_term(&doc.render())?;
+        Ok(true)
+    }
+
+    /// Entries whose section, key or value contains every word of `query` (all entries if empty).
+    /// Shared entries follow the store's own, unless shadowed by an own entry with the same key.
+    pub fn recall(&self, query: &str) -> Vec<MemoryEntry> {
+        let words: Vec<String> = query.split_whitespace().map(|w| w.to_lowercase()).collect();
+        let mut entries = LongTermDoc::parse(&self.read_long_term()).entries();
+        for shared in LongTermDoc::parse(&self.read_shared()).entries() {
+            if !entries.iter().any(|e| e.key.eq_ignore_ascii_case(&shared.key)) {
+                entries.push(shared);
+            }
+        }
+        entries.into_iter().filter(|e| {
+            let haystack = format!("{} {} {}", e.section, e.key, e.value).to_lowercase();
+            words.iter().all(|w| haystack.contains(w))
+        }).collect()
+    }
+
     pub fn read_today(&self) -> String {
         fs::read_to_string(self.get_today_file()).unwrap_or_default()
     }
@@ -57,54 +504,223 @@ impl MemoryStore {
             fs::create_dir_all(parent)?;
         }
 
-        let existing = fs::read_to_string(&today_file).unwrap_or_default();
-        let now = OffsetDateTime::now_utc();
-        let format = format_description::parse("[year]-[month]-[day]").unwrap();
-        let date_str = now.format(&format).unwrap();
+        let _guard = lock_memory();
+        let now = self.now();
+        let entry = format!("[{}] {}", now.format(format_description!("[hour]:[minute]")).unwrap(), content);
 
-        let new_content = if existing.is_empty() {
-            format!("# {}\n\n{}", date_str, content)
+        // Append in place: a crash can at worst cut the last entry short, never lose the day
+        let mut file = fs::OpenOptions::new().create(true).read(true).append(true).open(&today_file)?;
+        let len = file.metadata()?.len();
+        let prefix = if len == 0 {
+            format!("# {}\n\n", now.format(format_description!("[year]-[month]-[day]")).unwrap())
         } else {
-            format!("{}\n{}", existing, content)
+            file.seek(SeekFrom::Start(len - 1))?;
+            let mut last = [0u8; 1];
+            file.read_exact(&mut last)?;
+            // Finish a line torn by an earlier crash before starting the next entry
+            if last[0] == b'\n' { "\n".to_string() } else { "\n\n".to_string() }
         };
-
-        fs::write(today_file, new_content)?;
+        file.write_all(format!("{}{}", prefix, entry).as_bytes())?;
+        file.sync_data()?;
+        if let Err(e) = self.index().add(day_number(now.date()), content) {
+            eprintln!("Failed to update memory index: {}", e);
+        }
         Ok(())
     }
 
     pub fn get_memory_context(&self) -> String {
+        self.build_memory_context().0
+    }
+
+    /// Memory for the system prompt, capped at the configured budget. Long-term memory
+    /// has priority; the remaining space goes to daily notes newest first, each cut from
+    /// the top so the latest exchanges survive. Only the needed tail of a day is read.
+    pub fn build_memory_context(&self) -> (String, MemoryContextStats) {
+        let mut stats = MemoryContextStats { budget: self.budget, ..Default::default() };
+        // Titles and separators count against the budget like the memory itself
+        let mut remaining = self.budget.saturating_sub(CONTEXT_TITLE.len());
         let mut parts = Vec::new();
 
-        let long_term = self.read_long_term();
-        if !long_term.is_empty() {
-            parts.push(format!("## Long-term Memory\n\n{}", long_term));
+        let shared = self.read_shared();
+        let own = self.read_long_term();
+        for (heading, content) in [("Shared Memory", &shared), ("Long-term Memory", &own)] {
+            // The file's own title would duplicate the heading below
+            let content = content.strip_prefix("# Long-term Memory").unwrap_or(content).trim();
+            stats.long_term_total += content.len();
+            if content.is_empty() {
+                continue;
+            }
+            let heading = format!("## {}\n\n", heading);
+            let overhead = heading.len() + if parts.is_empty() { 0 } else { SEPARATOR.len() };
+            let kept = truncate_bottom(content, remaining.saturating_sub(overhead));
+            if kept.is_empty() {
+                continue;
+            }
+            stats.long_term_bytes += kept.len();
+            remaining -= overhead + kept.len();
+            parts.push(format!("{}{}", heading, kept));
         }
 
+        let heading = "## Recent Daily Notes\n\n";
+        let mut notes_budget = remaining.saturating_sub(heading.len() + if parts.is_empty() { 0 } else { SEPARATOR.len() });
         let mut recent_notes = Vec::new();
-        let now = OffsetDateTime::now_utc();
-        
-        for i in 0..3 {
-            let date = now - time::Duration::days(i);
-            let year = date.year();
-            let month = u8::from(date.month());
-            let day = date.day();
-            let date_str = format!("{:04}{:02}{:02}", year, month, day);
-            let month_dir = format!("{:04}{:02}", year, month);
-            let file_path = self.memory_dir.join(month_dir).join(format!("{}.md", date_str));
-
-            if let Ok(data) = fs::read_to_string(file_path) {
-                recent_notes.push(data);
+        let now = self.now();
+
+        for i in 0..RECENT_DAYS {
+            let date = (now - time::Duration::days(i)).date();
+            let mut path = self.day_file(date);
+            if !path.exists() {
+                // Archived days are represented by their digest
+                path = self.digest_file(date);
+            }
+            let size = match fs::metadata(&path) {
+                Ok(m) => m.len() as usize,
+                Err(_) => continue,
+            };
+            let label = format!("{:04}-{:02}-{:02}", date.year(), u8::from(date.month()), date.day());
+            let separator = if recent_notes.is_empty() { 0 } else { SEPARATOR.len() };
+            let available = notes_budget.saturating_sub(separator);
+            let data = if available == 0 { String::new() } else { read_tail(&path, available).unwrap_or_default() };
+            stats.days.push((label, data.len(), size));
+            if data.trim().is_empty() {
+                continue;
             }
+            notes_budget -= separator + data.len();
+            recent_notes.push(data);
         }
 
         if !recent_notes.is_empty() {
-            parts.push(format!("## Recent Daily Notes\n\n{}", recent_notes.join("\n\n---\n\n")));
+            parts.push(format!("{}{}", heading, recent_notes.join(SEPARATOR)));
         }
 
         if parts.is_empty() {
-            return String::new();
+            return (String::new(), stats);
+        }
+
+        let context = format!("{}{}", CONTEXT_TITLE, parts.join(SEPARATOR));
+        stats.total_bytes = context.len();
+        (context, stats)
+    }
+}
+
+/// A scope's directory name, safe as a single path component. Bytes other than ASCII
+/// letters, digits and `-` become `_XX` hex, so distinct scopes never share a directory.
+pub fn encode_scope(scope: &str) -> String {
+    let mut name = String::new();
+    for b in scope.bytes() {
+        if b.is_ascii_alphanumeric() || b == b'-' {
+            name.push(b as char);
+        } else {
+            name.push_str(&format!("_{:02X}", b));
+        }
+    }
+    if name.is_empty() { "_".to_string() } else { name }
+}
+
+/// The scope a directory name encodes, or `None` if it isn't `encode_scope` output.
+fn decode_scope(name: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut rest = name.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'_' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok().filter(|s| !s.is_empty())
+}
+
+fn day_number(date: Date) -> u32 {
+    date.year() as u32 * 10000 + u8::from(date.month()) as u32 * 100 + date.day() as u32
+}
+
+fn date_from_number(n: u32) -> Option<Date> {
+    let month = time::Month::try_from((n / 100 % 100) as u8).ok()?;
+    Date::from_calendar_date((n / 10000) as i32, month, (n % 100) as u8).ok()
+}
+
+/// `User: ...`, optionally behind an `[HH:MM] ` timestamp.
+fn is_exchange_start(line: &str) -> bool {
+    let line = match line.strip_prefix('[').and_then(|l| l.split_once("] ")) {
+        Some((_, rest)) => rest,
+        None => line,
+    };
+    line.starts_with("User:")
+}
+
+/// The exchange in `text` mentioning the most query terms, trimmed to `SNIPPET_CHARS`.
+fn best_snippet(text: &str, terms: &[String]) -> String {
+    let mut chunks: Vec<String> = Vec::new();
+    for line in text.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        if is_exchange_start(line) || line.trim().is_empty() || chunks.is_empty() {
+            chunks.push(String::new());
+        }
+        if let Some(last) = chunks.last_mut() {
+            last.push_str(line);
+            last.push('\n');
         }
+    }
+    let best = chunks.iter()
+        .filter(|c| !c.trim().is_empty())
+        .max_by_key(|c| {
+            let tokens = tokenize(c);
+            terms.iter().map(|t| tokens.iter().filter(|w| *w == t).count()).sum::<usize>()
+        })
+        .map(|c| c.trim().to_string())
+        .unwrap_or_default();
+    if best.chars().count() > SNIPPET_CHARS {
+        let cut: String = best.chars().take(SNIPPET_CHARS).collect();
+        format!("{}...", cut)
+    } else {
+        best
+    }
+}
+
+const CONTEXT_TITLE: &str = "# Memory\n\n";
+const SEPARATOR: &str = "\n\n---\n\n";
+const TRUNCATED: &str = "\n... (truncated)";
+const OMITTED: &str = "... (earlier entries omitted)\n";
+
+/// At most `max` bytes from the start of `text`, cut at a line break. Empty if not
+/// even the truncation marker fits.
+fn truncate_bottom(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    if max < TRUNCATED.len() {
+        return String::new();
+    }
+    let mut end = max.saturating_sub(TRUNCATED.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let end = text[..end].rfind('\n').unwrap_or(end);
+    format!("{}{}", &text[..end], TRUNCATED)
+}
 
-        format!("# Memory\n\n{}", parts.join("\n\n---\n\n"))
+/// At most `max` bytes from the end of a file, starting at a line break, without reading the rest.
+fn read_tail(path: &Path, max: usize) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    if len as usize <= max {
+        return Ok(fs::read_to_string(path)?);
+    }
+    let max = max.saturating_sub(OMITTED.len());
+    file.seek(SeekFrom::Start(len - max as u64))?;
+    let mut buf = Vec::with_capacity(max);
+    file.take(max as u64).read_to_end(&mut buf)?;
+    let text = String::from_utf8_lossy(&buf);
+    // Drop the partial first line
+    let start = text.find('\n').map(|i| i + 1).unwrap_or(text.len());
+    if text[start..].trim().is_empty() {
+        return Ok(String::new());
     }
+    Ok(format!("{}{}", OMITTED, &text[start..]))
 }
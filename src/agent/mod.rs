@@ -2,40 +2,88 @@
 pub mod llm;
 pub mod memory;
 pub mod channels;
+pub mod scheduler;
+pub mod index;
+pub mod consolidate;
+pub mod session;
+pub mod workers;
+pub mod http;
+pub mod markdown;
+pub mod server;
 
 use anyhow::Result;
-use crate::config::Config;
+use crate::config::{Config, MemoryScope, ToolsConfig};
 use crate::tools::registry::ToolRegistry;
-use crate::agent::llm::{LLMClient, Message};
-use crate::agent::memory::MemoryStore;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use crate::tools::{Attachment, ToolContext, ToolOutput, ToolPolicy};
+use crate::agent::llm::{Completion, ContentPart, LLMClient, Message, MessageContent, ToolDefinition, Usage};
+use crate::agent::memory::{MemoryContextStats, MemoryStore};
+use crate::agent::session::{total_usage, SessionLog, SessionStore, StoredMessage};
 use std::fs;
-use std::path::PathBuf;
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Session {
-    pub messages: Vec<Message>,
-}
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+/// Cloning is cheap (the tool registry is shared), so concurrent channels run one
+/// clone per task, each with its own session and origin.
+#[derive(Clone)]
 pub struct Agent {
     client: LLMClient,
     memory: MemoryStore,
-    tools: ToolRegistry,
+    tools: Arc<ToolRegistry>,
+    tools_config: ToolsConfig,
+    tool_profile: Option<String>,
+    memory_scope: MemoryScope,
     max_iterations: usize,
+    vision: bool,
+    utc_offset: time::UtcOffset,
     session_key: String,
+    channel: Option<String>,
+    chat_id: Option<String>,
+    user_id: Option<String>,
+    attachments: Vec<Attachment>,
+    cancel: Option<Arc<AtomicBool>>,
+    stream: bool,
+    progress: Option<ProgressFn>,
 }
 
+/// What a run is doing, reported as it happens.
+#[derive(Debug, Clone)]
+pub enum Progress {
+    /// A tool is about to run with these raw arguments.
+    Tool { name: String, args: String },
+    /// A piece of reply text, when streaming.
+    Text(String),
+}
+
+pub type ProgressFn = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// Reply given when a run is stopped with the cancel flag.
+pub const CANCELLED_REPLY: &str = "Stopped.";
+
 impl Agent {
     pub fn new(config: &Config, client: LLMClient, tools: ToolRegistry) -> Self {
         let workspace = config.workspace_path();
-        let memory = MemoryStore::new(&workspace);
+        let utc_offset = config.utc_offset();
+        let memory = MemoryStore::new(&workspace)
+            .with_budget(config.agents.defaults.memory_budget)
+            .with_offset(utc_offset);
         Self {
             client,
             memory,
-            tools,
+            tools: Arc::new(tools),
+            tools_config: config.tools.clone(),
+            tool_profile: None,
+            memory_scope: config.agents.defaults.memory_scope,
             max_iterations: config.agents.defaults.max_tool_iterations,
+            vision: config.agents.defaults.vision,
+            utc_offset,
             session_key: "default".to_string(),
+            channel: None,
+            chat_id: None,
+            user_id: None,
+            attachments: Vec::new(),
+            cancel: None,
+            stream: config.agents.defaults.stream,
+            progress: None,
         }
     }
 
@@ -43,40 +91,159 @@ impl Agent {
         self.session_key = key.to_string();
     }
 
-    fn get_session_path(&self) -> PathBuf {
-        self.memory.workspace().join("sessions").join(format!("{}.json", self.session_key))
+    pub fn session_key(&self) -> &str {
+        &self.session_key
     }
 
-    fn load_session(&self) -> Session {
-        let path = self.get_session_path();
-        if let Ok(file) = fs::File::open(path) {
-            let reader = std::io::BufReader::new(file);
-            serde_json::from_reader(reader).unwrap_or(Session { messages: Vec::new() })
-        } else {
-            Session { messages: Vec::new() }
+    /// Records where the next run's input comes from, so tools can act on behalf of
+    /// that chat (e.g. deliver scheduled jobs back to it).
+    pub fn set_origin(&mut self, channel: &str, chat_id: &str, user_id: Option<&str>) {
+        self.channel = Some(channel.to_string());
+        self.chat_id = Some(chat_id.to_string());
+        self.user_id = user_id.map(|u| u.to_string());
+    }
+
+    /// A run checks `flag` between steps and stops early once it is set.
+    pub fn set_cancel_flag(&mut self, flag: Option<Arc<AtomicBool>>) {
+        self.cancel = flag;
+    }
+
+    /// Receives tool actions and, with `stream` enabled, reply text during each run.
+    pub fn set_progress(&mut self, progress: Option<ProgressFn>) {
+        self.progress = progress;
+    }
+
+    /// Overrides `agents.defaults.stream` for this agent's runs.
+    pub fn set_stream(&mut self, stream: bool) {
+        self.stream = stream;
+    }
+
+    fn report(&self, event: Progress) {
+        if let Some(progress) = &self.progress {
+            progress(&event);
         }
     }
 
-    fn save_session(&self, session: &Session) -> Result<()> {
-        let path = self.get_session_path();
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).ok();
+    /// Streams the completion when someone is watching, so they see text as it arrives.
+    fn complete(&self, messages: &[Message], tools: Option<&[ToolDefinition]>) -> Result<Completion> {
+        match &self.progress {
+            Some(progress) if self.stream => {
+                self.client.complete_streaming(messages, tools, &mut |text| progress(&Progress::Text(text.to_string())))
+            }
+            _ => self.client.complete(messages, tools),
         }
-        let file = fs::File::create(path)?;
-        let writer = std::io::BufWriter::new(file);
-        serde_json::to_writer(writer, session)?;
-        Ok(())
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|f| f.load(Ordering::SeqCst))
+    }
+
+    pub fn model(&self) -> &str {
+        &self.client.model
+    }
+
+    /// Tools the current session may call.
+    pub fn available_tools(&self) -> Vec<ToolDefinition> {
+        self.tools.get_definitions(&self.tool_context().policy)
+    }
+
+    /// Archives the current session's log so the next run starts without history.
+    pub fn reset_session(&self) -> Result<()> {
+        self.open_session()?.archive(0)
+    }
+
+    /// Messages in the current session and the tokens they used.
+    pub fn session_usage(&self) -> Result<(usize, Usage)> {
+        let messages = self.open_session()?.all()?;
+        Ok((messages.len(), total_usage(&messages)))
+    }
+
+    /// How the memory context for the next request would be assembled, for debugging.
+    pub fn memory_stats(&self) -> MemoryContextStats {
+        self.scoped_memory().build_memory_context().1
+    }
+
+    /// Memory scope of the current origin, or `None` for the global store.
+    fn memory_scope(&self) -> Option<String> {
+        let session = || Some(format!("session-{}", self.session_key));
+        match (self.memory_scope, &self.channel, &self.user_id) {
+            (MemoryScope::Global, _, _) => None,
+            (MemoryScope::Session, _, _) => session(),
+            (MemoryScope::User, Some(channel), Some(user)) => Some(format!("{}-user-{}", channel, user)),
+            // A channel message without a known user must not fall through to the global store
+            (MemoryScope::User, Some(_), None) => session(),
+            (MemoryScope::User, None, _) => None,
+        }
+    }
+
+    /// The only memory the current run may read or write.
+    fn scoped_memory(&self) -> MemoryStore {
+        self.memory.for_scope(self.memory_scope().as_deref())
+    }
+
+    /// Attachments produced by tools during the last run, for the channel to deliver.
+    pub fn take_attachments(&mut self) -> Vec<Attachment> {
+        std::mem::take(&mut self.attachments)
+    }
+
+    /// Sets the tool profile for subsequent runs, e.g. the one configured for a channel.
+    /// A per-session override in `tools.sessions` still takes precedence.
+    pub fn set_tool_profile(&mut self, profile: Option<&str>) {
+        self.tool_profile = profile.map(|p| p.to_string());
+    }
+
+    fn tool_context(&self) -> ToolContext {
+        let profile = self.tools_config.sessions.get(&self.session_key)
+            .or(self.tool_profile.as_ref());
+        ToolContext {
+            session_key: self.session_key.clone(),
+            channel: self.channel.clone(),
+            chat_id: self.chat_id.clone(),
+            user_id: self.user_id.clone(),
+            policy: ToolPolicy::resolve(&self.tools_config, profile.map(|p| p.as_str())),
+            memory_scope: self.memory_scope(),
+            workspace: Some(self.workspace()),
+        }
+    }
+
+    /// e.g. `Current date and time: Sunday, 2026-10-18 21:05 (UTC-03:00)`.
+    fn current_time(&self) -> String {
+        let now = time::OffsetDateTime::now_utc().to_offset(self.utc_offset);
+        let (h, m, _) = self.utc_offset.as_hms();
+        format!(
+            "Current date and time: {}, {} (UTC{}{:02}:{:02})",
+            now.weekday(),
+            crate::agent::scheduler::format_time(now),
+            if h < 0 || m < 0 { '-' } else { '+' },
+            h.abs(),
+            m.abs()
+        )
+    }
+
+    fn open_session(&self) -> Result<SessionLog> {
+        SessionStore::new(&self.memory.workspace()).open(&self.session_key)
+    }
+
+    pub fn workspace(&self) -> std::path::PathBuf {
+        self.memory.workspace()
     }
 
     pub fn run(&mut self, user_input: &str) -> Result<String> {
-        let mut session = self.load_session();
+        self.run_with_attachments(user_input, &[])
+    }
+
+    /// Like `run`, with files the user sent. A vision model also sees the images as
+    /// content parts for this request; the session keeps only the text, which names
+    /// every file.
+    pub fn run_with_attachments(&mut self, user_input: &str, attachments: &[Attachment]) -> Result<String> {
+        let log = self.open_session()?;
+        let mut new_messages = Vec::new();
         
+        let memory = self.scoped_memory();
         let mut bootstrap_context = String::new();
         let bootstrap_files = ["USER.md", "SOUL.md", "IDENTITY.md"];
-        let workspace = self.memory.workspace();
         for file in bootstrap_files {
-            let path = workspace.join(file);
-            if let Ok(content) = fs::read_to_string(path) {
+            if let Some(content) = memory.bootstrap_file(file).and_then(|p| fs::read_to_string(p).ok()) {
                 bootstrap_context.push_str(&format!("## {}\n\n{}\n\n", file, content));
             }
         }
@@ -84,58 +251,75 @@ impl Agent {
         let system_prompt = format!(
             "You are RedClaw, an ultra-efficient embedded AI agent. Keep responses brief. \
              If you have enough information from tool results, provide a final answer immediately. \
-             Avoid repeating the same tool calls with identical parameters.\n\n{}\n\n{}",
+             Avoid repeating the same tool calls with identical parameters.\n\n{}\n\n{}\n\n{}",
+            self.current_time(),
             bootstrap_context,
-            self.memory.get_memory_context()
+            memory.get_memory_context()
         );
 
         let mut api_messages = Vec::new();
         api_messages.push(Message {
             role: "system".to_string(),
-            content: Some(system_prompt),
+            content: Some(system_prompt.into()),
             name: None,
             tool_call_id: None,
             tool_calls: None,
         });
 
         let history_limit = 10;
-        let mut start = if session.messages.len() > history_limit {
-            session.messages.len() - history_limit
-        } else {
-            0
-        };
-        
-        while start < session.messages.len() && session.messages[start].role == "tool" {
-            start += 1;
-        }
-
-        for msg in &session.messages[start..] {
-            api_messages.push(msg.clone());
-        }
+        let history = log.recent(history_limit)?;
+        // A window starting mid tool exchange would orphan the tool results
+        let start = history.iter().position(|m| m.message.role != "tool").unwrap_or(history.len());
+        // Only the wire message goes to the provider; the envelope stays on disk
+        api_messages.extend(history.into_iter().skip(start).map(|m| m.message));
 
         let current_user_msg = Message {
             role: "user".to_string(),
-            content: Some(user_input.to_string()),
+            content: Some(user_input.into()),
             name: None,
             tool_call_id: None,
             tool_calls: None,
         };
-        api_messages.push(current_user_msg.clone());
-        session.messages.push(current_user_msg);
+        let images: Vec<ContentPart> = if self.vision {
+            attachments.iter().filter_map(|a| a.to_image_part()).collect()
+        } else {
+            Vec::new()
+        };
+        if images.is_empty() {
+            api_messages.push(current_user_msg.clone());
+        } else {
+            let mut parts = vec![ContentPart::Text { text: user_input.to_string() }];
+            parts.extend(images);
+            api_messages.push(Message { content: Some(MessageContent::Parts(parts)), ..current_user_msg.clone() });
+        }
+        new_messages.push(StoredMessage {
+            channel: self.channel.clone(),
+            user_id: self.user_id.clone(),
+            ..StoredMessage::new(current_user_msg)
+        });
 
+        let ctx = self.tool_context();
+        let tool_defs = self.tools.get_definitions(&ctx.policy);
         let mut iteration = 0;
         let mut final_content = String::new();
+        self.attachments.clear();
 
         while iteration < self.max_iterations {
+            // Checked before each step, so a stopped run never leaves a tool call unanswered
+            if self.cancelled() {
+                final_content = CANCELLED_REPLY.to_string();
+                break;
+            }
             iteration += 1;
-            let tool_defs = self.tools.get_definitions();
-            let response = self.client.chat(&api_messages, Some(&tool_defs))?;
+            let completion = self.complete(&api_messages, Some(&tool_defs))?;
+            let response = completion.message.clone();
 
             api_messages.push(response.clone());
-            session.messages.push(response.clone());
+            new_messages.push(completion.into());
 
             if let Some(tool_calls) = &response.tool_calls {
                 if let Some(calls) = tool_calls.as_array() {
+                    let mut image_parts = Vec::new();
                     for tc in calls {
                         let id = tc.get("id").and_then(|v| v.as_str()).unwrap_or("");
                         let function = tc.get("function").ok_or_else(|| anyhow::anyhow!("No function in tool call"))?;
@@ -143,49 +327,73 @@ impl Agent {
                         let args_str = function.get("arguments").and_then(|v| v.as_str()).unwrap_or("{}");
                         
                         println!("  Action: {}({})", name, args_str);
+                        self.report(Progress::Tool { name: name.to_string(), args: args_str.to_string() });
 
-                        let args: Value = serde_json::from_str(args_str)?;
-                        let result = match self.tools.execute(name, args) {
-                            Ok(res) => res,
-                            Err(e) => format!("Error: {}", e),
+                        // Argument and schema errors go back to the model so it can self-correct
+                        let started = std::time::Instant::now();
+                        let output = match self.tools.parse_arguments(args_str).and_then(|args| self.tools.execute(name, args, &ctx)) {
+                            Ok(out) => out,
+                            Err(e) => ToolOutput::error(e.to_string()),
                         };
 
+                        if self.vision {
+                            image_parts.extend(output.attachments.iter().filter_map(|a| a.to_image_part()));
+                        }
+
                         let tool_msg = Message {
                             role: "tool".to_string(),
-                            content: Some(result),
+                            content: Some(output.to_text().into()),
                             name: Some(name.to_string()),
                             tool_call_id: Some(id.to_string()),
                             tool_calls: None,
                         };
                         api_messages.push(tool_msg.clone());
-                        session.messages.push(tool_msg);
+                        new_messages.push(StoredMessage {
+                            duration_ms: Some(started.elapsed().as_millis() as u64),
+                            ..StoredMessage::new(tool_msg)
+                        });
+                        self.attachments.extend(output.attachments);
+                    }
+
+                    // Tool messages can't carry images, so show them to the model in a
+                    // follow-up user message. Kept out of the session to avoid bloating it.
+                    if !image_parts.is_empty() {
+                        image_parts.insert(0, ContentPart::Text { text: "Images returned by the tools above:".to_string() });
+                        api_messages.push(Message {
+                            role: "user".to_string(),
+                            content: Some(MessageContent::Parts(image_parts)),
+                            name: None,
+                            tool_call_id: None,
+                            tool_calls: None,
+                        });
                     }
                 }
             } else {
-                final_content = response.content.clone().unwrap_or_default();
+                final_content = response.text();
                 break;
             }
         }
 
         // If we hit the limit without a final answer, force one last completion without tools
-        if final_content.is_empty() && iteration >= self.max_iterations {
-            if let Ok(last_res) = self.client.chat(&api_messages, None) {
-                final_content = last_res.content.unwrap_or_default();
-                session.messages.push(Message {
+        if final_content.is_empty() && iteration >= self.max_iterations && !self.cancelled() {
+            if let Ok(mut last_res) = self.complete(&api_messages, None) {
+                final_content = last_res.message.text();
+                last_res.message = Message {
                     role: "assistant".to_string(),
-                    content: Some(final_content.clone()),
+                    content: Some(final_content.clone().into()),
                     name: None,
                     tool_call_id: None,
                     tool_calls: None,
-                });
+                };
+                new_messages.push(last_res.into());
             }
         }
 
-        if !final_content.is_empty() {
-            let _ = self.memory.append_today(&format!("User: {}\nAssistant: {}\n", user_input, final_content));
+        if !final_content.is_empty() && !self.cancelled() {
+            let _ = memory.append_today(&format!("User: {}\nAssistant: {}\n", user_input, final_content));
         }
 
-        self.save_session(&session)?;
+        log.append(&new_messages)?;
         api_messages.clear();
         api_messages.shrink_to_fit();
         
@@ -196,22 +404,21 @@ impl Agent {
         let prompt = "Provide a very concise summary of this conversation segment, preserving core context and key points.\n\nCONVERSATION:\n";
         let summary_messages = vec![Message {
             role: "user".to_string(),
-            content: Some(format!("{}{}", prompt, self.format_messages(messages))),
+            content: Some(format!("{}{}", prompt, self.format_messages(messages)).into()),
             name: None,
             tool_call_id: None,
             tool_calls: None,
         }];
 
         let response = self.client.chat(&summary_messages, None)?;
-        Ok(response.content.unwrap_or_default())
+        Ok(response.text())
     }
 
     fn format_messages(&self, messages: &[Message]) -> String {
         messages.iter()
             .filter(|m| m.role == "user" || m.role == "assistant")
             .map(|m| {
-                let content = m.content.as_deref().unwrap_or("");
-                format!("{}: {}", m.role, content)
+                format!("{}: {}", m.role, m.text())
             })
             .collect::<Vec<_>>()
             .join("\n")
@@ -0,0 +1,393 @@
+#![allow(dead_code)]
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use time::{Date, Duration, Month, OffsetDateTime, Time};
+use std::sync::{Mutex, MutexGuard};
+use crate::utils::parse_utc_offset;
+
+/// Serialises read-modify-write cycles on schedule files across concurrent runs.
+static SCHEDULE_LOCK: Mutex<()> = Mutex::new(());
+
+fn lock_schedule() -> MutexGuard<'static, ()> {
+    SCHEDULE_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Schedule {
+    /// Fires once at a unix timestamp.
+    Once { at: i64 },
+    /// Fires on every match of a 5-field cron expression.
+    Cron { expr: String },
+}
+
+/// Id of the system job created from `agents.defaults.consolidation`.
+pub const CONSOLIDATE_JOB_ID: &str = "consolidate-memory";
+
+/// Built-in work a system job performs instead of running its prompt.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobAction {
+    ConsolidateMemory { archive: bool },
+}
+
+/// A prompt the agent runs on a schedule, in the session and chat it was created from.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Job {
+    pub id: String,
+    pub prompt: String,
+    pub schedule: Schedule,
+    pub timezone: String,
+    pub session_key: String,
+    pub channel: Option<String>,
+    pub chat_id: Option<String>,
+    /// Who created the job, so it runs with that user's memory.
+    #[serde(default)]
+    pub user_id: Option<String>,
+    pub next_run: i64,
+    pub created_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<JobAction>,
+}
+
+impl Job {
+    /// Next fire time strictly after `after`, or `None` when a one-shot job is spent.
+    pub fn next_after(&self, after: i64) -> Result<Option<i64>> {
+        match &self.schedule {
+            Schedule::Once { at } => Ok(if *at > after { Some(*at) } else { None }),
+            Schedule::Cron { expr } => {
+                let cron = Cron::parse(expr)?;
+                let offset = parse_utc_offset(&self.timezone)?;
+                let t = OffsetDateTime::from_unix_timestamp(after)?.to_offset(offset);
+                Ok(cron.next_after(t).map(|t| t.unix_timestamp()))
+            }
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let when = match &self.schedule {
+            Schedule::Once { .. } => "once".to_string(),
+            Schedule::Cron { expr } => format!("cron '{}'", expr),
+        };
+        let next = parse_utc_offset(&self.timezone).ok()
+            .and_then(|o| OffsetDateTime::from_unix_timestamp(self.next_run).ok().map(|t| t.to_offset(o)))
+            .map(format_time)
+            .unwrap_or_default();
+        format!("[{}] {}, next: {} ({}): {}", self.id, when, next, self.timezone, self.prompt)
+    }
+}
+
+pub fn format_time(t: OffsetDateTime) -> String {
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", t.year(), u8::from(t.month()), t.day(), t.hour(), t.minute())
+}
+
+/// Persists jobs in `workspace/schedule.json`.
+pub struct Scheduler {
+    path: PathBuf,
+}
+
+impl Scheduler {
+    pub fn new(workspace: &Path) -> Self {
+        Self { path: workspace.join("schedule.json") }
+    }
+
+    /// Jobs on disk. An unparseable file is quarantined so the next save doesn't
+    /// silently replace every job with an empty list.
+    pub fn load(&self) -> Vec<Job> {
+        let data = match fs::read_to_string(&self.path) {
+            Ok(d) => d,
+            Err(_) => return Vec::new(),
+        };
+        match serde_json::from_str(&data) {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                match crate::utils::quarantine(&self.path) {
+                    Ok(moved) => eprintln!("Schedule file is corrupt ({}); moved to {}", e, moved.display()),
+                    Err(qe) => eprintln!("Schedule file is corrupt ({}) and could not be moved: {}", e, qe),
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    fn save(&self, jobs: &[Job]) -> Result<()> {
+        crate::utils::write_atomic(&self.path, serde_json::to_string_pretty(jobs)?.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn add(&self, mut job: Job) -> Result<Job> {
+        let _guard = lock_schedule();
+        let mut jobs = self.load();
+        if job.id.is_empty() {
+            job.id = new_id(&jobs);
+        }
+        jobs.push(job.clone());
+        self.save(&jobs)?;
+        Ok(job)
+    }
+
+    /// Adds `job`, replacing any job with the same id.
+    pub fn upsert(&self, job: Job) -> Result<Job> {
+        let _guard = lock_schedule();
+        let mut jobs = self.load();
+        match jobs.iter_mut().find(|j| j.id == job.id) {
+            Some(existing) => *existing = job.clone(),
+            None => jobs.push(job.clone()),
+        }
+        self.save(&jobs)?;
+        Ok(job)
+    }
+
+    /// Installs or updates the consolidation system job on `channel`, or removes it
+    /// when `cron` is `None`. An unchanged job keeps its armed time.
+    pub fn sync_consolidation(&self, channel: &str, cron: Option<&str>, archive: bool, timezone: &str) -> Result<()> {
+        let cron = match cron {
+            Some(c) => c,
+            None => {
+                self.remove(CONSOLIDATE_JOB_ID)?;
+                return Ok(());
+            }
+        };
+        Cron::parse(cron)?;
+        let schedule = Schedule::Cron { expr: cron.to_string() };
+        let action = Some(JobAction::ConsolidateMemory { archive });
+        if self.load().iter().any(|j| j.id == CONSOLIDATE_JOB_ID && j.schedule == schedule
+            && j.action == action && j.timezone == timezone && j.channel.as_deref() == Some(channel)) {
+            return Ok(());
+        }
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let mut job = Job {
+            id: CONSOLIDATE_JOB_ID.to_string(),
+            prompt: "Consolidate daily notes into long-term memory".to_string(),
+            schedule,
+            timezone: timezone.to_string(),
+            session_key: String::new(),
+            channel: Some(channel.to_string()),
+            chat_id: None,
+            user_id: None,
+            next_run: 0,
+            created_at: now,
+            action,
+        };
+        job.next_run = job.next_after(now)?.unwrap_or(i64::MAX);
+        self.upsert(job)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, id: &str) -> Result<Option<Job>> {
+        let _guard = lock_schedule();
+        let mut jobs = self.load();
+        let removed = jobs.iter().position(|j| j.id == id).map(|i| jobs.remove(i));
+        if removed.is_some() {
+            self.save(&jobs)?;
+        }
+        Ok(removed)
+    }
+
+    /// Removes and returns the jobs on `channel` due at `now`. Recurring jobs are
+    /// re-armed for their next occurrence before returning, so a crash while running
+    /// one skips that occurrence rather than repeating it forever.
+    pub fn take_due(&self, channel: &str, now: i64) -> Result<Vec<Job>> {
+        let _guard = lock_schedule();
+        let mut jobs = self.load();
+        let mut due = Vec::new();
+        let mut changed = false;
+
+        jobs.retain_mut(|job| {
+            if job.channel.as_deref() != Some(channel) || job.next_run > now {
+                return true;
+            }
+            changed = true;
+            due.push(job.clone());
+            match job.next_after(now) {
+                Ok(Some(next)) if matches!(job.schedule, Schedule::Cron { .. }) => {
+                    job.next_run = next;
+                    true
+                }
+                _ => false,
+            }
+        });
+
+        if changed {
+            self.save(&jobs)?;
+        }
+        Ok(due)
+    }
+}
+
+fn new_id(existing: &[Job]) -> String {
+    let mut n = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1000) as u64;
+    loop {
+        let id = format!("{:06x}", n & 0xff_ffff);
+        if !existing.iter().any(|j| j.id == id) {
+            return id;
+        }
+        n = n.wrapping_add(1);
+    }
+}
+
+/// Parses a one-shot time: `HH:MM` (next occurrence), `YYYY-MM-DD HH:MM` or `YYYY-MM-DDTHH:MM`.
+pub fn parse_at(input: &str, now: OffsetDateTime) -> Result<OffsetDateTime> {
+    let input = input.trim();
+    let bad = || anyhow!("Invalid time '{}'. Use HH:MM or YYYY-MM-DD HH:MM", input);
+    let parse_hm = |s: &str| -> Result<Time> {
+        let (h, m) = s.split_once(':').ok_or_else(bad)?;
+        let m = m.get(..2).unwrap_or(m);
+        Time::from_hms(h.parse().map_err(|_| bad())?, m.parse().map_err(|_| bad())?, 0).map_err(|_| bad())
+    };
+
+    match input.split_once([' ', 'T']) {
+        Some((date, time)) => {
+            let mut parts = date.split('-');
+            let y: i32 = parts.next().and_then(|p| p.parse().ok()).ok_or_else(bad)?;
+            let m: u8 = parts.next().and_then(|p| p.parse().ok()).ok_or_else(bad)?;
+            let d: u8 = parts.next().and_then(|p| p.parse().ok()).ok_or_else(bad)?;
+            let month = Month::try_from(m).map_err(|_| bad())?;
+            let date = Date::from_calendar_date(y, month, d).map_err(|_| bad())?;
+            Ok(now.replace_date(date).replace_time(parse_hm(time)?))
+        }
+        None => {
+            let t = now.replace_time(parse_hm(input)?);
+            Ok(if t <= now { t + Duration::days(1) } else { t })
+        }
+    }
+}
+
+/// Standard 5-field cron: minute hour day-of-month month day-of-week.
+/// Supports `*`, lists, ranges, steps, month/day names and `@hourly`-style aliases.
+#[derive(Debug, Clone)]
+pub struct Cron {
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    days_any: bool,
+    weekdays_any: bool,
+}
+
+const MONTH_NAMES: &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const DAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl Cron {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let expr = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(anyhow!("Cron expression '{}' must have 5 fields: minute hour day month weekday", expr));
+        }
+        let (minutes, _) = parse_field(fields[0], 0, 59, &[])?;
+        let (hours, _) = parse_field(fields[1], 0, 23, &[])?;
+        let (days, days_any) = parse_field(fields[2], 1, 31, &[])?;
+        let (months, _) = parse_field(fields[3], 1, 12, MONTH_NAMES)?;
+        let (mut weekdays, weekdays_any) = parse_field(fields[4], 0, 7, DAY_NAMES)?;
+        // 7 is an alias for Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            minutes,
+            hours: hours as u32,
+            days: days as u32,
+            months: months as u16,
+            weekdays: (weekdays & 0x7f) as u8,
+            days_any,
+            weekdays_any,
+        })
+    }
+
+    fn day_matches(&self, t: OffsetDateTime) -> bool {
+        let dom = self.days & (1 << t.day()) != 0;
+        let dow = self.weekdays & (1 << t.weekday().number_days_from_sunday()) != 0;
+        match (self.days_any, self.weekdays_any) {
+            (true, true) => true,
+            (false, true) => dom,
+            (true, false) => dow,
+            // Both restricted: cron fires when either matches
+            (false, false) => dom || dow,
+        }
+    }
+
+    /// First matching minute strictly after `after`, in `after`'s offset.
+    pub fn next_after(&self, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        let mut t = after.replace_second(0).ok()?.replace_nanosecond(0).ok()? + Duration::minutes(1);
+        // Five years covers every valid expression, including Feb 29th
+        let limit = t + Duration::days(5 * 366);
+
+        while t < limit {
+            if self.months & (1 << u8::from(t.month())) == 0 {
+                let (y, m) = match t.month() {
+                    Month::December => (t.year() + 1, Month::January),
+                    m => (t.year(), m.next()),
+                };
+                t = t.replace_date(Date::from_calendar_date(y, m, 1).ok()?).replace_time(Time::MIDNIGHT);
+                continue;
+            }
+            if !self.day_matches(t) {
+                t = t.replace_time(Time::MIDNIGHT) + Duration::days(1);
+                continue;
+            }
+            if self.hours & (1 << t.hour()) == 0 {
+                t = t.replace_minute(0).ok()? + Duration::hours(1);
+                continue;
+            }
+            if self.minutes & (1 << t.minute()) == 0 {
+                t += Duration::minutes(1);
+                continue;
+            }
+            return Some(t);
+        }
+        None
+    }
+}
+
+fn parse_value(s: &str, names: &[&str], offset: u64) -> Option<u64> {
+    s.parse().ok().or_else(|| {
+        let lower = s.to_lowercase();
+        names.iter().position(|n| *n == lower).map(|i| i as u64 + offset)
+    })
+}
+
+/// Returns the bitmask of allowed values and whether the field was a bare `*`.
+fn parse_field(field: &str, min: u64, max: u64, names: &[&str]) -> Result<(u64, bool)> {
+    let bad = || anyhow!("Invalid cron field '{}'", field);
+    // Month names start at 1, weekday names at 0
+    let name_offset = min;
+    let mut mask = 0u64;
+
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((r, s)) => (r, s.parse::<u64>().map_err(|_| bad())?),
+            None => (item, 1),
+        };
+        if step == 0 {
+            return Err(bad());
+        }
+        let (lo, hi) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (parse_value(a, names, name_offset).ok_or_else(bad)?, parse_value(b, names, name_offset).ok_or_else(bad)?)
+        } else {
+            let v = parse_value(range, names, name_offset).ok_or_else(bad)?;
+            (v, if item.contains('/') { max } else { v })
+        };
+        if lo < min || hi > max || lo > hi {
+            return Err(bad());
+        }
+        let mut v = lo;
+        while v <= hi {
+            mask |= 1 << v;
+            v += step;
+        }
+    }
+    Ok((mask, field == "*"))
+}
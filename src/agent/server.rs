@@ -0,0 +1,426 @@
+#![allow(dead_code)]
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use crate::agent::{Agent, Progress};
+use crate::agent::http::{self, Request};
+use crate::agent::session::SessionStore;
+use std::collections::HashMap;
+use std::io::{BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Agent runs are shallow; a small stack keeps idle connections cheap.
+const CONNECTION_STACK: usize = 1024 * 1024;
+/// How long shutdown waits for requests in progress.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(120);
+/// How long a client has to send its whole request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Connections still sending their request. Beyond this, new ones are refused on the
+/// accept thread; `max_concurrent` only counts authenticated requests.
+const MAX_PENDING: usize = 64;
+
+/// A local HTTP API over the agent: an OpenAI-compatible `POST /v1/chat/completions`
+/// (streaming included), `GET /v1/models` and `/v1/sessions` to list, read and delete
+/// conversations. Each connection carries one request on its own thread.
+pub struct ApiServer {
+    listener: TcpListener,
+    token: String,
+    tool_profile: Option<String>,
+    max_concurrent: usize,
+}
+
+/// State of a running server, shared by its request threads.
+struct Shared {
+    token: String,
+    tool_profile: Option<String>,
+    max_concurrent: usize,
+    /// Connections whose request hasn't been read and authenticated yet.
+    pending: AtomicUsize,
+    /// Authenticated requests in progress.
+    active: AtomicUsize,
+    /// Requests for one session run one at a time, like a chat's queue.
+    sessions: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+/// Holds a place in `Shared::pending` or `Shared::active` until dropped.
+struct Slot {
+    shared: Arc<Shared>,
+    active: bool,
+}
+
+impl Slot {
+    /// Takes a place if fewer than `max` are taken.
+    fn acquire(shared: &Arc<Shared>, active: bool, max: usize) -> Option<Self> {
+        let counter = if active { &shared.active } else { &shared.pending };
+        counter.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < max).then_some(n + 1)).ok()?;
+        Some(Self { shared: shared.clone(), active })
+    }
+
+    fn counter(&self) -> &AtomicUsize {
+        if self.active { &self.shared.active } else { &self.shared.pending }
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.counter().fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ApiServer {
+    pub fn bind(addr: &str, token: &str) -> Result<Self> {
+        if token.trim().is_empty() {
+            return Err(anyhow!("The API server needs a token; set server.token in the config"));
+        }
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            token: token.trim().to_string(),
+            tool_profile: None,
+            max_concurrent: 4,
+        })
+    }
+
+    /// Tool profile applied to every request.
+    pub fn with_tool_profile(mut self, profile: Option<String>) -> Self {
+        self.tool_profile = profile;
+        self
+    }
+
+    pub fn with_max_concurrent(mut self, max: usize) -> Self {
+        self.max_concurrent = max.max(1);
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serves until shutdown, then waits for the requests in progress.
+    pub fn run(&self, agent: Agent) -> Result<()> {
+        let addr = self.local_addr()?;
+        // A connection of our own wakes the listener so it sees the shutdown flag
+        crate::utils::on_shutdown(move || {
+            let _ = TcpStream::connect_timeout(&addr, Duration::from_secs(1));
+        });
+        println!("API server listening on http://{} ({} concurrent requests).", addr, self.max_concurrent);
+        let shared = Arc::new(Shared {
+            token: self.token.clone(),
+            tool_profile: self.tool_profile.clone(),
+            max_concurrent: self.max_concurrent,
+            pending: AtomicUsize::new(0),
+            active: AtomicUsize::new(0),
+            sessions: Mutex::new(HashMap::new()),
+        });
+
+        while !crate::utils::shutdown_requested() {
+            let mut stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    eprintln!("API listener error: {}", e);
+                    crate::utils::sleep_unless_shutdown(Duration::from_millis(200));
+                    continue;
+                }
+            };
+            if crate::utils::shutdown_requested() {
+                break;
+            }
+            let pending = match Slot::acquire(&shared, false, MAX_PENDING) {
+                Some(slot) => slot,
+                None => {
+                    reject_busy(stream);
+                    continue;
+                }
+            };
+            let agent = agent.clone();
+            let spawned = std::thread::Builder::new()
+                .name("api-request".to_string())
+                .stack_size(CONNECTION_STACK)
+                .spawn(move || {
+                    let shared = pending.shared.clone();
+                    // Slots are freed on return, before the client sees the connection close
+                    handle_connection(&shared, pending, agent, &mut stream);
+                });
+            if let Err(e) = spawned {
+                eprintln!("Failed to start request thread: {}", e);
+            }
+        }
+
+        let deadline = Instant::now() + SHUTDOWN_GRACE;
+        let busy = || shared.active.load(Ordering::SeqCst) + shared.pending.load(Ordering::SeqCst) > 0;
+        while busy() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        println!("API server stopped.");
+        Ok(())
+    }
+}
+
+impl Shared {
+    fn session_lock(&self, key: &str) -> Arc<Mutex<()>> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        // Drop locks nobody holds, so the map doesn't grow with every session ever seen
+        sessions.retain(|_, lock| Arc::strong_count(lock) > 1);
+        sessions.entry(key.to_string()).or_default().clone()
+    }
+}
+
+/// Refuses a connection on the accept thread without waiting on the client: the 429
+/// goes out only if the socket takes it at once, and the connection is closed.
+fn reject_busy(mut stream: TcpStream) {
+    let _ = stream.set_nonblocking(true);
+    let _ = write_error(&mut stream, 429, "rate_limit_exceeded", "Too many connections");
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+/// Reads the request, checks the token, and only then takes one of the
+/// `max_concurrent` places, so clients that never authenticate can't hold them.
+fn handle_connection(shared: &Arc<Shared>, pending: Slot, agent: Agent, stream: &mut TcpStream) {
+    let _ = stream.set_write_timeout(Some(Duration::from_secs(30)));
+    let deadline = Deadline { stream, until: Instant::now() + REQUEST_TIMEOUT };
+    let request = match http::read_request(&mut BufReader::new(deadline)) {
+        Ok(r) => r,
+        Err(e) => {
+            let _ = write_error(stream, 400, "invalid_request_error", &e.to_string());
+            return;
+        }
+    };
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(10)));
+    let token = request.header("Authorization").and_then(|h| h.strip_prefix("Bearer ")).unwrap_or("");
+    if !http::secret_matches(token.trim(), &shared.token) {
+        let _ = write_error(stream, 401, "authentication_error", "Invalid or missing bearer token");
+        return;
+    }
+    drop(pending);
+    let _active = match Slot::acquire(shared, true, shared.max_concurrent) {
+        Some(slot) => slot,
+        None => {
+            let _ = write_error(stream, 429, "rate_limit_exceeded", "Too many requests in progress");
+            return;
+        }
+    };
+
+    let result = match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/v1/chat/completions") => chat_completions(shared, agent, &request, stream),
+        ("GET", "/v1/models") => write_json(stream, 200, &serde_json::json!({
+            "object": "list",
+            "data": [{ "id": agent.model(), "object": "model", "owned_by": "redclaw" }],
+        })),
+        ("GET", "/v1/sessions") => list_sessions(&agent, stream),
+        (method, path) => match path.strip_prefix("/v1/sessions/") {
+            Some(key) => session(shared, &agent, method, key, stream),
+            None => write_error(stream, 404, "invalid_request_error", "Unknown endpoint"),
+        },
+    };
+    if let Err(e) = result {
+        eprintln!("API request {} {} failed: {}", request.method, request.path, e);
+    }
+}
+
+/// Reads from a stream until `until`, however slowly the client sends.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    until: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "the request took too long to arrive"));
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        let mut stream = self.stream;
+        stream.read(buf)
+    }
+}
+
+/// Runs the agent on the last user message. The agent keeps the conversation itself,
+/// so earlier messages in the request are not replayed; `session` (or `user`) picks it.
+fn chat_completions(shared: &Shared, mut agent: Agent, request: &Request, stream: &mut TcpStream) -> Result<()> {
+    let body: Value = match serde_json::from_slice(&request.body) {
+        Ok(v) => v,
+        Err(e) => return write_error(stream, 400, "invalid_request_error", &format!("Invalid JSON: {}", e)),
+    };
+    let input = match last_user_text(&body) {
+        Some(text) if !text.trim().is_empty() => text,
+        _ => return write_error(stream, 400, "invalid_request_error", "messages must include a user message with text"),
+    };
+    let key = body["session"].as_str().or_else(|| body["user"].as_str()).unwrap_or("api");
+    if let Err(e) = SessionStore::validate_key(key) {
+        return write_error(stream, 400, "invalid_request_error", &e.to_string());
+    }
+
+    let lock = shared.session_lock(key);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+    agent.set_session(key);
+    agent.set_origin("api", key, body["user"].as_str());
+    agent.set_tool_profile(shared.tool_profile.as_deref());
+
+    let id = format!("chatcmpl-{}", time::OffsetDateTime::now_utc().unix_timestamp_nanos());
+    let created = time::OffsetDateTime::now_utc().unix_timestamp();
+    if body["stream"].as_bool().unwrap_or(false) {
+        return stream_completion(agent, &input, stream, &id, created);
+    }
+    match agent.run(&input) {
+        Ok(reply) => write_json(stream, 200, &serde_json::json!({
+            "id": id,
+            "object": "chat.completion",
+            "created": created,
+            "model": agent.model(),
+            "choices": [{ "index": 0, "message": { "role": "assistant", "content": reply }, "finish_reason": "stop" }],
+        })),
+        Err(e) => write_error(stream, 500, "server_error", &e.to_string()),
+    }
+}
+
+/// Sends the reply as `chat.completion.chunk` events while the agent writes it. Text
+/// written before a tool call is kept, separated from what follows by a blank line.
+/// A client that hangs up cancels the run.
+fn stream_completion(mut agent: Agent, input: &str, stream: &mut TcpStream, id: &str, created: i64) -> Result<()> {
+    http::write_head(stream, 200, "text/event-stream")?;
+    let events = Arc::new(EventStream {
+        out: Mutex::new(stream.try_clone()?),
+        id: id.to_string(),
+        model: agent.model().to_string(),
+        created,
+        gone: Arc::new(AtomicBool::new(false)),
+        since_tool: Mutex::new(String::new()),
+    });
+    events.delta(serde_json::json!({ "role": "assistant", "content": "" }), None);
+
+    let progress = {
+        let events = events.clone();
+        Arc::new(move |event: &Progress| {
+            let mut since_tool = events.since_tool.lock().unwrap_or_else(|e| e.into_inner());
+            match event {
+                Progress::Text(text) => {
+                    since_tool.push_str(text);
+                    events.delta(serde_json::json!({ "content": text }), None);
+                }
+                Progress::Tool { .. } if !since_tool.is_empty() => {
+                    since_tool.clear();
+                    events.delta(serde_json::json!({ "content": "\n\n" }), None);
+                }
+                Progress::Tool { .. } => {}
+            }
+        })
+    };
+    agent.set_stream(true);
+    agent.set_progress(Some(progress));
+    agent.set_cancel_flag(Some(events.gone.clone()));
+    let result = agent.run(input);
+    agent.set_progress(None);
+    agent.set_cancel_flag(None);
+
+    match result {
+        Ok(reply) => {
+            // Replies the provider didn't stream (e.g. a forced final answer) arrive whole
+            let streamed = !events.since_tool.lock().unwrap_or_else(|e| e.into_inner()).is_empty();
+            if !streamed && !reply.is_empty() {
+                events.delta(serde_json::json!({ "content": reply }), None);
+            }
+            events.delta(serde_json::json!({}), Some("stop"));
+        }
+        Err(e) => events.send(&serde_json::json!({ "error": { "message": e.to_string(), "type": "server_error" } })),
+    }
+    events.write_raw("data: [DONE]\n\n");
+    Ok(())
+}
+
+struct EventStream {
+    out: Mutex<TcpStream>,
+    id: String,
+    model: String,
+    created: i64,
+    /// Set once the client stops reading; doubles as the run's cancel flag.
+    gone: Arc<AtomicBool>,
+    /// Text streamed since the last tool call.
+    since_tool: Mutex<String>,
+}
+
+impl EventStream {
+    fn delta(&self, delta: Value, finish_reason: Option<&str>) {
+        self.send(&serde_json::json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        }));
+    }
+
+    fn send(&self, event: &Value) {
+        self.write_raw(&format!("data: {}\n\n", event));
+    }
+
+    fn write_raw(&self, text: &str) {
+        if self.gone.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        if out.write_all(text.as_bytes()).and_then(|_| out.flush()).is_err() {
+            self.gone.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+fn list_sessions(agent: &Agent, stream: &mut TcpStream) -> Result<()> {
+    let sessions = SessionStore::new(&agent.workspace()).list()?;
+    let data: Vec<Value> = sessions.iter().map(|s| serde_json::json!({
+        "id": s.key,
+        "messages": s.messages,
+        "bytes": s.bytes,
+        "modified": s.modified,
+    })).collect();
+    write_json(stream, 200, &serde_json::json!({ "object": "list", "data": data }))
+}
+
+/// `GET` returns a session's messages, `DELETE` removes it with its archives.
+fn session(shared: &Shared, agent: &Agent, method: &str, key: &str, stream: &mut TcpStream) -> Result<()> {
+    if SessionStore::validate_key(key).is_err() {
+        return write_error(stream, 400, "invalid_request_error", "Invalid session id");
+    }
+    let store = SessionStore::new(&agent.workspace());
+    if !store.exists(key) {
+        return write_error(stream, 404, "invalid_request_error", &format!("No session '{}'", key));
+    }
+    match method {
+        "GET" => {
+            let messages = store.open(key)?.all()?;
+            write_json(stream, 200, &serde_json::json!({ "id": key, "messages": messages }))
+        }
+        "DELETE" => {
+            let lock = shared.session_lock(key);
+            let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+            let deleted = store.remove(key)?;
+            write_json(stream, 200, &serde_json::json!({ "id": key, "deleted": deleted }))
+        }
+        _ => write_error(stream, 405, "invalid_request_error", "Use GET or DELETE"),
+    }
+}
+
+/// Text of the last `user` message, whether `content` is a string or a list of parts.
+fn last_user_text(body: &Value) -> Option<String> {
+    let message = body["messages"].as_array()?.iter().rev().find(|m| m["role"] == "user")?;
+    match &message["content"] {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(parts) => Some(parts.iter()
+            .filter(|p| p["type"] == "text")
+            .filter_map(|p| p["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n")),
+        _ => None,
+    }
+}
+
+fn write_json(stream: &mut TcpStream, status: u16, body: &Value) -> Result<()> {
+    http::write_response(stream, status, "application/json", body.to_string().as_bytes())?;
+    Ok(())
+}
+
+/// Errors in the shape OpenAI clients expect.
+fn write_error(stream: &mut TcpStream, status: u16, kind: &str, message: &str) -> Result<()> {
+    write_json(stream, status, &serde_json::json!({ "error": { "message": message, "type": kind, "code": null } }))
+}
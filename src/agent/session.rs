@@ -0,0 +1,451 @@
+#![allow(dead_code)]
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use crate::agent::llm::{Completion, Message, Usage};
+
+/// Log size past which older messages are rotated out to `sessions/archive/`.
+const ROTATE_BYTES: u64 = 256 * 1024;
+/// Messages carried over into a freshly rotated log, so recent context survives.
+const CARRY_OVER: usize = 20;
+const BLOCK: usize = 4096;
+
+/// Pre-JSONL session file: `{"messages": [...]}`.
+#[derive(Deserialize)]
+struct LegacySession {
+    messages: Vec<Message>,
+}
+
+/// A message as stored in a session: the wire-format `message` plus when and how it
+/// was produced. Only `message` is ever sent to the provider.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StoredMessage {
+    /// Unix time; 0 for messages migrated from before timestamps were kept.
+    #[serde(default)]
+    pub ts: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// Provider latency for assistant messages, execution time for tool results.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    pub message: Message,
+}
+
+impl StoredMessage {
+    /// Wraps `message` stamped with the current time.
+    pub fn new(message: Message) -> Self {
+        Self {
+            ts: time::OffsetDateTime::now_utc().unix_timestamp(),
+            model: None,
+            usage: None,
+            duration_ms: None,
+            channel: None,
+            user_id: None,
+            message,
+        }
+    }
+}
+
+impl From<Message> for StoredMessage {
+    fn from(message: Message) -> Self {
+        Self { ts: 0, ..StoredMessage::new(message) }
+    }
+}
+
+impl From<Completion> for StoredMessage {
+    fn from(c: Completion) -> Self {
+        Self {
+            model: Some(c.model),
+            usage: c.usage,
+            duration_ms: Some(c.duration_ms),
+            ..StoredMessage::new(c.message)
+        }
+    }
+}
+
+/// A log line: an envelope, or a bare message written before envelopes existed.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Line {
+    Stored(StoredMessage),
+    Bare(Message),
+}
+
+/// A conversation stored as `sessions/<key>.jsonl`, one message per line. New messages
+/// are appended; recent ones are read by scanning backwards from the end of the file.
+pub struct SessionLog {
+    path: PathBuf,
+}
+
+impl SessionLog {
+    /// Opens the log for `key` in `dir`, migrating a legacy `<key>.json` on first use.
+    pub fn open(dir: &Path, key: &str) -> Result<Self> {
+        let log = Self { path: dir.join(format!("{}.jsonl", key)) };
+        let legacy = dir.join(format!("{}.json", key));
+        if legacy.exists() && !log.path.exists() {
+            log.migrate(&legacy)?;
+        }
+        Ok(log)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn migrate(&self, legacy: &Path) -> Result<()> {
+        let data = fs::read(legacy)?;
+        match serde_json::from_slice::<LegacySession>(&data) {
+            Ok(session) => {
+                let mut out = Vec::new();
+                for msg in &session.messages {
+                    serde_json::to_writer(&mut out, msg)?;
+                    out.push(b'\n');
+                }
+                crate::utils::write_atomic(&self.path, &out)?;
+                fs::remove_file(legacy)?;
+            }
+            Err(e) => {
+                let moved = crate::utils::quarantine(legacy)?;
+                eprintln!("Session {} is corrupt ({}); moved to {} and starting fresh", legacy.display(), e, moved.display());
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends `messages` in a single write, then rotates the log if it grew too large.
+    pub fn append(&self, messages: &[StoredMessage]) -> Result<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(&self.path)?;
+        let len = file.metadata()?.len();
+        let mut out = Vec::new();
+        if len > 0 {
+            file.seek(SeekFrom::Start(len - 1))?;
+            let mut last = [0u8; 1];
+            file.read_exact(&mut last)?;
+            // Terminate a line torn by an earlier crash so it stays a single bad line
+            if last[0] != b'\n' {
+                out.push(b'\n');
+            }
+        }
+        for msg in messages {
+            serde_json::to_writer(&mut out, msg)?;
+            out.push(b'\n');
+        }
+        file.write_all(&out)?;
+        file.sync_data()?;
+
+        if len + out.len() as u64 > ROTATE_BYTES {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// The last `n` messages, oldest first. Only the tail of the file is read.
+    pub fn recent(&self, n: usize) -> Result<Vec<StoredMessage>> {
+        let mut file = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut pos = file.metadata()?.len();
+        let mut tail: Vec<u8> = Vec::new();
+        let mut block = vec![0u8; BLOCK];
+
+        // Read blocks backwards until the tail holds more than `n` line breaks
+        while pos > 0 && tail.iter().filter(|b| **b == b'\n').count() <= n {
+            let size = BLOCK.min(pos as usize);
+            pos -= size as u64;
+            file.seek(SeekFrom::Start(pos))?;
+            file.read_exact(&mut block[..size])?;
+            tail.splice(0..0, block[..size].iter().copied());
+        }
+
+        let text = String::from_utf8_lossy(&tail);
+        let mut lines: Vec<&str> = text.lines().collect();
+        if pos > 0 && !lines.is_empty() {
+            // The first line may start mid-message
+            lines.remove(0);
+        }
+        let start = lines.len().saturating_sub(n);
+        Ok(lines[start..].iter().filter_map(|l| parse_line(l)).collect())
+    }
+
+    /// Every message in the current log, streamed line by line.
+    pub fn all(&self) -> Result<Vec<StoredMessage>> {
+        let file = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut messages = Vec::new();
+        for line in BufReader::new(file).lines() {
+            if let Some(msg) = parse_line(&line?) {
+                messages.push(msg);
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Moves the log to `sessions/archive/<key>-<unix time>.jsonl` and starts a new one
+    /// holding the last `CARRY_OVER` messages.
+    pub fn rotate(&self) -> Result<()> {
+        self.archive(CARRY_OVER)
+    }
+
+    /// Archives the log like `rotate`, keeping only the last `keep` messages; `0`
+    /// starts the conversation over. A log that was never written is left alone.
+    pub fn archive(&self, keep: usize) -> Result<()> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+        let recent = if keep == 0 { Vec::new() } else { self.recent(keep)? };
+        let dir = self.path.parent().unwrap_or(Path::new(".")).join("archive");
+        fs::create_dir_all(&dir)?;
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let mut target = dir.join(format!("{}-{}.jsonl", stem, now));
+        let mut n = 1;
+        while target.exists() {
+            target = dir.join(format!("{}-{}-{}.jsonl", stem, now, n));
+            n += 1;
+        }
+        fs::rename(&self.path, &target)?;
+
+        let mut out = Vec::new();
+        for msg in &recent {
+            serde_json::to_writer(&mut out, msg)?;
+            out.push(b'\n');
+        }
+        crate::utils::write_atomic(&self.path, &out)?;
+        Ok(())
+    }
+}
+
+/// Token usage summed over `messages`.
+pub fn total_usage(messages: &[StoredMessage]) -> Usage {
+    messages.iter().filter_map(|m| m.usage).fold(Usage::default(), |acc, u| Usage {
+        prompt_tokens: acc.prompt_tokens + u.prompt_tokens,
+        completion_tokens: acc.completion_tokens + u.completion_tokens,
+        total_tokens: acc.total_tokens + u.total_tokens,
+    })
+}
+
+fn parse_line(line: &str) -> Option<StoredMessage> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    match serde_json::from_str(line) {
+        Ok(Line::Stored(msg)) => Some(msg),
+        Ok(Line::Bare(msg)) => Some(msg.into()),
+        Err(e) => {
+            eprintln!("Skipping unreadable session line: {}", e);
+            None
+        }
+    }
+}
+
+/// Summary of a stored session for listings.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub key: String,
+    pub messages: usize,
+    pub bytes: u64,
+    /// Unix time of the last write.
+    pub modified: i64,
+}
+
+/// The `sessions/` directory of a workspace.
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(workspace: &Path) -> Self {
+        Self { dir: workspace.join("sessions") }
+    }
+
+    /// Session keys become file names, so they are limited to letters, digits, `-`, `_` and `.`.
+    pub fn validate_key(key: &str) -> Result<()> {
+        let ok = !key.is_empty()
+            && !key.starts_with('.')
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if ok {
+            Ok(())
+        } else {
+            Err(anyhow!("Invalid session key '{}': use letters, digits, '-', '_' or '.'", key))
+        }
+    }
+
+    pub fn open(&self, key: &str) -> Result<SessionLog> {
+        Self::validate_key(key)?;
+        SessionLog::open(&self.dir, key)
+    }
+
+    pub fn exists(&self, key: &str) -> bool {
+        self.dir.join(format!("{}.jsonl", key)).exists() || self.dir.join(format!("{}.json", key)).exists()
+    }
+
+    /// Every session, most recently used first.
+    pub fn list(&self) -> Result<Vec<SessionInfo>> {
+        let mut keys: Vec<String> = fs::read_dir(&self.dir).into_iter().flatten().flatten()
+            .filter_map(|e| {
+                let name = e.file_name().to_string_lossy().to_string();
+                name.strip_suffix(".jsonl").or_else(|| name.strip_suffix(".json")).map(|k| k.to_string())
+            })
+            .collect();
+        keys.sort();
+        keys.dedup();
+
+        let mut sessions = Vec::new();
+        for key in keys {
+            let log = match self.open(&key) {
+                Ok(log) => log,
+                Err(_) => continue,
+            };
+            let meta = match fs::metadata(log.path()) {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
+            let messages = BufReader::new(File::open(log.path())?).lines()
+                .map_while(|l| l.ok())
+                .filter(|l| !l.trim().is_empty())
+                .count();
+            let modified = meta.modified().ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            sessions.push(SessionInfo { key, messages, bytes: meta.len(), modified });
+        }
+        sessions.sort_by(|a, b| b.modified.cmp(&a.modified).then(a.key.cmp(&b.key)));
+        Ok(sessions)
+    }
+
+    /// Deletes a session and its rotated segments; returns whether it existed.
+    pub fn remove(&self, key: &str) -> Result<bool> {
+        Self::validate_key(key)?;
+        let mut found = false;
+        for ext in ["jsonl", "json"] {
+            let path = self.dir.join(format!("{}.{}", key, ext));
+            if path.exists() {
+                fs::remove_file(path)?;
+                found = true;
+            }
+        }
+        for entry in fs::read_dir(self.dir.join("archive")).into_iter().flatten().flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if archived_key(&name) == Some(key) {
+                fs::remove_file(entry.path())?;
+                found = true;
+            }
+        }
+        Ok(found)
+    }
+
+    /// Copies the current log of `from` into a new session `to`.
+    pub fn fork(&self, from: &str, to: &str) -> Result<()> {
+        Self::validate_key(to)?;
+        if !self.exists(from) {
+            return Err(anyhow!("No session '{}'", from));
+        }
+        if self.exists(to) {
+            return Err(anyhow!("Session '{}' already exists", to));
+        }
+        let source = self.open(from)?;
+        crate::utils::write_atomic(&self.dir.join(format!("{}.jsonl", to)), &fs::read(source.path())?)?;
+        Ok(())
+    }
+}
+
+/// Session key of an archived segment named `<key>-<unix time>[-n].jsonl`. The time
+/// always has ten digits and `n` at most four, so keys that extend another key's name,
+/// like `foo-1` and `-100123`, are never mistaken for a segment of `foo` or `-100`.
+pub fn archived_key(file_name: &str) -> Option<&str> {
+    let digits = |s: &str, len: std::ops::RangeInclusive<usize>| len.contains(&s.len()) && s.bytes().all(|b| b.is_ascii_digit());
+    let stem = file_name.strip_suffix(".jsonl")?;
+    let (rest, last) = stem.rsplit_once('-')?;
+    if digits(last, 1..=4) {
+        let (key, time) = rest.rsplit_once('-')?;
+        if digits(time, 10..=10) {
+            return Some(key).filter(|k| !k.is_empty());
+        }
+    }
+    Some(rest).filter(|k| !k.is_empty() && digits(last, 10..=10))
+}
+
+/// Renders a transcript as Markdown, including tool calls and their results.
+pub fn export_markdown(key: &str, messages: &[StoredMessage]) -> String {
+    let mut out = format!("# Session {}\n", key);
+    for stored in messages {
+        let msg = &stored.message;
+        match msg.role.as_str() {
+            "tool" => {
+                out.push_str(&format!(
+                    "\n**Tool result** `{}`:\n\n```\n{}\n```\n",
+                    msg.name.as_deref().unwrap_or("?"),
+                    msg.text().trim_end()
+                ));
+            }
+            role => {
+                let text = msg.text();
+                if !text.trim().is_empty() {
+                    out.push_str(&format!("\n## {}{}\n\n{}\n", capitalize(role), describe_meta(stored), text.trim_end()));
+                }
+                for call in msg.tool_calls.as_ref().and_then(|c| c.as_array()).into_iter().flatten() {
+                    let function = &call["function"];
+                    out.push_str(&format!(
+                        "\n**Tool call** `{}`:\n\n```json\n{}\n```\n",
+                        function["name"].as_str().unwrap_or("?"),
+                        function["arguments"].as_str().unwrap_or("{}")
+                    ));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Renders a transcript as a pretty-printed JSON array of envelopes, metadata included.
+pub fn export_json(messages: &[StoredMessage]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(messages)?)
+}
+
+/// ` · 2026-10-18 21:05 UTC · model · 1234 tokens · 850 ms`, skipping what is unknown.
+fn describe_meta(stored: &StoredMessage) -> String {
+    let mut parts = Vec::new();
+    if stored.ts > 0 {
+        if let Ok(t) = time::OffsetDateTime::from_unix_timestamp(stored.ts) {
+            parts.push(format!("{} UTC", crate::agent::scheduler::format_time(t)));
+        }
+    }
+    if let Some(model) = &stored.model {
+        parts.push(model.clone());
+    }
+    if let Some(usage) = &stored.usage {
+        parts.push(format!("{} tokens", usage.total_tokens));
+    }
+    if let Some(ms) = stored.duration_ms {
+        parts.push(format!("{} ms", ms));
+    }
+    parts.iter().map(|p| format!(" · {}", p)).collect()
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().collect::<String>() + chars.as_str(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,169 @@
+#![allow(dead_code)]
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a chat's worker thread lingers without work before exiting.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Agent runs are shallow; a small stack keeps many idle chats cheap.
+const WORKER_STACK: usize = 1024 * 1024;
+
+type Handler<T> = Box<dyn Fn(&str, T) + Send + Sync>;
+
+/// Runs tasks on one queue per key (e.g. per chat): tasks with the same key run in
+/// submission order, different keys run concurrently, and at most `max_concurrent`
+/// handlers run at once. A key's thread is started on demand and exits when idle.
+pub struct ChatWorkers<T: Send + 'static> {
+    inner: Arc<Inner<T>>,
+}
+
+struct Inner<T> {
+    queues: Mutex<HashMap<String, Sender<T>>>,
+    permits: Semaphore,
+    handler: Handler<T>,
+    idle: Duration,
+    /// Tasks submitted and not yet finished.
+    pending: AtomicUsize,
+}
+
+impl<T: Send + 'static> Clone for ChatWorkers<T> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone() }
+    }
+}
+
+impl<T: Send + 'static> ChatWorkers<T> {
+    pub fn new<F: Fn(&str, T) + Send + Sync + 'static>(max_concurrent: usize, handler: F) -> Self {
+        Self::with_idle_timeout(max_concurrent, IDLE_TIMEOUT, handler)
+    }
+
+    pub fn with_idle_timeout<F: Fn(&str, T) + Send + Sync + 'static>(max_concurrent: usize, idle: Duration, handler: F) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                queues: Mutex::new(HashMap::new()),
+                permits: Semaphore::new(max_concurrent.max(1)),
+                handler: Box::new(handler),
+                idle,
+                pending: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Queues `task` behind any earlier tasks for `key`.
+    pub fn submit(&self, key: &str, task: T) -> std::io::Result<()> {
+        let mut queues = lock(&self.inner.queues);
+        self.inner.pending.fetch_add(1, Ordering::SeqCst);
+        let task = match queues.get(key) {
+            Some(tx) => match tx.send(task) {
+                Ok(()) => return Ok(()),
+                // The worker is gone; start a new one below
+                Err(mpsc::SendError(task)) => task,
+            },
+            None => task,
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let inner = self.inner.clone();
+        let owned_key = key.to_string();
+        let spawned = std::thread::Builder::new()
+            .name(format!("chat-{}", key))
+            .stack_size(WORKER_STACK)
+            .spawn(move || inner.work(&owned_key, rx));
+        if let Err(e) = spawned {
+            self.inner.pending.fetch_sub(1, Ordering::SeqCst);
+            return Err(e);
+        }
+        let _ = tx.send(task);
+        queues.insert(key.to_string(), tx);
+        Ok(())
+    }
+
+    /// Tasks queued or running.
+    pub fn pending(&self) -> usize {
+        self.inner.pending.load(Ordering::SeqCst)
+    }
+
+    /// Keys with a live worker thread.
+    pub fn active_keys(&self) -> usize {
+        lock(&self.inner.queues).len()
+    }
+
+    /// Blocks until every submitted task has finished or `timeout` passes; returns
+    /// whether the queues drained.
+    pub fn wait_idle(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.pending() > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        true
+    }
+}
+
+impl<T: Send + 'static> Inner<T> {
+    fn work(&self, key: &str, rx: mpsc::Receiver<T>) {
+        loop {
+            let task = match rx.recv_timeout(self.idle) {
+                Ok(task) => task,
+                Err(RecvTimeoutError::Timeout) => {
+                    // Checked under the map lock so a concurrent submit can't slip a
+                    // task into a queue nobody will read
+                    let mut queues = lock(&self.queues);
+                    match rx.try_recv() {
+                        Ok(task) => task,
+                        Err(_) => {
+                            queues.remove(key);
+                            return;
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+
+            let _permit = self.permits.acquire();
+            // A panicking run must not take the chat's queue down with it
+            let run = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| (self.handler)(key, task)));
+            if run.is_err() {
+                eprintln!("Worker for {} panicked; continuing with its next task", key);
+            }
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+struct Semaphore {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+struct Permit<'a>(&'a Semaphore);
+
+impl Semaphore {
+    fn new(permits: usize) -> Self {
+        Self { available: Mutex::new(permits), released: Condvar::new() }
+    }
+
+    fn acquire(&self) -> Permit<'_> {
+        let mut available = lock(&self.available);
+        while *available == 0 {
+            available = self.released.wait(available).unwrap_or_else(|e| e.into_inner());
+        }
+        *available -= 1;
+        Permit(self)
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *lock(&self.0.available) += 1;
+        self.0.released.notify_one();
+    }
+}
+
+fn lock<T>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
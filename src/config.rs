@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use anyhow::Result;
@@ -11,11 +12,33 @@ pub struct Config {
     pub tools: ToolsConfig,
     #[serde(default)]
     pub channels: ChannelsConfig,
+    #[serde(default)]
+    pub server: Option<ServerConfig>,
+}
+
+/// The local HTTP API started by `--serve`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServerConfig {
+    #[serde(default = "default_server_listen")]
+    pub listen: String,
+    /// Clients must send `Authorization: Bearer <token>`.
+    pub token: String,
+    #[serde(default)]
+    pub tool_profile: Option<String>,
+    /// Requests handled at once; further ones are refused with 429.
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent: usize,
+}
+
+fn default_server_listen() -> String {
+    "127.0.0.1:8080".to_string()
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ChannelsConfig {
     pub telegram: Option<TelegramConfig>,
+    #[serde(default)]
+    pub matrix: Option<MatrixConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -23,6 +46,72 @@ pub struct TelegramConfig {
     pub enabled: bool,
     pub token: String,
     pub allow_from: Vec<String>,
+    /// Tool profile applied to every chat handled by the bot: `readonly` unless set,
+    /// `full` gives allowed users every tool.
+    #[serde(default = "default_channel_tool_profile")]
+    pub tool_profile: Option<String>,
+    /// Chats whose agent runs may execute at the same time; further chats wait their turn.
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent: usize,
+    /// Receive updates over HTTP instead of long polling.
+    #[serde(default)]
+    pub webhook: Option<WebhookConfig>,
+    /// Shell command that prints a transcript of a voice note; `{file}` is replaced by
+    /// the downloaded `.ogg` path. Voice notes are refused when unset.
+    #[serde(default)]
+    pub transcribe: Option<String>,
+}
+
+/// Telegram only calls HTTPS URLs, so `url` normally points at a reverse proxy that
+/// terminates TLS and forwards to `listen`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookConfig {
+    /// Public URL registered with Telegram, e.g. `https://bot.example.com/telegram`.
+    pub url: String,
+    /// Local address the listener binds to.
+    #[serde(default = "default_webhook_listen")]
+    pub listen: String,
+    /// Request path updates are accepted on.
+    #[serde(default = "default_webhook_path")]
+    pub path: String,
+    /// Expected `X-Telegram-Bot-Api-Secret-Token`; a random one is used per start if unset.
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+fn default_webhook_listen() -> String {
+    "127.0.0.1:8443".to_string()
+}
+
+fn default_webhook_path() -> String {
+    "/telegram".to_string()
+}
+
+/// A Matrix account the bot logs in as, through the client-server API. Encrypted rooms
+/// are not supported.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MatrixConfig {
+    pub enabled: bool,
+    /// Base URL of the homeserver, e.g. `https://matrix.example.org`.
+    pub homeserver: String,
+    pub access_token: String,
+    /// User ids such as `@alice:example.org`, or room ids. The channel refuses to start
+    /// without any, since anyone on the federation could invite the bot.
+    #[serde(default)]
+    pub allow_from: Vec<String>,
+    /// `readonly` unless set; `full` gives allowed users every tool.
+    #[serde(default = "default_channel_tool_profile")]
+    pub tool_profile: Option<String>,
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent: usize,
+}
+
+fn default_channel_tool_profile() -> Option<String> {
+    Some("readonly".to_string())
+}
+
+fn default_max_concurrent() -> usize {
+    4
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -37,6 +126,53 @@ pub struct AgentDefaults {
     pub max_tokens: usize,
     pub temperature: f32,
     pub max_tool_iterations: usize,
+    /// Whether the model accepts image content parts.
+    #[serde(default)]
+    pub vision: bool,
+    /// Maximum bytes of memory (MEMORY.md + recent notes) injected per request.
+    #[serde(default = "default_memory_budget")]
+    pub memory_budget: usize,
+    /// `UTC`, `local` or a fixed offset like `-03:00`, for memory dates, timestamps and schedules.
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// How memory is partitioned between users and chats.
+    #[serde(default)]
+    pub memory_scope: MemoryScope,
+    /// Stream replies from the provider so channels can show text as it arrives.
+    #[serde(default)]
+    pub stream: bool,
+    /// Periodic consolidation of finished daily notes in bot mode.
+    #[serde(default)]
+    pub consolidation: Option<ConsolidationConfig>,
+}
+
+/// `global` shares one memory between everyone; `user` gives each channel user a private
+/// memory (chats without a user fall back to their session); `session` isolates every
+/// session. Non-global scopes still read the workspace MEMORY.md as a shared layer.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryScope {
+    Global,
+    #[default]
+    User,
+    Session,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConsolidationConfig {
+    /// 5-field cron expression in `timezone`, e.g. `"30 3 * * *"`.
+    pub cron: String,
+    /// Gzip each day's raw note once it has a digest.
+    #[serde(default)]
+    pub archive: bool,
+}
+
+fn default_timezone() -> String {
+    "local".to_string()
+}
+
+fn default_memory_budget() -> usize {
+    crate::agent::memory::DEFAULT_MEMORY_BUDGET
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -57,6 +193,15 @@ pub struct ProviderConfig {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ToolsConfig {
     pub web: WebToolsConfig,
+    /// Global allow-list of tool names. `None` enables every registered tool.
+    #[serde(default)]
+    pub enabled: Option<Vec<String>>,
+    /// Named tool lists, e.g. `"readonly": ["read_file", "list_dir"]`. `"*"` matches every tool.
+    #[serde(default)]
+    pub profiles: HashMap<String, Vec<String>>,
+    /// Per-session profile overrides, keyed by session key.
+    #[serde(default)]
+    pub sessions: HashMap<String, String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -86,4 +231,13 @@ impl Config {
         }
         PathBuf::from(path)
     }
+
+    /// The configured timezone as an offset. `local` must be resolved while the process is
+    /// still single-threaded, so call this at startup; an invalid value falls back to UTC.
+    pub fn utc_offset(&self) -> time::UtcOffset {
+        crate::utils::parse_utc_offset(&self.agents.defaults.timezone).unwrap_or_else(|e| {
+            eprintln!("{}; using UTC", e);
+            time::UtcOffset::UTC
+        })
+    }
 }
@@ -1,8 +1,5 @@
-#[allow(dead_code)]
 pub mod config;
-#[allow(dead_code)]
 pub mod agent;
-#[allow(dead_code)]
 pub mod tools;
 #[allow(dead_code)]
 pub mod utils;
@@ -1,15 +1,17 @@
-mod config;
-mod agent;
-mod tools;
-mod utils;
-
 use anyhow::{Result, anyhow};
 use redclaw::config::Config;
 use redclaw::agent::Agent;
+use redclaw::agent::consolidate::ConsolidateOptions;
 use redclaw::agent::llm::LLMClient;
-use redclaw::agent::channels::TelegramBot;
+use redclaw::agent::channels::{self, Channel, MatrixChannel, TelegramBot};
 use redclaw::tools::registry::ToolRegistry;
+use redclaw::agent::scheduler::Scheduler;
+use redclaw::agent::server::ApiServer;
 use redclaw::tools::builtin::{ReadFileTool, WriteFileTool, ListDirTool, ExecTool, WebSearchTool, WebFetchTool, SysInfoTool};
+use redclaw::tools::schedule::ScheduleTool;
+use redclaw::tools::memory::{RememberTool, ForgetTool, RecallTool, SearchMemoryTool};
+use redclaw::agent::memory::MemoryStore;
+use redclaw::agent::session::{SessionStore, export_json, export_markdown};
 use std::env;
 
 struct Args {
@@ -17,7 +19,16 @@ struct Args {
     config: String,
     interactive: bool,
     telegram: bool,
+    channels: bool,
+    serve: bool,
     onboard: bool,
+    memory: Option<String>,
+    dry_run: bool,
+    archive: bool,
+    sessions: Option<Vec<String>>,
+    session: Option<String>,
+    format: String,
+    output: Option<String>,
 }
 
 fn parse_args() -> Args {
@@ -27,12 +38,39 @@ fn parse_args() -> Args {
         config: "config.json".to_string(),
         interactive: false,
         telegram: false,
+        channels: false,
+        serve: false,
         onboard: false,
+        memory: None,
+        dry_run: false,
+        archive: false,
+        sessions: None,
+        session: None,
+        format: "md".to_string(),
+        output: None,
     };
 
     while let Some(arg) = args.next() {
+        // After `sessions` every word is a key or subcommand, even `onboard` or `-s`
+        if let Some(rest) = parsed.sessions.as_mut() {
+            match arg.as_str() {
+                "--format" => parsed.format = args.next().unwrap_or_default(),
+                "-o" | "--output" => parsed.output = args.next(),
+                // Everything after `--` is a key, even `--format` or `-o`
+                "--" => rest.extend(args.by_ref()),
+                _ => rest.push(arg),
+            }
+            continue;
+        }
         match arg.as_str() {
             "onboard" | "--onboard" => parsed.onboard = true,
+            "memory" => parsed.memory = Some(args.next().unwrap_or_default()),
+            "--dry-run" => parsed.dry_run = true,
+            "--archive" => parsed.archive = true,
+            "sessions" => parsed.sessions = Some(Vec::new()),
+            "-s" | "--session" => parsed.session = args.next(),
+            "--format" => parsed.format = args.next().unwrap_or_default(),
+            "-o" | "--output" => parsed.output = args.next(),
             "-m" | "--message" => parsed.message = args.next(),
             "-c" | "--config" => {
                 if let Some(c) = args.next() {
@@ -41,24 +79,40 @@ fn parse_args() -> Args {
             }
             "-i" | "--interactive" => parsed.interactive = true,
             "-t" | "--telegram" => parsed.telegram = true,
+            "--channels" => parsed.channels = true,
+            "--serve" => parsed.serve = true,
             "-h" | "--help" => {
                 println!("RedClaw 🦀 - Ultra-lightweight AI Agent (<2MB RAM)");
-                println!("");
+                println!();
                 println!("Usage: redclaw [COMMAND] [OPTIONS]");
-                println!("");
+                println!();
                 println!("Commands:");
                 println!("  onboard              Start interactive configuration wizard");
-                println!("");
+                println!("  memory consolidate   Distill finished daily notes into digests and MEMORY.md");
+                println!("                       [--dry-run] print proposed changes only");
+                println!("                       [--archive] gzip each consolidated day's raw note");
+                println!("  sessions list        List stored conversations");
+                println!("  sessions show <KEY>  Print the last messages of a session");
+                println!("  sessions export <KEY> [--format md|json] [-o FILE]");
+                println!("  sessions rm <KEY>    Delete a session and its archived segments");
+                println!("  sessions fork <KEY> <NEW>  Copy a session under a new key");
+                println!("                       Other options go before `sessions`; put a key after --");
+                println!("                       if it is --format or -o");
+                println!();
                 println!("Options:");
                 println!("  -m, --message <MSG>  Send a single message to the agent and exit");
                 println!("  -c, --config <PATH>  Path to config.json (default: config.json)");
                 println!("  -i, --interactive    Start an interactive session in the terminal");
                 println!("  -t, --telegram       Run in Telegram Bot mode");
+                println!("      --channels       Run every enabled channel (Telegram, Matrix) at once");
+                println!("      --serve          Serve the OpenAI-compatible HTTP API from the server config");
+                println!("  -s, --session <KEY>  Session to use with -m or -i (default: default)");
                 println!("  -h, --help           Display this help message");
-                println!("");
+                println!();
                 println!("Examples:");
                 println!("  ./redclaw onboard");
                 println!("  ./redclaw -m \"Hello!\"");
+                println!("  ./redclaw memory consolidate --dry-run");
                 std::process::exit(0);
             }
             _ => {
@@ -97,7 +151,10 @@ fn run_onboard() -> Result<()> {
                 "model": model,
                 "max_tokens": 4096,
                 "temperature": 0.7,
-                "max_tool_iterations": 10
+                "max_tool_iterations": 10,
+                "memory_budget": 8192,
+                "memory_scope": "user",
+                "timezone": "local"
             }
         },
         "providers": {
@@ -126,7 +183,8 @@ fn run_onboard() -> Result<()> {
             "telegram": {
                 "enabled": !tg_token.is_empty(),
                 "token": tg_token,
-                "allow_from": if tg_user == "your_id" { vec![] } else { vec![tg_user] }
+                "allow_from": if tg_user == "your_id" { vec![] } else { vec![tg_user] },
+                "tool_profile": "readonly"
             }
         }
     });
@@ -139,7 +197,117 @@ fn run_onboard() -> Result<()> {
     Ok(())
 }
 
+fn run_sessions(config: &Config, args: &[String], format: &str, output: Option<&str>) -> Result<()> {
+    let store = SessionStore::new(&config.workspace_path());
+    let arg = |i: usize| args.get(i).map(|s| s.as_str()).ok_or_else(|| anyhow!("Missing session key. Use --help for usage."));
+    match args.first().map(|s| s.as_str()).unwrap_or("list") {
+        "list" => print_sessions(&store, None)?,
+        "show" => {
+            let key = arg(1)?;
+            if !store.exists(key) {
+                return Err(anyhow!("No session '{}'", key));
+            }
+            for stored in store.open(key)?.recent(20)? {
+                let msg = &stored.message;
+                let text = msg.text();
+                let line = match (msg.role.as_str(), &msg.tool_calls) {
+                    ("tool", _) => format!("  [{} result] {}", msg.name.as_deref().unwrap_or("tool"), first_line(&text)),
+                    (_, Some(_)) if text.is_empty() => format!("{}: (tool call)", msg.role),
+                    (role, _) => format!("{}: {}", role, text),
+                };
+                println!("{}", line);
+            }
+        }
+        "export" => {
+            let key = arg(1)?;
+            if !store.exists(key) {
+                return Err(anyhow!("No session '{}'", key));
+            }
+            let messages = store.open(key)?.all()?;
+            let rendered = match format {
+                "md" | "markdown" => export_markdown(key, &messages),
+                "json" => export_json(&messages)?,
+                other => return Err(anyhow!("Unknown export format '{}'. Use md or json", other)),
+            };
+            match output {
+                Some(path) => {
+                    std::fs::write(path, rendered)?;
+                    println!("Exported {} messages to {}", messages.len(), path);
+                }
+                None => println!("{}", rendered.trim_end()),
+            }
+        }
+        "rm" => {
+            let key = arg(1)?;
+            if !store.remove(key)? {
+                return Err(anyhow!("No session '{}'", key));
+            }
+            println!("Removed session {}", key);
+        }
+        "fork" => {
+            let (from, to) = (arg(1)?, arg(2)?);
+            store.fork(from, to)?;
+            println!("Forked {} into {}", from, to);
+        }
+        other => return Err(anyhow!("Unknown sessions command '{}'. Use --help for usage.", other)),
+    }
+    Ok(())
+}
+
+fn print_sessions(store: &SessionStore, current: Option<&str>) -> Result<()> {
+    let sessions = store.list()?;
+    if sessions.is_empty() {
+        println!("No sessions yet.");
+    }
+    for s in sessions {
+        let when = time::OffsetDateTime::from_unix_timestamp(s.modified)
+            .map(redclaw::agent::scheduler::format_time)
+            .unwrap_or_default();
+        let marker = if current == Some(s.key.as_str()) { "*" } else { " " };
+        println!("{} {:<24} {:>5} msgs {:>8} bytes  {}", marker, s.key, s.messages, s.bytes, when);
+    }
+    Ok(())
+}
+
+fn first_line(text: &str) -> String {
+    let line = text.lines().next().unwrap_or("");
+    if line.chars().count() > 100 {
+        format!("{}...", line.chars().take(100).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+fn run_consolidate(agent: &Agent, dry_run: bool, archive: bool) -> Result<()> {
+    let results = agent.consolidate_memory(&ConsolidateOptions { dry_run, archive })?;
+    if results.is_empty() {
+        println!("Nothing to consolidate.");
+        return Ok(());
+    }
+    for result in &results {
+        let scope = result.scope.as_deref().unwrap_or("global");
+        for day in &result.days {
+            redclaw::utils::print_box_top(&format!("Digest {} [{}]", day.date, scope));
+            redclaw::utils::print_box_line(&day.digest);
+            redclaw::utils::print_box_bottom();
+        }
+        let diff = result.memory_diff();
+        if diff.is_empty() {
+            println!("MEMORY.md [{}]: no changes", scope);
+        } else {
+            println!("MEMORY.md [{}]{}:", scope, if dry_run { " (proposed)" } else { "" });
+            println!("{}", diff);
+        }
+    }
+    if dry_run {
+        println!("\nDry run: nothing was written.");
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
+    // Must happen while the process is still single-threaded
+    redclaw::utils::local_offset();
     let args = parse_args();
 
     if args.onboard {
@@ -149,6 +317,10 @@ fn main() -> Result<()> {
     let config = Config::load(&args.config)
         .map_err(|e| anyhow!("Failed to load config from {}: {}", args.config, e))?;
 
+    if let Some(cmd) = &args.sessions {
+        return run_sessions(&config, cmd, &args.format, args.output.as_deref());
+    }
+
     // Priority: OpenRouter -> OpenAI -> Gemini -> Others
     let provider_config = config.providers.openrouter.as_ref()
         .or(config.providers.openai.as_ref())
@@ -186,26 +358,86 @@ fn main() -> Result<()> {
     }));
     registry.register(Box::new(WebFetchTool));
     registry.register(Box::new(SysInfoTool));
+    registry.register(Box::new(ScheduleTool {
+        scheduler: Scheduler::new(&config.workspace_path()),
+        default_timezone: config.agents.defaults.timezone.clone(),
+    }));
+    let memory = MemoryStore::new(&config.workspace_path()).with_offset(config.utc_offset());
+    registry.register(Box::new(RememberTool { memory: memory.clone() }));
+    registry.register(Box::new(ForgetTool { memory: memory.clone() }));
+    registry.register(Box::new(RecallTool { memory: memory.clone() }));
+    registry.register(Box::new(SearchMemoryTool { memory }));
 
     let mut agent = Agent::new(&config, client, registry);
+    if let Some(key) = &args.session {
+        SessionStore::validate_key(key)?;
+        agent.set_session(key);
+    }
+
+    if let Some(cmd) = args.memory {
+        return match cmd.as_str() {
+            "consolidate" => run_consolidate(&agent, args.dry_run, args.archive),
+            other => Err(anyhow!("Unknown memory command '{}'. Use: memory consolidate", other)),
+        };
+    }
 
-    if args.telegram {
-        let tg_cfg = config.channels.telegram.as_ref()
-            .ok_or_else(|| anyhow!("Telegram not configured in {}", args.config))?;
-        if !tg_cfg.enabled {
-            return Err(anyhow!("Telegram is disabled in config"));
+    if args.serve {
+        let server_cfg = config.server.as_ref()
+            .ok_or_else(|| anyhow!("No server section in {}", args.config))?;
+        let server = ApiServer::bind(&server_cfg.listen, &server_cfg.token)?
+            .with_tool_profile(server_cfg.tool_profile.clone())
+            .with_max_concurrent(server_cfg.max_concurrent);
+        redclaw::utils::install_shutdown_handler()?;
+        server.run(agent)?;
+    } else if args.telegram || args.channels {
+        let workspace = config.workspace_path();
+        let mut enabled: Vec<Box<dyn Channel>> = Vec::new();
+        match config.channels.telegram.as_ref() {
+            Some(tg_cfg) if tg_cfg.enabled => enabled.push(Box::new(
+                TelegramBot::new(tg_cfg.token.clone(), tg_cfg.allow_from.clone())
+                    .with_tool_profile(tg_cfg.tool_profile.clone())
+                    .with_scheduler(Scheduler::new(&workspace))
+                    .with_max_concurrent(tg_cfg.max_concurrent)
+                    .with_webhook(tg_cfg.webhook.clone())
+                    .with_transcriber(tg_cfg.transcribe.clone())
+                    .with_offset_file(workspace.join("telegram_offset.json")),
+            )),
+            Some(_) if args.telegram => return Err(anyhow!("Telegram is disabled in config")),
+            None if args.telegram => return Err(anyhow!("Telegram not configured in {}", args.config)),
+            _ => {}
         }
-        let bot = TelegramBot::new(tg_cfg.token.clone(), tg_cfg.allow_from.clone());
-        bot.run(&mut agent)?;
+        if args.channels {
+            if let Some(mx_cfg) = config.channels.matrix.as_ref().filter(|c| c.enabled) {
+                enabled.push(Box::new(
+                    MatrixChannel::new(&mx_cfg.homeserver, &mx_cfg.access_token, mx_cfg.allow_from.clone())
+                        .with_tool_profile(mx_cfg.tool_profile.clone())
+                        .with_scheduler(Scheduler::new(&workspace))
+                        .with_max_concurrent(mx_cfg.max_concurrent)
+                        .with_state_file(workspace.join("matrix_sync.json")),
+                ));
+            }
+        }
+        let first = enabled.first().ok_or_else(|| anyhow!("No channel is enabled in {}", args.config))?;
+        // Memory consolidation runs on the first channel's schedule
+        let consolidation = config.agents.defaults.consolidation.as_ref();
+        Scheduler::new(&workspace).sync_consolidation(
+            first.name(),
+            consolidation.map(|c| c.cron.as_str()),
+            consolidation.map(|c| c.archive).unwrap_or(false),
+            &config.agents.defaults.timezone,
+        )?;
+        redclaw::utils::install_shutdown_handler()?;
+        channels::run_all(enabled, agent)?;
         } else if let Some(msg) = args.message {
             let response = agent.run(&msg)?;
-            crate::utils::print_box_top("Claw");
-            crate::utils::print_box_line(&response);
-            crate::utils::print_box_bottom();
+            redclaw::utils::print_box_top("Claw");
+            redclaw::utils::print_box_line(&response);
+            redclaw::utils::print_box_bottom();
         } else if args.interactive {
     
             use std::io::{self, Write};
-            println!("RedClaw Interactive Mode");
+            println!("RedClaw Interactive Mode (session: {})", agent.session_key());
+            println!("Commands: /new [key], /switch <key>, /sessions, /memory, exit");
             loop {
                 print!("╭─ Input: ");
                 io::stdout().flush()?;
@@ -214,19 +446,46 @@ fn main() -> Result<()> {
                 let input = input.trim();
                 if input.is_empty() { continue; }
                 if input == "exit" || input == "quit" { break; }
+                if input == "/memory" {
+                    redclaw::utils::print_box_line(&agent.memory_stats().summary());
+                    continue;
+                }
+                if input == "/sessions" {
+                    print_sessions(&SessionStore::new(&config.workspace_path()), Some(agent.session_key()))?;
+                    continue;
+                }
+                if input == "/new" || input.starts_with("/new ") || input.starts_with("/switch ") {
+                    let (cmd, key) = input.split_once(' ').unwrap_or((input, ""));
+                    let key = match key.trim() {
+                        "" if cmd == "/new" => format!("cli-{}", time::OffsetDateTime::now_utc().unix_timestamp()),
+                        k => k.to_string(),
+                    };
+                    let store = SessionStore::new(&config.workspace_path());
+                    if let Err(e) = SessionStore::validate_key(&key) {
+                        println!("{}", e);
+                    } else if cmd == "/new" && store.exists(&key) {
+                        println!("Session '{}' already exists; use /switch {}", key, key);
+                    } else if cmd == "/switch" && !store.exists(&key) {
+                        println!("No session '{}'; use /new {} to start it", key, key);
+                    } else {
+                        agent.set_session(&key);
+                        println!("Session: {}", key);
+                    }
+                    continue;
+                }
                 
             match agent.run(input) {
                 Ok(response) => {
                     println!("\n  Claw:");
-                    crate::utils::print_box_line(&response);
-                    crate::utils::print_box_bottom();
-                    println!("");
+                    redclaw::utils::print_box_line(&response);
+                    redclaw::utils::print_box_bottom();
+                    println!();
                 },
                 Err(e) => {
                     println!("\n  Error:");
-                    crate::utils::print_box_line(&format!("{}", e));
-                    crate::utils::print_box_bottom();
-                    println!("");
+                    redclaw::utils::print_box_line(&format!("{}", e));
+                    redclaw::utils::print_box_bottom();
+                    println!();
                 }
             }
             }
@@ -5,7 +5,7 @@ use std::fs::{self, File};
 use std::io::{Read, BufReader};
 use std::path::Path;
 use std::process::Command;
-use crate::tools::Tool;
+use crate::tools::{Tool, ToolContext, ToolOutput};
 
 pub struct ReadFileTool;
 impl Tool for ReadFileTool {
@@ -20,8 +20,9 @@ impl Tool for ReadFileTool {
             "required": ["path"]
         })
     }
-    fn execute(&self, args: Value) -> Result<String> {
+    fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolOutput> {
         let path = args["path"].as_str().ok_or_else(|| anyhow::anyhow!("path is required"))?;
+        ctx.check_path(Path::new(path))?;
         let metadata = fs::metadata(path)?;
         let file_size = metadata.len();
         
@@ -35,10 +36,10 @@ impl Tool for ReadFileTool {
             reader.by_ref().take(limit).read_to_end(&mut buffer)?;
             let mut content = String::from_utf8_lossy(&buffer).to_string();
             content.push_str("\n... (truncated: file exceeds 256KB safety limit)");
-            Ok(content)
+            Ok(content.into())
         } else {
             reader.read_to_end(&mut buffer)?;
-            Ok(String::from_utf8_lossy(&buffer).to_string())
+            Ok(String::from_utf8_lossy(&buffer).to_string().into())
         }
     }
 }
@@ -57,14 +58,15 @@ impl Tool for WriteFileTool {
             "required": ["path", "content"]
         })
     }
-    fn execute(&self, args: Value) -> Result<String> {
+    fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolOutput> {
         let path = args["path"].as_str().ok_or_else(|| anyhow::anyhow!("path is required"))?;
         let content = args["content"].as_str().ok_or_else(|| anyhow::anyhow!("content is required"))?;
+        ctx.check_path(Path::new(path))?;
         if let Some(parent) = Path::new(path).parent() {
             fs::create_dir_all(parent)?;
         }
         fs::write(path, content)?;
-        Ok("File written successfully".to_string())
+        Ok(ToolOutput::text("File written successfully"))
     }
 }
 
@@ -81,8 +83,9 @@ impl Tool for ListDirTool {
             "required": ["path"]
         })
     }
-    fn execute(&self, args: Value) -> Result<String> {
+    fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolOutput> {
         let path = args["path"].as_str().unwrap_or(".");
+        ctx.check_path(Path::new(path))?;
         let entries = fs::read_dir(path)?;
         let mut result = String::new();
         for entry in entries {
@@ -96,7 +99,7 @@ impl Tool for ListDirTool {
             result.push_str(&entry.file_name().to_string_lossy());
             result.push('\n');
         }
-        Ok(result)
+        Ok(result.into())
     }
 }
 
@@ -115,7 +118,7 @@ impl Tool for ExecTool {
             "required": ["command"]
         })
     }
-    fn execute(&self, args: Value) -> Result<String> {
+    fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<ToolOutput> {
         let command = args["command"].as_str().ok_or_else(|| anyhow::anyhow!("command is required"))?;
         
         // Command Guard: Security Blacklist
@@ -127,7 +130,7 @@ impl Tool for ExecTool {
         let cmd_lower = command.to_lowercase();
         for pattern in dangerous_patterns {
             if cmd_lower.contains(pattern) {
-                return Ok(format!("Security Error: Command blocked. Dangerous pattern '{}' detected.", pattern));
+                return Ok(ToolOutput::error(format!("Security Error: Command blocked. Dangerous pattern '{}' detected.", pattern)));
             }
         }
 
@@ -151,7 +154,11 @@ impl Tool for ExecTool {
         if result.is_empty() {
             result = "(no output)".to_string();
         }
-        Ok(result)
+        if !output.status.success() {
+            let code = output.status.code().map(|c| c.to_string()).unwrap_or_else(|| "signal".to_string());
+            return Ok(ToolOutput::error(format!("Command exited with status {}\n{}", code, result)));
+        }
+        Ok(result.into())
     }
 }
 
@@ -172,9 +179,9 @@ impl Tool for WebSearchTool {
             "required": ["query"]
         })
     }
-    fn execute(&self, args: Value) -> Result<String> {
+    fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<ToolOutput> {
         if self.api_key.is_empty() {
-            return Ok("Error: Brave API key not configured".to_string());
+            return Ok(ToolOutput::error("Brave API key not configured"));
         }
         let query = args["query"].as_str().ok_or_else(|| anyhow::anyhow!("query is required"))?;
         let count = args["count"].as_u64().unwrap_or(self.max_results as u64) as usize;
@@ -200,7 +207,7 @@ impl Tool for WebSearchTool {
                 res["description"].as_str().unwrap_or("")
             ));
         }
-        Ok(output)
+        Ok(output.into())
     }
 }
 
@@ -217,7 +224,7 @@ impl Tool for WebFetchTool {
             "required": ["url"]
         })
     }
-    fn execute(&self, args: Value) -> Result<String> {
+    fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<ToolOutput> {
         let url = args["url"].as_str().ok_or_else(|| anyhow::anyhow!("url is required"))?;
         let output = Command::new("curl")
             .arg("-s")
@@ -228,16 +235,16 @@ impl Tool for WebFetchTool {
         let text = String::from_utf8_lossy(&output.stdout).to_string();
         
         let text = text.split('<')
-            .map(|s| s.split('>').last().unwrap_or(""))
+            .map(|s| s.split('>').next_back().unwrap_or(""))
             .collect::<Vec<_>>()
             .join(" ");
         
         let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
         let limit = 5000;
         if text.len() > limit {
-            Ok(format!("{}... (truncated)", &text[..limit]))
+            Ok(format!("{}... (truncated)", &text[..limit]).into())
         } else {
-            Ok(text)
+            Ok(text.into())
         }
     }
 }
@@ -247,7 +254,7 @@ impl Tool for SysInfoTool {
     fn name(&self) -> &str { "get_sys_info" }
     fn description(&self) -> &str { "Get real-time system and process memory info (RSS)" }
     fn parameters(&self) -> Value { json!({}) }
-    fn execute(&self, _args: Value) -> Result<String> {
+    fn execute(&self, _args: Value, _ctx: &ToolContext) -> Result<ToolOutput> {
         let statm = fs::read_to_string("/proc/self/statm")?;
         let parts: Vec<&str> = statm.split_whitespace().collect();
         let pages: u64 = parts[1].parse().unwrap_or(0);
@@ -271,6 +278,6 @@ impl Tool for SysInfoTool {
             let free = os_info.lines().find(|l| l.starts_with("MemAvailable")).unwrap_or("");
             output.push_str(&format!("- System Info: {}\n", free));
         }
-        Ok(output)
+        Ok(output.into())
     }
 }
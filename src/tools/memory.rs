@@ -0,0 +1,120 @@
+#![allow(dead_code)]
+use anyhow::{Result, anyhow};
+use serde_json::{json, Value};
+use crate::agent::memory::{MemoryStore, Remembered};
+use crate::tools::{Tool, ToolContext, ToolOutput};
+
+pub struct RememberTool {
+    pub memory: MemoryStore,
+}
+impl Tool for RememberTool {
+    fn name(&self) -> &str { "remember" }
+    fn description(&self) -> &str {
+        "Save a lasting fact to long-term memory (MEMORY.md) under a short key. \
+         Reusing a key updates the fact. Use for preferences, names, ongoing projects; not for chit-chat."
+    }
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "key": { "type": "string", "minLength": 1, "description": "Short unique key, e.g. 'preferred language'" },
+                "value": { "type": "string", "minLength": 1, "description": "The fact to remember" },
+                "section": { "type": "string", "description": "Section heading, e.g. 'User', 'Projects' (default: General)" }
+            },
+            "required": ["key", "value"]
+        })
+    }
+    fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolOutput> {
+        let key = args["key"].as_str().ok_or_else(|| anyhow!("key is required"))?;
+        let value = args["value"].as_str().ok_or_else(|| anyhow!("value is required"))?;
+        let outcome = self.memory.for_scope(ctx.memory_scope.as_deref()).remember(args["section"].as_str(), key, value)?;
+        let verb = match outcome {
+            Remembered::Added => "Remembered",
+            Remembered::Updated => "Updated",
+            Remembered::Unchanged => "Already known",
+        };
+        Ok(ToolOutput::text(format!("{}: {}", verb, key)))
+    }
+}
+
+pub struct ForgetTool {
+    pub memory: MemoryStore,
+}
+impl Tool for ForgetTool {
+    fn name(&self) -> &str { "forget" }
+    fn description(&self) -> &str { "Remove a fact from long-term memory by its key" }
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "key": { "type": "string", "minLength": 1, "description": "Key of the fact to remove" }
+            },
+            "required": ["key"]
+        })
+    }
+    fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolOutput> {
+        let key = args["key"].as_str().ok_or_else(|| anyhow!("key is required"))?;
+        if self.memory.for_scope(ctx.memory_scope.as_deref()).forget(key)? {
+            Ok(ToolOutput::text(format!("Forgot: {}", key)))
+        } else {
+            Ok(ToolOutput::error(format!("No memory with key '{}'", key)))
+        }
+    }
+}
+
+pub struct RecallTool {
+    pub memory: MemoryStore,
+}
+impl Tool for RecallTool {
+    fn name(&self) -> &str { "recall" }
+    fn description(&self) -> &str { "Look up facts in long-term memory. Omit the query to list everything." }
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "Words to match against section, key and value" }
+            }
+        })
+    }
+    fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolOutput> {
+        let entries = self.memory.for_scope(ctx.memory_scope.as_deref()).recall(args["query"].as_str().unwrap_or(""));
+        if entries.is_empty() {
+            return Ok(ToolOutput::text("No matching memories."));
+        }
+        let lines: Vec<String> = entries.iter()
+            .map(|e| format!("[{}] {}: {}", e.section, e.key, e.value))
+            .collect();
+        Ok(ToolOutput::text(lines.join("\n")))
+    }
+}
+
+pub struct SearchMemoryTool {
+    pub memory: MemoryStore,
+}
+impl Tool for SearchMemoryTool {
+    fn name(&self) -> &str { "search_memory" }
+    fn description(&self) -> &str {
+        "Keyword search over all past daily notes (conversation history), including days no longer in context. \
+         Returns the best matching excerpts with their dates."
+    }
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "minLength": 1, "description": "Keywords to look for" },
+                "limit": { "type": "integer", "minimum": 1, "maximum": 10, "description": "Number of results (default 3)" }
+            },
+            "required": ["query"]
+        })
+    }
+    fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolOutput> {
+        let query = args["query"].as_str().ok_or_else(|| anyhow!("query is required"))?;
+        let limit = args["limit"].as_u64().unwrap_or(3) as usize;
+        let hits = self.memory.for_scope(ctx.memory_scope.as_deref()).search(query, limit)?;
+        if hits.is_empty() {
+            return Ok(ToolOutput::text(format!("No notes mention '{}'.", query)));
+        }
+        let results: Vec<String> = hits.iter().map(|h| format!("## {}\n{}", h.date, h.snippet)).collect();
+        Ok(ToolOutput::text(results.join("\n\n")))
+    }
+}
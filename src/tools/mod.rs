@@ -1,16 +1,266 @@
 #![allow(dead_code)]
 pub mod registry;
 pub mod builtin;
+pub mod schema;
+pub mod schedule;
+pub mod memory;
 
 use anyhow::Result;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::config::ToolsConfig;
+use crate::agent::llm::{ContentPart, ImageUrl};
 
-pub trait Tool {
+/// Tools are shared by every concurrent agent run, so they must be thread-safe.
+pub trait Tool: Send + Sync {
     fn name(&self) -> &str;
     fn description(&self) -> &str;
     fn parameters(&self) -> Value;
-    fn execute(&self, args: Value) -> Result<String>;
+    fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolOutput>;
 }
 
-pub type ToolBox = HashMap<String, Box<dyn Tool>>;
+/// Where a tool call comes from. Tools acting on behalf of a conversation
+/// (scheduling, memory) key their state by it; `policy` is what the caller may run
+/// and `memory_scope` the only memory it may touch (`None` is the global store).
+#[derive(Debug, Clone, Default)]
+pub struct ToolContext {
+    pub session_key: String,
+    pub channel: Option<String>,
+    pub chat_id: Option<String>,
+    pub user_id: Option<String>,
+    pub policy: ToolPolicy,
+    pub memory_scope: Option<String>,
+    pub workspace: Option<PathBuf>,
+}
+
+impl ToolContext {
+    /// Refuses paths in the workspace that hold another user's or conversation's data,
+    /// once a memory scope is active: memory other than the shared MEMORY.md and the
+    /// caller's own scope, other sessions' logs, and other conversations' inboxes.
+    pub fn check_path(&self, path: &Path) -> Result<()> {
+        if self.may_access(path) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Access denied: {} belongs to another user or conversation", path.display()))
+        }
+    }
+
+    fn may_access(&self, path: &Path) -> bool {
+        let (scope, workspace) = match (&self.memory_scope, &self.workspace) {
+            (Some(scope), Some(workspace)) => (scope, workspace),
+            _ => return true,
+        };
+        let (path, workspace) = match (resolve(path), resolve(workspace)) {
+            (Some(path), Some(workspace)) => (path, workspace),
+            _ => return false,
+        };
+        let parts: Vec<String> = match path.strip_prefix(&workspace) {
+            Ok(rel) => rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect(),
+            Err(_) => return true,
+        };
+        let parts: Vec<&str> = parts.iter().map(String::as_str).collect();
+        let key = self.session_key.as_str();
+        match parts.as_slice() {
+            ["memory", "MEMORY.md"] => true,
+            ["memory", "scopes", own, ..] => *own == crate::agent::memory::encode_scope(scope),
+            ["memory", ..] => false,
+            ["sessions", file] => *file == format!("{}.jsonl", key) || crate::agent::session::archived_key(file) == Some(key),
+            ["sessions", ..] => false,
+            ["inbox", own, ..] => *own == key,
+            ["inbox"] => false,
+            _ => true,
+        }
+    }
+}
+
+/// `path` made absolute with symlinks and `..` resolved, including a tail that doesn't
+/// exist yet. `None` if a `..` follows a missing directory.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let mut existing = if path.is_absolute() { path.to_path_buf() } else { std::env::current_dir().ok()?.join(path) };
+    let mut tail = Vec::new();
+    loop {
+        if let Ok(mut real) = fs::canonicalize(&existing) {
+            real.extend(tail.iter().rev());
+            return Some(real);
+        }
+        tail.push(existing.file_name()?.to_os_string());
+        existing.pop();
+    }
+}
+
+/// Largest attachment inlined into a request as a data URL.
+const MAX_INLINE_IMAGE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub enum AttachmentData {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub name: String,
+    pub mime: String,
+    pub data: AttachmentData,
+}
+
+impl Attachment {
+    pub fn from_path(path: impl Into<PathBuf>, mime: &str) -> Self {
+        let path = path.into();
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        Self { name, mime: mime.to_string(), data: AttachmentData::Path(path) }
+    }
+
+    pub fn from_bytes(name: &str, mime: &str, bytes: Vec<u8>) -> Self {
+        Self { name: name.to_string(), mime: mime.to_string(), data: AttachmentData::Bytes(bytes) }
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.mime.starts_with("image/")
+    }
+
+    /// Image as an OpenAI-style `image_url` part with a base64 data URL.
+    pub fn to_image_part(&self) -> Option<ContentPart> {
+        if !self.is_image() {
+            return None;
+        }
+        let bytes = match &self.data {
+            AttachmentData::Bytes(b) => b.clone(),
+            AttachmentData::Path(p) => {
+                if fs::metadata(p).ok()?.len() > MAX_INLINE_IMAGE {
+                    return None;
+                }
+                fs::read(p).ok()?
+            }
+        };
+        if bytes.len() as u64 > MAX_INLINE_IMAGE {
+            return None;
+        }
+        Some(ContentPart::ImageUrl {
+            image_url: ImageUrl { url: format!("data:{};base64,{}", self.mime, crate::utils::base64_encode(&bytes)) },
+        })
+    }
+
+    /// One line naming the attachment and where it is, for the model.
+    pub fn describe(&self) -> String {
+        match &self.data {
+            AttachmentData::Path(p) => format!("[Attachment: {} ({}) at {}]", self.name, self.mime, p.display()),
+            AttachmentData::Bytes(b) => format!("[Attachment: {} ({}, {} bytes)]", self.name, self.mime, b.len()),
+        }
+    }
+}
+
+/// Result of a tool call. `is_error` lets the agent tell failures from output
+/// that merely mentions an error; attachments travel back to the channel.
+#[derive(Debug, Clone, Default)]
+pub struct ToolOutput {
+    pub is_error: bool,
+    pub parts: Vec<String>,
+    pub data: Option<Value>,
+    pub attachments: Vec<Attachment>,
+}
+
+impl ToolOutput {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self { parts: vec![text.into()], ..Default::default() }
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self { is_error: true, parts: vec![text.into()], ..Default::default() }
+    }
+
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    pub fn with_attachment(mut self, attachment: Attachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+
+    /// Flattens the output into the string content of a `tool` message.
+    pub fn to_text(&self) -> String {
+        let mut out = self.parts.join("\n");
+        if let Some(data) = &self.data {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&data.to_string());
+        }
+        for attachment in &self.attachments {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&attachment.describe());
+        }
+        if self.is_error {
+            format!("Error: {}", out)
+        } else {
+            out
+        }
+    }
+}
+
+impl From<String> for ToolOutput {
+    fn from(text: String) -> Self {
+        ToolOutput::text(text)
+    }
+}
+
+pub type ToolBox = Vec<Box<dyn Tool>>;
+
+/// Tools that never modify files, run commands or queue later runs.
+pub const READONLY_TOOLS: &[&str] = &["read_file", "list_dir", "web_search", "web_fetch", "get_sys_info", "recall", "search_memory"];
+
+/// Which tools a given context may see and call. `allowed: None` means no restriction.
+#[derive(Debug, Clone, Default)]
+pub struct ToolPolicy {
+    allowed: Option<Vec<String>>,
+}
+
+impl ToolPolicy {
+    pub fn only(names: &[&str]) -> Self {
+        Self { allowed: Some(names.iter().map(|n| n.to_string()).collect()) }
+    }
+
+    pub fn permits(&self, name: &str) -> bool {
+        match &self.allowed {
+            Some(list) => list.iter().any(|n| n == name || n == "*"),
+            None => true,
+        }
+    }
+
+    /// Builds the policy for `profile` from config, intersected with `tools.enabled`.
+    /// Profiles defined in config take precedence over the builtin `full` and `readonly`.
+    /// An unknown profile denies everything rather than silently granting full access.
+    pub fn resolve(config: &ToolsConfig, profile: Option<&str>) -> Self {
+        let profile_list: Option<Vec<String>> = match profile {
+            None => None,
+            Some(name) => match config.profiles.get(name) {
+                Some(list) => Some(list.clone()),
+                None => match name {
+                    "full" => None,
+                    "readonly" => Some(READONLY_TOOLS.iter().map(|n| n.to_string()).collect()),
+                    _ => {
+                        eprintln!("Unknown tool profile '{}', disabling all tools", name);
+                        Some(Vec::new())
+                    }
+                },
+            },
+        };
+
+        let allowed = match (config.enabled.clone(), profile_list) {
+            (None, p) => p,
+            (Some(e), None) => Some(e),
+            (Some(e), Some(p)) if e.iter().any(|n| n == "*") => Some(p),
+            (Some(e), Some(p)) => {
+                let profile = ToolPolicy { allowed: Some(p) };
+                Some(e.into_iter().filter(|n| profile.permits(n)).collect())
+            }
+        };
+
+        Self { allowed }
+    }
+}
@@ -1,40 +1,101 @@
 #![allow(dead_code)]
-use std::collections::HashMap;
 use anyhow::{Result, anyhow};
 use serde_json::Value;
-use crate::tools::{Tool, ToolBox};
-use crate::agent::llm::ToolDefinition;
+use crate::tools::{Tool, ToolBox, ToolContext, ToolOutput, ToolPolicy, schema};
+use crate::agent::llm::{FunctionDefinition, ToolDefinition};
 
+/// Tools in registration order. Definitions are built once at registration so that
+/// requests are byte-identical across runs, which keeps provider prompt caches warm.
 pub struct ToolRegistry {
     tools: ToolBox,
+    definitions: Vec<ToolDefinition>,
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ToolRegistry {
     pub fn new() -> Self {
         Self {
-            tools: HashMap::new(),
+            tools: Vec::new(),
+            definitions: Vec::new(),
         }
     }
 
+    /// Adds a tool. Registering a name twice replaces the tool but keeps its position.
     pub fn register(&mut self, tool: Box<dyn Tool>) {
-        self.tools.insert(tool.name().to_string(), tool);
-    }
-
-    pub fn get_definitions(&self) -> Vec<ToolDefinition> {
-        self.tools.values().map(|t| {
-            ToolDefinition {
-                r#type: "function".to_string(),
-                function: crate::agent::llm::FunctionDefinition {
-                    name: t.name().to_string(),
-                    description: t.description().to_string(),
-                    parameters: t.parameters(),
-                },
+        let definition = ToolDefinition {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: tool.name().to_string(),
+                description: tool.description().to_string(),
+                parameters: tool.parameters(),
+            },
+        };
+        match self.position(tool.name()) {
+            Some(i) => {
+                self.tools[i] = tool;
+                self.definitions[i] = definition;
             }
-        }).collect()
+            None => {
+                self.tools.push(tool);
+                self.definitions.push(definition);
+            }
+        }
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<Box<dyn Tool>> {
+        let i = self.position(name)?;
+        self.definitions.remove(i);
+        Some(self.tools.remove(i))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Tool> {
+        self.position(name).map(|i| self.tools[i].as_ref())
+    }
+
+    /// All registered tools with their metadata, in registration order.
+    pub fn list(&self) -> &[ToolDefinition] {
+        &self.definitions
     }
 
-    pub fn execute(&self, name: &str, args: Value) -> Result<String> {
-        let tool = self.tools.get(name).ok_or_else(|| anyhow!("Tool {} not found", name))?;
-        tool.execute(args)
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.tools.iter().position(|t| t.name() == name)
+    }
+
+    /// Definitions advertised to the model: only tools permitted by `policy`.
+    pub fn get_definitions(&self, policy: &ToolPolicy) -> Vec<ToolDefinition> {
+        self.definitions.iter()
+            .filter(|d| policy.permits(&d.function.name))
+            .cloned()
+            .collect()
+    }
+
+    /// Parses raw tool call arguments, repairing common JSON mistakes.
+    pub fn parse_arguments(&self, raw: &str) -> Result<Value> {
+        schema::parse_arguments(raw)
+    }
+
+    /// Runs a tool. `Err` means the call never reached the tool (unknown, refused or
+    /// invalid arguments); failures inside the tool come back as `ToolOutput::is_error`.
+    pub fn execute(&self, name: &str, args: Value, ctx: &ToolContext) -> Result<ToolOutput> {
+        let i = self.position(name).ok_or_else(|| anyhow!("Tool {} not found", name))?;
+        if !ctx.policy.permits(name) {
+            return Err(anyhow!("Tool {} is not permitted in this context", name));
+        }
+        schema::validate(&self.definitions[i].function.parameters, &args)
+            .map_err(|e| anyhow!("Invalid arguments for {}: {}. Fix the arguments and call the tool again.", name, e))?;
+        self.tools[i].execute(args, ctx).or_else(|e| Ok(ToolOutput::error(e.to_string())))
     }
 }
@@ -0,0 +1,105 @@
+#![allow(dead_code)]
+use anyhow::{Result, anyhow};
+use serde_json::{json, Value};
+use time::{Duration, OffsetDateTime};
+use crate::agent::scheduler::{Cron, Job, Schedule, Scheduler, parse_at};
+use crate::tools::{Tool, ToolContext, ToolOutput};
+use crate::utils::parse_utc_offset;
+
+pub struct ScheduleTool {
+    pub scheduler: Scheduler,
+    pub default_timezone: String,
+}
+
+impl Tool for ScheduleTool {
+    fn name(&self) -> &str { "schedule" }
+    fn description(&self) -> &str {
+        "Schedule a prompt for you to run later in this chat (reminders, recurring reports). \
+         Give exactly one of `at`, `in_minutes` or `cron` when adding. The prompt is executed by you when due, \
+         and your answer is sent to the user."
+    }
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": { "type": "string", "enum": ["add", "list", "remove"] },
+                "prompt": { "type": "string", "description": "Instruction to run when due, e.g. 'Remind the user to call the bank'" },
+                "at": { "type": "string", "description": "One-shot time: HH:MM (next occurrence) or YYYY-MM-DD HH:MM" },
+                "in_minutes": { "type": "integer", "minimum": 1, "description": "One-shot delay from now" },
+                "cron": { "type": "string", "description": "Recurring 5-field cron, e.g. '0 9 * * mon'" },
+                "timezone": { "type": "string", "description": "UTC, local or an offset like -03:00" },
+                "id": { "type": "string", "description": "Job id, for remove" }
+            },
+            "required": ["action"]
+        })
+    }
+    fn execute(&self, args: Value, ctx: &ToolContext) -> Result<ToolOutput> {
+        match args["action"].as_str().unwrap_or("") {
+            "add" => self.add(&args, ctx),
+            "list" => {
+                let jobs: Vec<String> = self.scheduler.load().iter()
+                    .filter(|j| j.session_key == ctx.session_key && j.action.is_none())
+                    .map(|j| j.describe())
+                    .collect();
+                if jobs.is_empty() {
+                    Ok(ToolOutput::text("No scheduled jobs."))
+                } else {
+                    Ok(ToolOutput::text(jobs.join("\n")))
+                }
+            }
+            "remove" => {
+                let id = args["id"].as_str().ok_or_else(|| anyhow!("id is required for remove"))?;
+                let owned = self.scheduler.load().iter().any(|j| j.id == id && j.session_key == ctx.session_key && j.action.is_none());
+                if !owned {
+                    return Ok(ToolOutput::error(format!("No job {} in this chat", id)));
+                }
+                self.scheduler.remove(id)?;
+                Ok(ToolOutput::text(format!("Removed job {}", id)))
+            }
+            other => Ok(ToolOutput::error(format!("Unknown action '{}'", other))),
+        }
+    }
+}
+
+impl ScheduleTool {
+    fn add(&self, args: &Value, ctx: &ToolContext) -> Result<ToolOutput> {
+        if ctx.chat_id.is_none() {
+            return Ok(ToolOutput::error("Scheduling needs a chat to deliver to; it is only available in bot mode"));
+        }
+        let prompt = args["prompt"].as_str().ok_or_else(|| anyhow!("prompt is required for add"))?;
+        let timezone = args["timezone"].as_str().unwrap_or(&self.default_timezone).to_string();
+        let offset = parse_utc_offset(&timezone)?;
+        let now = OffsetDateTime::now_utc().to_offset(offset);
+
+        let schedule = match (args["at"].as_str(), args["in_minutes"].as_i64(), args["cron"].as_str()) {
+            (Some(at), None, None) => Schedule::Once { at: parse_at(at, now)?.unix_timestamp() },
+            (None, Some(m), None) => Schedule::Once { at: (now + Duration::minutes(m)).unix_timestamp() },
+            (None, None, Some(expr)) => {
+                Cron::parse(expr)?;
+                Schedule::Cron { expr: expr.to_string() }
+            }
+            _ => return Ok(ToolOutput::error("Give exactly one of at, in_minutes or cron")),
+        };
+
+        let mut job = Job {
+            id: String::new(),
+            prompt: prompt.to_string(),
+            schedule,
+            timezone,
+            session_key: ctx.session_key.clone(),
+            channel: ctx.channel.clone(),
+            chat_id: ctx.chat_id.clone(),
+            user_id: ctx.user_id.clone(),
+            next_run: 0,
+            created_at: now.unix_timestamp(),
+            action: None,
+        };
+        job.next_run = match job.next_after(now.unix_timestamp())? {
+            Some(t) => t,
+            None => return Ok(ToolOutput::error("That time is in the past")),
+        };
+
+        let job = self.scheduler.add(job)?;
+        Ok(ToolOutput::text(format!("Scheduled {}", job.describe())))
+    }
+}
@@ -0,0 +1,270 @@
+#![allow(dead_code)]
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+
+/// Parses tool call arguments, repairing the malformed JSON models commonly emit
+/// (code fences, trailing commas, single quotes, Python literals) before giving up.
+pub fn parse_arguments(raw: &str) -> Result<Value> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return Ok(Value::Object(Default::default()));
+    }
+
+    let first_err = match serde_json::from_str::<Value>(trimmed) {
+        Ok(v) => return Ok(normalize(v)),
+        Err(e) => e,
+    };
+
+    let repaired = repair_json(trimmed);
+    serde_json::from_str::<Value>(&repaired)
+        .map(normalize)
+        .map_err(|_| anyhow!("Invalid JSON in tool arguments: {}. Send arguments as a JSON object.", first_err))
+}
+
+// Some models double-encode arguments ("{\"path\": ...}") or send null for no-arg tools.
+fn normalize(v: Value) -> Value {
+    match v {
+        Value::Null => Value::Object(Default::default()),
+        Value::String(s) => match serde_json::from_str::<Value>(&s) {
+            Ok(inner @ Value::Object(_)) => inner,
+            _ => Value::String(s),
+        },
+        other => other,
+    }
+}
+
+fn repair_json(input: &str) -> String {
+    let mut s = input.trim();
+
+    // Strip markdown code fences: ```json ... ```
+    if let Some(rest) = s.strip_prefix("```") {
+        let rest = rest.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+        s = rest.strip_suffix("```").unwrap_or(rest).trim();
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    // Quote char of the string we are inside, if any
+    let mut in_string: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        if let Some(q) = in_string {
+            match c {
+                '\\' => {
+                    if let Some(next) = chars.next() {
+                        // \' is not a valid JSON escape
+                        if next == '\'' {
+                            out.push('\'');
+                        } else {
+                            out.push('\\');
+                            out.push(next);
+                        }
+                    }
+                }
+                '"' if q == '\'' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                c if c == q => {
+                    out.push('"');
+                    in_string = None;
+                }
+                c => out.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' | '\'' => {
+                out.push('"');
+                in_string = Some(c);
+            }
+            ',' => {
+                // Drop trailing commas before a closing bracket
+                let mut lookahead = chars.clone();
+                while lookahead.peek().is_some_and(|c| c.is_whitespace()) {
+                    lookahead.next();
+                }
+                if !matches!(lookahead.peek(), Some('}') | Some(']')) {
+                    out.push(',');
+                }
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut word = String::from(c);
+                while let Some(&n) = chars.peek() {
+                    if n.is_ascii_alphanumeric() || n == '_' {
+                        word.push(n);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let is_key = {
+                    let mut lookahead = chars.clone();
+                    while lookahead.peek().is_some_and(|c| c.is_whitespace()) {
+                        lookahead.next();
+                    }
+                    lookahead.peek() == Some(&':')
+                };
+                match word.as_str() {
+                    _ if is_key => out.push_str(&format!("\"{}\"", word)),
+                    "True" => out.push_str("true"),
+                    "False" => out.push_str("false"),
+                    "None" => out.push_str("null"),
+                    _ => out.push_str(&word),
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    // Close an object the model forgot to terminate
+    if in_string.is_none() && out.starts_with('{') {
+        let opens = out.matches('{').count();
+        let closes = out.matches('}').count();
+        for _ in closes..opens {
+            out.push('}');
+        }
+    }
+
+    out
+}
+
+/// Validates `value` against the subset of JSON Schema used by tool definitions:
+/// `type`, `properties`, `required`, `additionalProperties`, `enum`, `items`,
+/// numeric bounds and string/array length bounds. All violations are reported at once.
+pub fn validate(schema: &Value, value: &Value) -> Result<()> {
+    let mut errors = Vec::new();
+    check(schema, value, "$", &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("{}", errors.join("; ")))
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn matches_type(expected: &str, value: &Value) -> bool {
+    match expected {
+        "number" => value.is_number(),
+        "integer" => match value {
+            Value::Number(n) => n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|f| f.fract() == 0.0),
+            _ => false,
+        },
+        other => type_name(value) == other,
+    }
+}
+
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let schema = match schema.as_object() {
+        Some(s) => s,
+        None => return,
+    };
+
+    if let Some(t) = schema.get("type") {
+        let allowed: Vec<&str> = match t {
+            Value::String(s) => vec![s.as_str()],
+            Value::Array(a) => a.iter().filter_map(|v| v.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| matches_type(t, value)) {
+            errors.push(format!("{}: expected {}, got {}", path, allowed.join(" or "), type_name(value)));
+            return;
+        }
+    }
+
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            let list: Vec<String> = options.iter().map(|o| o.to_string()).collect();
+            errors.push(format!("{}: must be one of [{}], got {}", path, list.join(", "), value));
+        }
+    }
+
+    match value {
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or(0.0);
+            if let Some(min) = schema.get("minimum").and_then(|v| v.as_f64()) {
+                if n < min {
+                    errors.push(format!("{}: must be >= {}, got {}", path, min, n));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(|v| v.as_f64()) {
+                if n > max {
+                    errors.push(format!("{}: must be <= {}, got {}", path, max, n));
+                }
+            }
+            if let Some(min) = schema.get("exclusiveMinimum").and_then(|v| v.as_f64()) {
+                if n <= min {
+                    errors.push(format!("{}: must be > {}, got {}", path, min, n));
+                }
+            }
+            if let Some(max) = schema.get("exclusiveMaximum").and_then(|v| v.as_f64()) {
+                if n >= max {
+                    errors.push(format!("{}: must be < {}, got {}", path, max, n));
+                }
+            }
+        }
+        Value::String(s) => {
+            let len = s.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(|v| v.as_u64()) {
+                if len < min {
+                    errors.push(format!("{}: must be at least {} characters", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(|v| v.as_u64()) {
+                if len > max {
+                    errors.push(format!("{}: must be at most {} characters", path, max));
+                }
+            }
+        }
+        Value::Array(items) => {
+            let len = items.len() as u64;
+            if let Some(min) = schema.get("minItems").and_then(|v| v.as_u64()) {
+                if len < min {
+                    errors.push(format!("{}: must have at least {} items", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(|v| v.as_u64()) {
+                if len > max {
+                    errors.push(format!("{}: must have at most {} items", path, max));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(item_schema, item, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        Value::Object(obj) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for key in required.iter().filter_map(|k| k.as_str()) {
+                    if !obj.contains_key(key) {
+                        errors.push(format!("{}: missing required property '{}'", path, key));
+                    }
+                }
+            }
+            let props = schema.get("properties").and_then(|p| p.as_object());
+            for (key, v) in obj {
+                let child = format!("{}.{}", path, key);
+                match props.and_then(|p| p.get(key)) {
+                    Some(prop_schema) => check(prop_schema, v, &child, errors),
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => errors.push(format!("{}: unknown property", child)),
+                        Some(extra @ Value::Object(_)) => check(extra, v, &child, errors),
+                        _ => {}
+                    },
+                }
+            }
+        }
+        _ => {}
+    }
+}
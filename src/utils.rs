@@ -1,4 +1,9 @@
 use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 // FFI to call malloc_trim from glibc
 extern "C" {
@@ -36,3 +41,136 @@ pub fn print_box_bottom() {
     let rss = get_current_rss_kb();
     println!("╰──── [RSS: {:.2} MB] ────", rss as f32 / 1024.0);
 }
+
+pub fn base64_encode(input: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        out.push(TABLE[(n >> 18) as usize & 63] as char);
+        out.push(TABLE[(n >> 12) as usize & 63] as char);
+        out.push(if chunk.len() > 1 { TABLE[(n >> 6) as usize & 63] as char } else { '=' });
+        out.push(if chunk.len() > 2 { TABLE[n as usize & 63] as char } else { '=' });
+    }
+    out
+}
+
+/// The machine's UTC offset, read once. `time` refuses to read it once the process has
+/// several threads, so call this at startup before any are spawned.
+pub fn local_offset() -> time::UtcOffset {
+    static LOCAL: std::sync::OnceLock<time::UtcOffset> = std::sync::OnceLock::new();
+    *LOCAL.get_or_init(|| time::UtcOffset::current_local_offset().unwrap_or(time::UtcOffset::UTC))
+}
+
+/// Parses a timezone given as `UTC`, `local`, or a fixed offset like `+03:00` / `-0300` / `+3`.
+/// IANA names are not supported: there is no tz database on the target boards.
+pub fn parse_utc_offset(tz: &str) -> anyhow::Result<time::UtcOffset> {
+    let tz = tz.trim();
+    match tz.to_lowercase().as_str() {
+        "" | "utc" | "z" | "gmt" => return Ok(time::UtcOffset::UTC),
+        "local" => return Ok(local_offset()),
+        _ => {}
+    }
+    let body = tz.trim_start_matches("UTC").trim_start_matches("GMT");
+    let (sign, digits) = match body.chars().next() {
+        Some('+') => (1, &body[1..]),
+        Some('-') => (-1, &body[1..]),
+        _ => return Err(anyhow::anyhow!("Unsupported timezone '{}'. Use UTC, local or an offset like +03:00", tz)),
+    };
+    let (h, m) = match digits.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if digits.len() == 4 => (&digits[..2], &digits[2..]),
+        None => (digits, "0"),
+    };
+    let h: i8 = h.parse().map_err(|_| anyhow::anyhow!("Invalid timezone offset '{}'", tz))?;
+    let m: i8 = m.parse().map_err(|_| anyhow::anyhow!("Invalid timezone offset '{}'", tz))?;
+    time::UtcOffset::from_hms(sign * h, sign * m, 0).map_err(|e| anyhow::anyhow!("Invalid timezone offset '{}': {}", tz, e))
+}
+
+/// Moves an unreadable state file aside to `<name>.corrupt-<unix time>` so it can be
+/// inspected, and returns the new path. The caller then starts from a clean state.
+pub fn quarantine(path: &Path) -> std::io::Result<std::path::PathBuf> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".corrupt-{}", time::OffsetDateTime::now_utc().unix_timestamp()));
+    let target = path.with_file_name(name);
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+/// Replaces `path` atomically: write a sibling temp file, fsync it, then rename over
+/// the target. Readers see either the old or the new content, never a torn file.
+pub fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+    // Persist the rename itself
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = fs::File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+static SHUTDOWN_HOOKS: Mutex<Vec<Box<dyn Fn() + Send>>> = Mutex::new(Vec::new());
+
+/// Turns the first Ctrl-C or SIGTERM into a shutdown request, so long-running modes
+/// can finish the work in hand. A second one exits at once.
+pub fn install_shutdown_handler() -> anyhow::Result<()> {
+    ctrlc::set_handler(|| {
+        if shutdown_requested() {
+            eprintln!("Exiting now.");
+            std::process::exit(130);
+        }
+        eprintln!("Shutting down; press Ctrl-C again to exit immediately.");
+        request_shutdown();
+    })?;
+    Ok(())
+}
+
+/// Sets the shutdown flag and runs the hooks registered with `on_shutdown`.
+pub fn request_shutdown() {
+    if !SHUTDOWN.swap(true, Ordering::SeqCst) {
+        for hook in SHUTDOWN_HOOKS.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            hook();
+        }
+    }
+}
+
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+/// Registers `hook` to run on shutdown, e.g. to interrupt a blocking wait. Runs at once
+/// if shutdown was already requested.
+pub fn on_shutdown<F: Fn() + Send + 'static>(hook: F) {
+    let mut hooks = SHUTDOWN_HOOKS.lock().unwrap_or_else(|e| e.into_inner());
+    if shutdown_requested() {
+        hook();
+    }
+    hooks.push(Box::new(hook));
+}
+
+/// Sleeps for `duration`, waking early on shutdown. Returns whether shutdown was requested.
+pub fn sleep_unless_shutdown(duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while !shutdown_requested() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return false;
+        }
+        std::thread::sleep(left.min(Duration::from_millis(200)));
+    }
+    true
+}
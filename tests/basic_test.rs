@@ -1,9 +1,6 @@
 #[cfg(test)]
 mod tests {
     use redclaw::config::{Config, AgentsConfig, AgentDefaults, ProvidersConfig, ToolsConfig, WebToolsConfig, WebSearchConfig};
-    use redclaw::agent::Agent;
-    use redclaw::agent::llm::LLMClient;
-    use redclaw::tools::registry::ToolRegistry;
     use redclaw::config::ProviderConfig;
 
     #[test]
@@ -16,6 +13,12 @@ mod tests {
                     max_tokens: 100,
                     temperature: 0.5,
                     max_tool_iterations: 5,
+                    vision: false,
+                    memory_budget: 8192,
+                    timezone: "UTC".to_string(),
+                    memory_scope: Default::default(),
+                    consolidation: None,
+            stream: false,
                 },
             },
             providers: ProvidersConfig {
@@ -29,6 +32,7 @@ mod tests {
                 vllm: None,
             },
             channels: Default::default(),
+            server: None,
             tools: ToolsConfig {
                 web: WebToolsConfig {
                     search: WebSearchConfig {
@@ -36,6 +40,9 @@ mod tests {
                         max_results: 5,
                     },
                 },
+                enabled: None,
+                profiles: Default::default(),
+                sessions: Default::default(),
             },
         };
         assert_eq!(config.agents.defaults.model, "test-model");
@@ -0,0 +1,58 @@
+use redclaw::agent::llm::Message;
+use redclaw::agent::session::{SessionStore, StoredMessage};
+use std::process::Command;
+
+fn redclaw(config: &std::path::Path, args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_redclaw"))
+        .arg("-c").arg(config)
+        .args(args)
+        .output()
+        .unwrap();
+    let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    (output.status.success(), text)
+}
+
+#[test]
+fn test_sessions_commands_accept_negative_chat_ids() {
+    let workspace = std::env::temp_dir().join(format!("redclaw_cli_{}", std::process::id()));
+    std::fs::remove_dir_all(&workspace).ok();
+    std::fs::create_dir_all(&workspace).unwrap();
+    let config = workspace.join("config.json");
+    std::fs::write(&config, serde_json::json!({
+        "agents": { "defaults": { "workspace": workspace, "model": "stub", "max_tokens": 100, "temperature": 0.7, "max_tool_iterations": 3 } },
+        "providers": {},
+        "tools": { "web": { "search": { "api_key": "", "max_results": 5 } } },
+    }).to_string()).unwrap();
+
+    let store = SessionStore::new(&workspace);
+    let message = Message { role: "user".to_string(), content: Some("hello group".into()), name: None, tool_call_id: None, tool_calls: None };
+    store.open("-100123-topic-5").unwrap().append(&[StoredMessage::new(message.clone())]).unwrap();
+
+    let (ok, out) = redclaw(&config, &["sessions", "show", "-100123-topic-5"]);
+    assert!(ok, "{}", out);
+    assert!(out.contains("user: hello group"), "{}", out);
+
+    let (ok, out) = redclaw(&config, &["sessions", "fork", "-100123-topic-5", "-100123"]);
+    assert!(ok, "{}", out);
+    let (ok, out) = redclaw(&config, &["sessions", "rm", "--", "-100123"]);
+    assert!(ok, "{}", out);
+    assert!(!store.exists("-100123"));
+    assert!(store.exists("-100123-topic-5"));
+
+    // Keys named like commands or options are still keys
+    let before = std::fs::read_to_string(&config).unwrap();
+    for key in ["onboard", "memory", "-s"] {
+        store.open(key).unwrap().append(&[StoredMessage::new(message.clone())]).unwrap();
+    }
+    let (ok, out) = redclaw(&config, &["sessions", "show", "memory"]);
+    assert!(ok, "{}", out);
+    assert!(out.contains("user: hello group"), "{}", out);
+    for key in ["onboard", "memory", "-s"] {
+        let (ok, out) = redclaw(&config, &["sessions", "rm", key]);
+        assert!(ok, "{}", out);
+        assert!(!store.exists(key));
+    }
+    assert_eq!(std::fs::read_to_string(&config).unwrap(), before);
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
@@ -0,0 +1,38 @@
+use redclaw::agent::channels::parse_command;
+use redclaw::agent::llm::{Message, Usage};
+use redclaw::agent::session::{total_usage, SessionLog, StoredMessage};
+
+#[test]
+fn test_parse_command() {
+    assert_eq!(parse_command("/start"), Some(("start".to_string(), "")));
+    assert_eq!(parse_command("/Usage@redclaw_bot  today "), Some(("usage".to_string(), "today")));
+    assert_eq!(parse_command("/model\ngpt-4o"), Some(("model".to_string(), "gpt-4o")));
+    assert_eq!(parse_command("hello /start"), None);
+    assert_eq!(parse_command("/etc/passwd is missing"), None);
+    assert_eq!(parse_command("/"), None);
+}
+
+#[test]
+fn test_reset_archives_session_and_usage_sums() {
+    let dir = std::env::temp_dir().join(format!("redclaw_reset_{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    let log = SessionLog::open(&dir, "chat").unwrap();
+    // Archiving a session that was never written is a no-op
+    log.archive(0).unwrap();
+    assert!(!dir.join("archive").exists());
+
+    let message = |role: &str| Message { role: role.to_string(), content: Some("x".into()), name: None, tool_call_id: None, tool_calls: None };
+    let reply = |total| StoredMessage {
+        usage: Some(Usage { prompt_tokens: total - 10, completion_tokens: 10, total_tokens: total }),
+        ..StoredMessage::new(message("assistant"))
+    };
+    log.append(&[StoredMessage::new(message("user")), reply(100), StoredMessage::new(message("user")), reply(250)]).unwrap();
+    let usage = total_usage(&log.all().unwrap());
+    assert_eq!(usage, Usage { prompt_tokens: 330, completion_tokens: 20, total_tokens: 350 });
+
+    log.archive(0).unwrap();
+    assert!(log.all().unwrap().is_empty());
+    assert_eq!(std::fs::read_dir(dir.join("archive")).unwrap().count(), 1);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
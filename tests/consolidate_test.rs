@@ -0,0 +1,137 @@
+use redclaw::agent::Agent;
+use redclaw::agent::consolidate::{parse_digest, Consolidation, ConsolidateOptions};
+use redclaw::agent::http;
+use redclaw::agent::llm::LLMClient;
+use redclaw::agent::memory::MemoryStore;
+use redclaw::config::{Config, AgentsConfig, AgentDefaults, ProvidersConfig, ToolsConfig, WebToolsConfig, WebSearchConfig, ProviderConfig};
+use redclaw::tools::registry::ToolRegistry;
+use std::io::BufReader;
+use std::net::TcpListener;
+
+/// An LLM provider stub whose every reply is `content`.
+fn provider(content: &'static str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            if http::read_request(&mut BufReader::new(&stream)).is_err() {
+                continue;
+            }
+            let reply = serde_json::json!({
+                "model": "stub-model",
+                "choices": [{ "message": { "role": "assistant", "content": content }, "finish_reason": "stop" }],
+            });
+            let _ = http::write_response(&mut stream, 200, "application/json", reply.to_string().as_bytes());
+        }
+    });
+    url
+}
+
+fn agent(workspace: &std::path::Path, api_base: &str) -> Agent {
+    let config = Config {
+        agents: AgentsConfig {
+            defaults: AgentDefaults {
+                workspace: workspace.to_string_lossy().to_string(),
+                model: "stub-model".to_string(),
+                max_tokens: 100,
+                temperature: 0.7,
+                max_tool_iterations: 3,
+                vision: false,
+                memory_budget: 8192,
+                timezone: "UTC".to_string(),
+                memory_scope: Default::default(),
+                consolidation: None,
+                stream: false,
+            },
+        },
+        providers: ProvidersConfig {
+            openai: Some(ProviderConfig { api_key: "fake-key".to_string(), api_base: Some(api_base.to_string()) }),
+            gemini: None,
+            openrouter: None,
+            zhipu: None,
+            vllm: None,
+        },
+        channels: Default::default(),
+        server: None,
+        tools: ToolsConfig {
+            web: WebToolsConfig { search: WebSearchConfig { api_key: "".to_string(), max_results: 5 } },
+            enabled: None,
+            profiles: Default::default(),
+            sessions: Default::default(),
+        },
+    };
+    let client = LLMClient::new(config.providers.openai.as_ref().unwrap(), api_base, "stub-model");
+    Agent::new(&config, client, ToolRegistry::new())
+}
+
+#[test]
+fn test_parse_digest_splits_facts() {
+    let reply = "- Planned the trip to Lisbon\n- Fixed the backup script\nFACT: User | home city | Porto\n- FACT: Projects | backup | rsync to NAS nightly\nFACT: malformed line";
+    let (digest, facts) = parse_digest(reply);
+    assert_eq!(digest, "- Planned the trip to Lisbon\n- Fixed the backup script");
+    assert_eq!(facts.len(), 2);
+    assert_eq!(facts[0].section, "User");
+    assert_eq!(facts[0].key, "home city");
+    assert_eq!(facts[1].value, "rsync to NAS nightly");
+}
+
+#[test]
+fn test_memory_diff_lists_changed_lines() {
+    let c = Consolidation {
+        scope: None,
+        days: Vec::new(),
+        memory_before: "# Long-term Memory\n\n## User\n\n- **name**: Ana\n".to_string(),
+        memory_after: "# Long-term Memory\n\n## User\n\n- **name**: Ana Souza\n- **city**: Porto\n".to_string(),
+    };
+    assert_eq!(c.memory_diff(), "- - **name**: Ana\n+ - **name**: Ana Souza\n+ - **city**: Porto");
+}
+
+#[test]
+fn test_archive_keeps_day_searchable_through_digest() {
+    let workspace = std::env::temp_dir().join(format!("redclaw_archive_{}", std::process::id()));
+    std::fs::remove_dir_all(&workspace).ok();
+    let memory = MemoryStore::new(&workspace);
+    let day_dir = workspace.join("memory").join("202401");
+    std::fs::create_dir_all(&day_dir).unwrap();
+    std::fs::write(day_dir.join("20240115.md"), "User: my bicycle has a flat tyre\nAssistant: Patch it.\n").unwrap();
+    memory.rebuild_index().unwrap();
+
+    assert!(memory.archive_day(20240115).is_err(), "archiving needs a digest first");
+    memory.write_digest(20240115, "\n").unwrap();
+    assert!(memory.archive_day(20240115).is_err(), "an empty digest doesn't count");
+    memory.write_digest(20240115, "- Repaired the bicycle puncture").unwrap();
+    memory.archive_day(20240115).unwrap();
+
+    assert!(!day_dir.join("20240115.md").exists());
+    assert!(day_dir.join("20240115.md.gz").exists());
+    assert!(memory.daily_files().is_empty());
+
+    let hits = memory.search("puncture", 3).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].date, "2024-01-15");
+    assert!(hits[0].snippet.contains("Repaired"));
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
+
+#[test]
+fn test_day_without_digest_is_neither_written_nor_archived() {
+    let workspace = std::env::temp_dir().join(format!("redclaw_empty_digest_{}", std::process::id()));
+    std::fs::remove_dir_all(&workspace).ok();
+    let day_dir = workspace.join("memory").join("202401");
+    std::fs::create_dir_all(&day_dir).unwrap();
+    std::fs::write(day_dir.join("20240115.md"), "User: call me Ana\nAssistant: Sure.\n").unwrap();
+
+    let agent = agent(&workspace, &provider("FACT: User | name | Ana"));
+    let results = agent.consolidate_memory(&ConsolidateOptions { dry_run: false, archive: true }).unwrap();
+    assert!(results.is_empty());
+
+    let memory = MemoryStore::new(&workspace);
+    assert!(memory.read_digest(20240115).is_none());
+    assert!(day_dir.join("20240115.md").exists());
+    assert!(!day_dir.join("20240115.md.gz").exists());
+    assert_eq!(memory.search("Ana", 3).unwrap().len(), 1);
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
@@ -0,0 +1,21 @@
+use redclaw::agent::channels::{session_key, strip_mention};
+use redclaw::agent::session::SessionStore;
+
+#[test]
+fn test_group_and_topic_session_keys_are_valid() {
+    assert_eq!(session_key(42, None), "42");
+    assert_eq!(session_key(-1001234567890, None), "-1001234567890");
+    assert_eq!(session_key(-1001234567890, Some(17)), "-1001234567890-topic-17");
+    for key in [session_key(-1001234567890, None), session_key(-1001234567890, Some(17))] {
+        SessionStore::validate_key(&key).unwrap();
+    }
+}
+
+#[test]
+fn test_strip_mention() {
+    assert_eq!(strip_mention("@RedClaw_Bot what's the weather?", "redclaw_bot").as_deref(), Some("what's the weather?"));
+    assert_eq!(strip_mention("hey @redclaw_bot, ping", "redclaw_bot").as_deref(), Some("hey , ping"));
+    assert_eq!(strip_mention("ask @redclaw_botnet instead", "redclaw_bot"), None);
+    assert_eq!(strip_mention("no mention here", "redclaw_bot"), None);
+    assert_eq!(strip_mention("@someone", ""), None);
+}
@@ -0,0 +1,73 @@
+use redclaw::agent::index::KeywordIndex;
+use redclaw::agent::memory::MemoryStore;
+use std::fs;
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("redclaw_{}_{}", name, std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    dir
+}
+
+#[test]
+fn test_bm25_ranking_across_journal_and_postings() {
+    let dir = temp_dir("index");
+    let index = KeywordIndex::new(&dir);
+
+    index.add(20260101, "We talked about the garden and tomatoes").unwrap();
+    index.add(20260102, "Router firmware upgrade, router rebooted twice").unwrap();
+    index.compact().unwrap();
+    // These stay in the journal and must merge with compacted postings at query time
+    index.add(20260103, "Tomatoes need water; garden hose is broken").unwrap();
+    index.add(20260102, "The router works now").unwrap();
+
+    let hits = index.search("router", 5).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].date, 20260102);
+
+    let hits = index.search("garden tomatoes hose", 5).unwrap();
+    assert_eq!(hits.iter().map(|h| h.date).collect::<Vec<_>>(), vec![20260103, 20260101]);
+
+    index.remove(20260103).unwrap();
+    let hits = index.search("hose", 5).unwrap();
+    assert!(hits.is_empty());
+    assert_eq!(index.search("tomatoes", 5).unwrap()[0].date, 20260101);
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_memory_search_rebuilds_and_returns_snippets() {
+    let workspace = temp_dir("search");
+    let memory = MemoryStore::new(&workspace);
+    let month = workspace.join("memory").join("202403");
+    fs::create_dir_all(&month).unwrap();
+    fs::write(
+        month.join("20240315.md"),
+        "# 2024-03-15\n\nUser: hi\nAssistant: hello\n\nUser: my passport expires in June\nAssistant: Noted, renew it soon.\n",
+    ).unwrap();
+
+    let hits = memory.search("passport", 3).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].date, "2024-03-15");
+    assert_eq!(hits[0].snippet, "User: my passport expires in June\nAssistant: Noted, renew it soon.");
+
+    fs::remove_dir_all(&workspace).ok();
+}
+
+#[test]
+fn test_search_indexes_notes_written_before_the_index() {
+    let workspace = temp_dir("backfill");
+    let memory = MemoryStore::new(&workspace);
+    let month = workspace.join("memory").join("202403");
+    fs::create_dir_all(&month).unwrap();
+    fs::write(month.join("20240310.md"), "# 2024-03-10\n\nUser: the boiler makes a clicking noise\n").unwrap();
+
+    // The first append after an upgrade creates the journal without the older notes
+    memory.append_today("User: booked a plumber for the boiler").unwrap();
+
+    let hits = memory.search("boiler clicking", 3).unwrap();
+    assert_eq!(hits[0].date, "2024-03-10");
+    assert_eq!(hits.len(), 2);
+
+    fs::remove_dir_all(&workspace).ok();
+}
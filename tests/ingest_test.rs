@@ -0,0 +1,16 @@
+use redclaw::agent::channels::transcribe;
+
+#[test]
+fn test_transcribe_substitutes_quoted_path() {
+    let dir = std::env::temp_dir().join(format!("redclaw_voice_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // A quote in the name must not break out of the shell argument
+    let path = dir.join("it's a note.ogg");
+    std::fs::write(&path, "  buy milk and eggs \n").unwrap();
+
+    assert_eq!(transcribe("cat {file}", &path).unwrap(), "buy milk and eggs");
+    assert!(transcribe("cat {file} >/dev/null", &path).unwrap_err().to_string().contains("no text"));
+    assert!(transcribe("echo broken >&2; exit 3", &path).unwrap_err().to_string().contains("broken"));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,52 @@
+use redclaw::agent::markdown::{split_markdown, to_telegram_html};
+
+#[test]
+fn test_inline_formatting_and_escaping() {
+    assert_eq!(
+        to_telegram_html("**Done**: moved *a* & `b<c>` to [the docs](https://example.com/?a=1&b=\"2\")"),
+        "<b>Done</b>: moved <i>a</i> &amp; <code>b&lt;c&gt;</code> to <a href=\"https://example.com/?a=1&amp;b=&quot;2&quot;\">the docs</a>"
+    );
+    assert_eq!(to_telegram_html("~~old~~ and __new__"), "<s>old</s> and <b>new</b>");
+    // Things that only look like markup stay literal
+    assert_eq!(to_telegram_html("call snake_case_name with 2 * 3 * 4"), "call snake_case_name with 2 * 3 * 4");
+    assert_eq!(to_telegram_html("an **unclosed bold"), "an **unclosed bold");
+    assert_eq!(to_telegram_html("[run](javascript:alert(1))"), "[run](javascript:alert(1))");
+    assert_eq!(to_telegram_html(r"1 \* 2"), "1 * 2");
+}
+
+#[test]
+fn test_blocks() {
+    let md = "# Plan\n- first\n  * nested\n> quoted *text*\n> more\n\n```rust\nfn main() { if a < b {} }\n```\ndone";
+    assert_eq!(
+        to_telegram_html(md),
+        "<b>Plan</b>\n• first\n  • nested\n<blockquote>quoted <i>text</i>\nmore</blockquote>\n\n<pre><code class=\"language-rust\">fn main() { if a &lt; b {} }</code></pre>\ndone"
+    );
+    // A fence the model never closed still ends up as a closed block
+    assert_eq!(to_telegram_html("```\nx **y**"), "<pre>x **y**</pre>");
+}
+
+#[test]
+fn test_split_keeps_code_fences_balanced() {
+    let mut md = String::from("Intro line\n```python\n");
+    for i in 0..40 {
+        md.push_str(&format!("print('line {}')\n", i));
+    }
+    md.push_str("```\nOutro");
+
+    let chunks = split_markdown(&md, 200);
+    assert!(chunks.len() > 3);
+    for chunk in &chunks {
+        assert!(chunk.chars().count() <= 200, "chunk too long: {}", chunk.len());
+        assert_eq!(chunk.matches("```").count() % 2, 0, "unbalanced fence in {:?}", chunk);
+    }
+    assert!(chunks[1].starts_with("```python\n"));
+    assert!(chunks.last().unwrap().ends_with("Outro"));
+    let html = to_telegram_html(&chunks[1]);
+    assert!(html.starts_with("<pre><code class=\"language-python\">") && html.ends_with("</code></pre>"));
+
+    // Short text is a single chunk; an over-long line is cut at whitespace
+    assert_eq!(split_markdown("hello", 4000), vec!["hello"]);
+    let long = "word ".repeat(100);
+    let pieces = split_markdown(long.trim(), 64);
+    assert!(pieces.iter().all(|p| p.chars().count() <= 64 && !p.starts_with(' ') && p.ends_with("word")));
+}
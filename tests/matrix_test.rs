@@ -0,0 +1,120 @@
+use redclaw::agent::channels::{Channel, MatrixChannel};
+use redclaw::agent::http;
+use redclaw::agent::session::SessionStore;
+use std::io::BufReader;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+type Seen = Arc<Mutex<Vec<(String, String, String)>>>;
+
+/// A homeserver stub answering the few client-server endpoints the channel uses.
+/// Records each request as (method, path?query, body).
+fn homeserver() -> (String, Seen) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let seen: Seen = Arc::default();
+    let log = seen.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let request = match http::read_request(&mut BufReader::new(&stream)) {
+                Ok(r) => r,
+                Err(_) => continue,
+            };
+            let target = format!("{}?{}", request.path, request.query);
+            log.lock().unwrap().push((request.method.clone(), target, String::from_utf8_lossy(&request.body).to_string()));
+            let (status, body) = if request.header("Authorization") != Some("Bearer tok") {
+                (401, serde_json::json!({ "errcode": "M_UNKNOWN_TOKEN", "error": "Invalid access token" }))
+            } else if request.path == "/_matrix/client/v3/account/whoami" {
+                (200, serde_json::json!({ "user_id": "@bot:localhost" }))
+            } else if request.path == "/_matrix/client/v3/sync" && !request.query.contains("since=") {
+                (200, serde_json::json!({
+                    "next_batch": "s1",
+                    "rooms": {
+                        "join": { "!room:localhost": { "timeline": { "events": [
+                            { "type": "m.room.message", "sender": "@alice:localhost", "content": { "msgtype": "m.text", "body": "old news" } }
+                        ] } } },
+                        "invite": { "!new:localhost": { "invite_state": { "events": [
+                            { "type": "m.room.member", "state_key": "@bot:localhost", "sender": "@alice:localhost", "content": { "membership": "invite" } }
+                        ] } } }
+                    }
+                }))
+            } else if request.path == "/_matrix/client/v3/sync" {
+                (200, serde_json::json!({
+                    "next_batch": "s2",
+                    "rooms": { "join": { "!room:localhost": { "timeline": { "events": [
+                        { "type": "m.room.message", "sender": "@bot:localhost", "content": { "msgtype": "m.text", "body": "my own reply" } },
+                        { "type": "m.room.member", "sender": "@carol:localhost", "content": { "membership": "join" } },
+                        { "type": "m.room.message", "sender": "@alice:localhost", "content": { "msgtype": "m.text", "body": " hello bot " } },
+                        { "type": "m.room.message", "sender": "@mallory:localhost", "content": { "msgtype": "m.text", "body": "let me in" } }
+                    ] } } } }
+                }))
+            } else if request.path.ends_with("/join") {
+                (200, serde_json::json!({ "room_id": "!new:localhost" }))
+            } else if request.method == "PUT" {
+                (200, serde_json::json!({ "event_id": "$1" }))
+            } else {
+                (404, serde_json::json!({ "errcode": "M_UNRECOGNIZED", "error": "Unrecognized request" }))
+            };
+            let _ = http::write_response(&mut stream, status, "application/json", body.to_string().as_bytes());
+        }
+    });
+    (url, seen)
+}
+
+#[test]
+fn test_matrix_sync_join_and_send() {
+    let (url, seen) = homeserver();
+    let dir = std::env::temp_dir().join(format!("redclaw_matrix_{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    let state = dir.join("matrix_sync.json");
+
+    let mut channel = MatrixChannel::new(&format!("{}/", url), "tok", vec!["@alice:localhost".to_string()])
+        .with_state_file(state.clone());
+    channel.connect().unwrap();
+    assert_eq!(channel.user_id(), "@bot:localhost");
+
+    // The first sync skips history but accepts the invite from an allowed user
+    assert!(channel.receive().unwrap().is_empty());
+    assert!(seen.lock().unwrap().iter().any(|(m, p, _)| m == "POST" && p.starts_with("/_matrix/client/v3/rooms/%21new%3Alocalhost/join")));
+
+    let messages = channel.receive().unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].text, "hello bot");
+    assert_eq!(messages[0].conversation, "!room:localhost");
+    assert!(channel.is_allowed(&messages[0]));
+    assert!(!channel.is_allowed(&messages[1]));
+    assert!(seen.lock().unwrap().iter().any(|(_, p, _)| p.contains("since=s1")));
+
+    let key = channel.session_key(&messages[0].conversation);
+    assert_eq!(key, "matrix-room_3Alocalhost");
+    SessionStore::validate_key(&key).unwrap();
+    assert_ne!(channel.session_key("!a:b.org"), channel.session_key("!a_b.org"));
+    SessionStore::validate_key(&channel.session_key("!a_b/ü:b.org")).unwrap();
+
+    channel.send_text("!room:localhost", "**Done**\nsecond line").unwrap();
+    let (_, path, body) = seen.lock().unwrap().iter().rev().find(|(m, _, _)| m == "PUT").cloned().unwrap();
+    assert!(path.starts_with("/_matrix/client/v3/rooms/%21room%3Alocalhost/send/m.room.message/"));
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["msgtype"], "m.text");
+    assert_eq!(body["body"], "**Done**\nsecond line");
+    assert_eq!(body["formatted_body"], "<b>Done</b><br>second line");
+
+    // A restarted channel resumes from the saved position
+    let alice = vec!["@alice:localhost".to_string()];
+    let mut restarted = MatrixChannel::new(&url, "tok", alice.clone()).with_state_file(state);
+    restarted.connect().unwrap();
+    restarted.receive().unwrap();
+    assert!(seen.lock().unwrap().iter().any(|(_, p, _)| p.contains("since=s2")));
+
+    let mut rejected = MatrixChannel::new(&url, "wrong", alice);
+    let err = rejected.connect().unwrap_err();
+    assert!(err.to_string().contains("M_UNKNOWN_TOKEN"), "{}", err);
+
+    // Without an allow list anyone on the federation could invite the bot
+    let mut open = MatrixChannel::new(&url, "tok", Vec::new());
+    let err = open.connect().unwrap_err();
+    assert!(err.to_string().contains("allow_from"), "{}", err);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,217 @@
+use redclaw::agent::memory::{MemoryStore, Remembered};
+
+fn store(name: &str) -> (MemoryStore, std::path::PathBuf) {
+    let workspace = std::env::temp_dir().join(format!("redclaw_{}_{}", name, std::process::id()));
+    std::fs::remove_dir_all(&workspace).ok();
+    (MemoryStore::new(&workspace), workspace)
+}
+
+#[test]
+fn test_remember_updates_and_deduplicates() {
+    let (memory, workspace) = store("remember");
+    memory.write_long_term("# Long-term Memory\n\nHand-written note.\n").unwrap();
+
+    assert_eq!(memory.remember(Some("User"), "name", "Ana").unwrap(), Remembered::Added);
+    assert_eq!(memory.remember(Some("User"), "Name", "Ana").unwrap(), Remembered::Unchanged);
+    assert_eq!(memory.remember(None, "name", "Ana Souza").unwrap(), Remembered::Updated);
+    assert_eq!(memory.remember(Some("Projects"), "redclaw", "Rust agent").unwrap(), Remembered::Added);
+
+    assert_eq!(
+        memory.read_long_term(),
+        "# Long-term Memory\n\nHand-written note.\n\n## User\n\n- **name**: Ana Souza\n\n## Projects\n\n- **redclaw**: Rust agent\n"
+    );
+
+    let found = memory.recall("rust");
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].key, "redclaw");
+    assert_eq!(memory.recall("").len(), 2);
+
+    assert!(memory.forget("NAME").unwrap());
+    assert!(!memory.forget("name").unwrap());
+    assert!(!memory.read_long_term().contains("## User"));
+    assert!(memory.get_memory_context().contains("- **redclaw**: Rust agent"));
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
+
+#[test]
+fn test_curation_keeps_empty_sections() {
+    let (memory, workspace) = store("sections");
+    memory.write_long_term("# Long-term Memory\n\n## User\n\n## Projects\n\n## Preferences\n").unwrap();
+
+    memory.remember(Some("User"), "name", "Ana").unwrap();
+    assert_eq!(
+        memory.read_long_term(),
+        "# Long-term Memory\n\n## User\n\n- **name**: Ana\n\n## Projects\n\n## Preferences\n"
+    );
+    memory.remember(Some("Preferences"), "editor", "helix").unwrap();
+
+    // Only the section forget empties goes
+    assert!(memory.forget("editor").unwrap());
+    assert_eq!(memory.read_long_term(), "# Long-term Memory\n\n## User\n\n- **name**: Ana\n\n## Projects\n");
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
+
+#[test]
+fn test_memory_context_respects_budget() {
+    let (memory, workspace) = store("budget");
+    let memory = memory.with_budget(600);
+    memory.remember(Some("User"), "name", "Ana").unwrap();
+    for i in 0..50 {
+        memory.append_today(&format!("User: question {}\nAssistant: answer {}\n", i, i)).unwrap();
+    }
+
+    let (context, stats) = memory.build_memory_context();
+    assert!(context.contains("- **name**: Ana"));
+    // The newest exchange survives, the oldest is cut
+    assert!(context.contains("answer 49"));
+    assert!(!context.contains("answer 0\n"));
+    assert!(context.contains("(earlier entries omitted)"));
+    assert!(stats.long_term_bytes > 0);
+    assert_eq!(stats.long_term_bytes, stats.long_term_total);
+    assert!(stats.days[0].1 < stats.days[0].2);
+    assert!(stats.days[0].1 + stats.long_term_bytes <= 600);
+    assert!(stats.total_bytes <= 600);
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
+
+#[test]
+fn test_small_memory_budget_is_never_exceeded() {
+    let (global, workspace) = store("small_budget");
+    global.remember(Some("Household"), "wifi", "casa").unwrap();
+    let memory = global.scoped("telegram-user-1");
+    memory.remember(Some("User"), "name", "Ana").unwrap();
+    for i in 0..5 {
+        memory.append_today(&format!("User: question {}\nAssistant: answer {}\n", i, i)).unwrap();
+    }
+
+    for budget in [0, 5, 12, 20, 40, 60, 100, 150] {
+        let (context, stats) = memory.scoped("telegram-user-1").with_budget(budget).build_memory_context();
+        assert!(stats.total_bytes <= budget, "{} bytes for a budget of {}:\n{}", stats.total_bytes, budget, context);
+        assert_eq!(context.len(), stats.total_bytes);
+        assert!(!context.ends_with("## Shared Memory\n\n") && !context.ends_with("## Long-term Memory\n\n"));
+    }
+    assert!(memory.scoped("telegram-user-1").with_budget(0).get_memory_context().is_empty());
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
+
+#[test]
+fn test_scoped_memory_is_isolated_with_shared_layer() {
+    let (global, workspace) = store("scopes");
+    global.remember(Some("Household"), "wifi", "guest network is 'casa'").unwrap();
+    std::fs::write(workspace.join("USER.md"), "Default user profile").unwrap();
+
+    let alice = global.scoped("telegram-user-1");
+    let bob = global.scoped("telegram-user-2");
+    alice.append_today("User: my locker code is 4711\nAssistant: Noted.\n").unwrap();
+    alice.remember(Some("User"), "name", "Alice").unwrap();
+    std::fs::write(workspace.join("memory/scopes/telegram-user-1/USER.md"), "Alice's profile").unwrap();
+
+    let alice_ctx = alice.get_memory_context();
+    assert!(alice_ctx.contains("## Shared Memory"));
+    assert!(alice_ctx.contains("casa"));
+    assert!(alice_ctx.contains("4711"));
+    assert!(alice_ctx.contains("Alice"));
+
+    let bob_ctx = bob.get_memory_context();
+    assert!(bob_ctx.contains("casa"));
+    assert!(!bob_ctx.contains("4711"));
+    assert!(!bob_ctx.contains("Alice"));
+    assert!(bob.search("locker", 3).unwrap().is_empty());
+    assert!(!global.get_memory_context().contains("4711"));
+
+    // Shared entries are visible to every scope but only editable globally
+    assert_eq!(bob.recall("wifi").len(), 1);
+    assert!(!bob.forget("wifi").unwrap());
+    assert_eq!(global.recall("wifi").len(), 1);
+
+    let read = |p: Option<std::path::PathBuf>| std::fs::read_to_string(p.unwrap()).unwrap();
+    assert_eq!(read(alice.bootstrap_file("USER.md")), "Alice's profile");
+    assert_eq!(read(bob.bootstrap_file("USER.md")), "Default user profile");
+    assert!(bob.bootstrap_file("SOUL.md").is_none());
+
+    assert_eq!(global.scopes(), vec!["telegram-user-1".to_string()]);
+
+    // Scope names map to directory names one to one, and can't leave `scopes/`
+    let dotted = global.scoped("matrix-user-@alice.smith:hs.org");
+    let underscored = global.scoped("matrix-user-@alice_smith:hs.org");
+    dotted.append_today("User: my pin is 1234\n").unwrap();
+    assert!(underscored.read_today().is_empty());
+    assert!(underscored.search("pin", 3).unwrap().is_empty());
+    let escaped = global.scoped("../etc");
+    escaped.append_today("note\n").unwrap();
+    assert!(workspace.join("memory/scopes/_2E_2E_2Fetc").is_dir());
+    assert_eq!(global.scopes(), ["../etc", "matrix-user-@alice.smith:hs.org", "telegram-user-1"]);
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
+
+#[test]
+fn test_notes_use_configured_offset_and_timestamps() {
+    let (memory, workspace) = store("offset");
+    let offset = time::UtcOffset::from_hms(-12, 0, 0).unwrap();
+    let memory = memory.with_offset(offset);
+    memory.append_today("User: where is the umbrella\nAssistant: In the hall.\n").unwrap();
+
+    let now = time::OffsetDateTime::now_utc().to_offset(offset);
+    let day = format!("{:04}{:02}{:02}", now.year(), u8::from(now.month()), now.day());
+    let files = memory.daily_files();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].0.to_string(), day);
+
+    let note = memory.read_today();
+    let entry = note.lines().find(|l| l.contains("umbrella")).unwrap();
+    assert!(entry.starts_with('[') && entry[6..].starts_with("] User: where"), "{}", entry);
+
+    let hits = memory.search("umbrella", 1).unwrap();
+    assert!(hits[0].snippet.contains("In the hall."));
+    assert!(!hits[0].snippet.contains("# "));
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
+
+#[test]
+fn test_append_recovers_from_torn_entry() {
+    let (memory, workspace) = store("torn");
+    memory.append_today("User: first\nAssistant: one\n").unwrap();
+    let (_, path) = memory.daily_files().pop().unwrap();
+    // Simulate a crash part-way through the next entry
+    let mut torn = std::fs::read_to_string(&path).unwrap();
+    torn.push_str("[12:00] User: sec");
+    std::fs::write(&path, torn).unwrap();
+
+    memory.append_today("User: third\nAssistant: three\n").unwrap();
+    let note = memory.read_today();
+    assert!(note.starts_with("# "));
+    assert_eq!(note.matches("# ").count(), 1);
+    assert!(note.contains("User: sec\n\n["), "{}", note);
+    assert!(note.ends_with("] User: third\nAssistant: three\n"));
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
+
+#[test]
+fn test_search_sees_every_note_during_rebuilds() {
+    let (memory, workspace) = store("search_race");
+    memory.append_today("User: where is the umbrella\nAssistant: In the hall.\n").unwrap();
+    memory.rebuild_index().unwrap();
+
+    let rebuilder = memory.clone();
+    let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let stop = done.clone();
+    let handle = std::thread::spawn(move || {
+        while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+            rebuilder.rebuild_index().unwrap();
+        }
+    });
+    for _ in 0..200 {
+        assert_eq!(memory.search("umbrella", 1).unwrap().len(), 1);
+    }
+    done.store(true, std::sync::atomic::Ordering::Relaxed);
+    handle.join().unwrap();
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
@@ -13,6 +13,12 @@ fn test_agent_initialization() {
                 max_tokens: 100,
                 temperature: 0.7,
                 max_tool_iterations: 5,
+                vision: false,
+                memory_budget: 8192,
+                timezone: "UTC".to_string(),
+                memory_scope: Default::default(),
+                consolidation: None,
+            stream: false,
             },
         },
         providers: ProvidersConfig {
@@ -26,6 +32,7 @@ fn test_agent_initialization() {
             vllm: None,
         },
         channels: Default::default(),
+        server: None,
         tools: ToolsConfig {
             web: WebToolsConfig {
                 search: WebSearchConfig {
@@ -33,6 +40,9 @@ fn test_agent_initialization() {
                     max_results: 5,
                 },
             },
+            enabled: None,
+            profiles: Default::default(),
+            sessions: Default::default(),
         },
     };
 
@@ -43,7 +53,7 @@ fn test_agent_initialization() {
     );
 
     let registry = ToolRegistry::new();
-    let agent = Agent::new(&config, client, registry);
+    let _agent = Agent::new(&config, client, registry);
     
     // Check if workspace was created
     assert!(std::path::Path::new("/tmp/redclaw_test/memory").exists());
@@ -0,0 +1,126 @@
+use redclaw::config::{ChannelsConfig, ToolsConfig, WebToolsConfig, WebSearchConfig};
+use redclaw::tools::{ToolContext, ToolPolicy};
+use redclaw::tools::registry::ToolRegistry;
+use redclaw::agent::memory::MemoryStore;
+use redclaw::tools::builtin::{ReadFileTool, WriteFileTool, ListDirTool};
+use serde_json::json;
+
+fn tools_config() -> ToolsConfig {
+    ToolsConfig {
+        web: WebToolsConfig {
+            search: WebSearchConfig {
+                api_key: "".to_string(),
+                max_results: 5,
+            },
+        },
+        enabled: None,
+        profiles: Default::default(),
+        sessions: Default::default(),
+    }
+}
+
+fn registry() -> ToolRegistry {
+    let mut registry = ToolRegistry::new();
+    registry.register(Box::new(ReadFileTool));
+    registry.register(Box::new(WriteFileTool));
+    registry.register(Box::new(ListDirTool));
+    registry
+}
+
+#[test]
+fn test_readonly_profile_hides_and_refuses_write_tools() {
+    let policy = ToolPolicy::resolve(&tools_config(), Some("readonly"));
+    let registry = registry();
+
+    let names: Vec<String> = registry.get_definitions(&policy).into_iter().map(|d| d.function.name).collect();
+    assert_eq!(names, vec!["read_file", "list_dir"]);
+    assert!(!policy.permits("schedule"));
+
+    let err = registry.execute("write_file", json!({"path": "/tmp/x", "content": "x"}), &ToolContext { policy, ..Default::default() }).unwrap_err();
+    assert!(err.to_string().contains("not permitted"));
+}
+
+#[test]
+fn test_enabled_list_intersects_profiles() {
+    let mut config = tools_config();
+    config.enabled = Some(vec!["read_file".to_string(), "write_file".to_string()]);
+    config.profiles.insert("writer".to_string(), vec!["*".to_string()]);
+
+    let policy = ToolPolicy::resolve(&config, Some("writer"));
+    assert!(policy.permits("write_file"));
+    assert!(!policy.permits("list_dir"));
+
+    let policy = ToolPolicy::resolve(&config, Some("readonly"));
+    assert!(policy.permits("read_file"));
+    assert!(!policy.permits("write_file"));
+
+    let policy = ToolPolicy::resolve(&config, Some("no-such-profile"));
+    assert!(!policy.permits("read_file"));
+}
+
+#[test]
+fn test_bot_channels_default_to_readonly() {
+    let channels: ChannelsConfig = serde_json::from_value(json!({
+        "telegram": { "enabled": true, "token": "t", "allow_from": ["1"] },
+        "matrix": { "enabled": true, "homeserver": "https://hs.org", "access_token": "t", "allow_from": ["@a:hs.org"] }
+    })).unwrap();
+    assert_eq!(channels.telegram.unwrap().tool_profile.as_deref(), Some("readonly"));
+    assert_eq!(channels.matrix.unwrap().tool_profile.as_deref(), Some("readonly"));
+
+    let channels: ChannelsConfig = serde_json::from_value(json!({
+        "telegram": { "enabled": true, "token": "t", "allow_from": ["1"], "tool_profile": "full" }
+    })).unwrap();
+    let profile = channels.telegram.unwrap().tool_profile;
+    assert!(ToolPolicy::resolve(&tools_config(), profile.as_deref()).permits("exec"));
+}
+
+#[test]
+fn test_file_tools_refuse_other_scopes_sessions_and_inboxes() {
+    let workspace = std::env::temp_dir().join(format!("redclaw_policy_scopes_{}", std::process::id()));
+    std::fs::remove_dir_all(&workspace).ok();
+    let memory = MemoryStore::new(&workspace);
+    memory.scoped("telegram-user-1").append_today("User: my locker code is 4711\n").unwrap();
+    memory.scoped("telegram-user-2").append_today("User: hi\n").unwrap();
+    for file in ["sessions/100.jsonl", "sessions/200.jsonl", "inbox/100/photo.jpg", "inbox/200/photo.jpg"] {
+        std::fs::create_dir_all(workspace.join(file).parent().unwrap()).unwrap();
+        std::fs::write(workspace.join(file), "x").unwrap();
+    }
+    let alice_notes = std::fs::read_dir(workspace.join("memory/scopes/telegram-user-1")).unwrap()
+        .flatten().find(|e| e.path().is_dir() && e.file_name() != "index").unwrap().path();
+    let alice_note = std::fs::read_dir(&alice_notes).unwrap().flatten().next().unwrap().path();
+
+    let registry = registry();
+    let bob = ToolContext {
+        session_key: "200".to_string(),
+        memory_scope: Some("telegram-user-2".to_string()),
+        workspace: Some(workspace.clone()),
+        ..Default::default()
+    };
+    let read = |ctx: &ToolContext, path: &std::path::Path| registry.execute("read_file", json!({ "path": path }), ctx).unwrap();
+    let list = |ctx: &ToolContext, path: &std::path::Path| registry.execute("list_dir", json!({ "path": path }), ctx).unwrap();
+
+    let refused = read(&bob, &alice_note);
+    assert!(refused.is_error);
+    assert!(refused.to_text().contains("Access denied"), "{}", refused.to_text());
+    // The same file reached through `..` and through a symlink
+    assert!(read(&bob, &workspace.join("inbox/200/../../memory/scopes/telegram-user-1").join(alice_note.strip_prefix(workspace.join("memory/scopes/telegram-user-1")).unwrap())).is_error);
+    std::os::unix::fs::symlink(&alice_notes, workspace.join("inbox/200/link")).unwrap();
+    assert!(list(&bob, &workspace.join("inbox/200/link")).is_error);
+
+    assert!(list(&bob, &workspace.join("memory/scopes")).is_error);
+    assert!(read(&bob, &workspace.join("sessions/100.jsonl")).is_error);
+    assert!(read(&bob, &workspace.join("inbox/100/photo.jpg")).is_error);
+    assert!(registry.execute("write_file", json!({ "path": workspace.join("memory/scopes/telegram-user-1/MEMORY.md"), "content": "x" }), &bob).unwrap().is_error);
+
+    // Bob's own data and the rest of the workspace stay readable
+    assert!(!read(&bob, &workspace.join("sessions/200.jsonl")).is_error);
+    assert!(!read(&bob, &workspace.join("inbox/200/photo.jpg")).is_error);
+    assert!(!list(&bob, &workspace.join("memory/scopes/telegram-user-2")).is_error);
+    assert!(!list(&bob, &workspace).is_error);
+
+    // Without a scope (the CLI, or memory_scope global) nothing is off limits
+    let cli = ToolContext { workspace: Some(workspace.clone()), ..Default::default() };
+    assert!(!read(&cli, &alice_note).is_error);
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
@@ -0,0 +1,31 @@
+use redclaw::agent::channels::{backoff_delay, read_offset, write_offset};
+use std::time::Duration;
+
+#[test]
+fn test_offset_persists_across_restarts() {
+    let dir = std::env::temp_dir().join(format!("redclaw_offset_{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    let path = dir.join("telegram_offset.json");
+
+    assert_eq!(read_offset(&path), 0);
+    write_offset(&path, 812345).unwrap();
+    assert_eq!(read_offset(&path), 812345);
+    write_offset(&path, 812350).unwrap();
+    assert_eq!(read_offset(&path), 812350);
+
+    // A damaged file falls back to whatever Telegram still holds
+    std::fs::write(&path, "{\"off").unwrap();
+    assert_eq!(read_offset(&path), 0);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_backoff_doubles_up_to_a_minute() {
+    assert_eq!(backoff_delay(0), Duration::ZERO);
+    assert_eq!(backoff_delay(1), Duration::from_secs(1));
+    assert_eq!(backoff_delay(2), Duration::from_secs(2));
+    assert_eq!(backoff_delay(5), Duration::from_secs(16));
+    assert_eq!(backoff_delay(7), Duration::from_secs(60));
+    assert_eq!(backoff_delay(100), Duration::from_secs(60));
+}
@@ -0,0 +1,42 @@
+use redclaw::tools::registry::ToolRegistry;
+use redclaw::tools::ToolPolicy;
+use redclaw::tools::builtin::{ReadFileTool, WriteFileTool, ListDirTool, SysInfoTool};
+
+fn names(registry: &ToolRegistry) -> Vec<String> {
+    registry.get_definitions(&ToolPolicy::default()).into_iter().map(|d| d.function.name).collect()
+}
+
+#[test]
+fn test_definitions_keep_registration_order() {
+    let mut registry = ToolRegistry::new();
+    registry.register(Box::new(WriteFileTool));
+    registry.register(Box::new(SysInfoTool));
+    registry.register(Box::new(ReadFileTool));
+    registry.register(Box::new(ListDirTool));
+
+    let expected = vec!["write_file", "get_sys_info", "read_file", "list_dir"];
+    for _ in 0..5 {
+        assert_eq!(names(&registry), expected);
+    }
+
+    // Re-registering replaces in place
+    registry.register(Box::new(SysInfoTool));
+    assert_eq!(names(&registry), expected);
+    assert_eq!(registry.len(), 4);
+}
+
+#[test]
+fn test_lookup_list_and_unregister() {
+    let mut registry = ToolRegistry::new();
+    registry.register(Box::new(ReadFileTool));
+    registry.register(Box::new(ListDirTool));
+
+    assert_eq!(registry.get("list_dir").map(|t| t.name()), Some("list_dir"));
+    assert!(registry.get("exec").is_none());
+    assert_eq!(registry.list()[0].function.description, "Read the contents of a file (limit 256KB for safety)");
+
+    let removed = registry.unregister("read_file").unwrap();
+    assert_eq!(removed.name(), "read_file");
+    assert!(registry.unregister("read_file").is_none());
+    assert_eq!(names(&registry), vec!["list_dir"]);
+}
@@ -0,0 +1,81 @@
+use redclaw::agent::scheduler::{Cron, Job, Schedule, Scheduler, parse_at, format_time};
+use time::macros::datetime;
+
+#[test]
+fn test_cron_next_occurrence() {
+    let monday_nine = Cron::parse("0 9 * * mon").unwrap();
+    // 2026-10-18 is a Sunday
+    let next = monday_nine.next_after(datetime!(2026-10-18 10:30 -03:00)).unwrap();
+    assert_eq!(format_time(next), "2026-10-19 09:00");
+    let next = monday_nine.next_after(next).unwrap();
+    assert_eq!(format_time(next), "2026-10-26 09:00");
+
+    let every_15 = Cron::parse("*/15 * * * *").unwrap();
+    assert_eq!(format_time(every_15.next_after(datetime!(2026-10-18 23:50 UTC)).unwrap()), "2026-10-19 00:00");
+
+    let leap = Cron::parse("0 0 29 feb *").unwrap();
+    assert_eq!(format_time(leap.next_after(datetime!(2026-03-01 00:00 UTC)).unwrap()), "2028-02-29 00:00");
+
+    assert!(Cron::parse("61 * * * *").is_err());
+    assert!(Cron::parse("* * *").is_err());
+}
+
+#[test]
+fn test_parse_one_shot_times() {
+    let now = datetime!(2026-10-18 21:00 -03:00);
+    assert_eq!(format_time(parse_at("09:00", now).unwrap()), "2026-10-19 09:00");
+    assert_eq!(format_time(parse_at("21:30", now).unwrap()), "2026-10-18 21:30");
+    assert_eq!(format_time(parse_at("2026-12-24 18:00", now).unwrap()), "2026-12-24 18:00");
+    assert!(parse_at("25:00", now).is_err());
+}
+
+#[test]
+fn test_take_due_rearms_cron_and_drops_one_shots() {
+    let workspace = std::env::temp_dir().join(format!("redclaw_sched_{}", std::process::id()));
+    let scheduler = Scheduler::new(&workspace);
+    let now = datetime!(2026-10-19 09:00 UTC).unix_timestamp();
+
+    let job = |prompt: &str, schedule: Schedule, channel: &str| Job {
+        id: String::new(),
+        prompt: prompt.to_string(),
+        schedule,
+        timezone: "UTC".to_string(),
+        session_key: "42".to_string(),
+        channel: Some(channel.to_string()),
+        chat_id: Some("42".to_string()),
+        user_id: None,
+        next_run: now,
+        created_at: now,
+        action: None,
+    };
+    scheduler.add(job("weekly", Schedule::Cron { expr: "0 9 * * mon".to_string() }, "telegram")).unwrap();
+    scheduler.add(job("once", Schedule::Once { at: now }, "telegram")).unwrap();
+    scheduler.add(job("elsewhere", Schedule::Once { at: now }, "matrix")).unwrap();
+
+    let due = scheduler.take_due("telegram", now).unwrap();
+    assert_eq!(due.iter().map(|j| j.prompt.as_str()).collect::<Vec<_>>(), vec!["weekly", "once"]);
+
+    let left = scheduler.load();
+    assert_eq!(left.len(), 2);
+    assert_eq!(left[0].next_run, datetime!(2026-10-26 09:00 UTC).unix_timestamp());
+    assert!(scheduler.take_due("telegram", now).unwrap().is_empty());
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
+
+#[test]
+fn test_corrupt_schedule_is_quarantined() {
+    let workspace = std::env::temp_dir().join(format!("redclaw_sched_corrupt_{}", std::process::id()));
+    std::fs::remove_dir_all(&workspace).ok();
+    std::fs::create_dir_all(&workspace).unwrap();
+    std::fs::write(workspace.join("schedule.json"), "[{\"id\": \"ab").unwrap();
+
+    let scheduler = Scheduler::new(&workspace);
+    assert!(scheduler.load().is_empty());
+    assert!(!workspace.join("schedule.json").exists());
+    let kept = std::fs::read_dir(&workspace).unwrap().flatten()
+        .any(|e| e.file_name().to_string_lossy().starts_with("schedule.json.corrupt-"));
+    assert!(kept);
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
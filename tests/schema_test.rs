@@ -0,0 +1,52 @@
+use redclaw::tools::schema::{parse_arguments, validate};
+use redclaw::tools::registry::ToolRegistry;
+use redclaw::tools::ToolContext;
+use redclaw::tools::builtin::{ReadFileTool, WebSearchTool};
+use serde_json::json;
+
+#[test]
+fn test_parse_arguments_repairs_common_mistakes() {
+    assert_eq!(parse_arguments(r#"{"path": "a.txt",}"#).unwrap(), json!({"path": "a.txt"}));
+    assert_eq!(parse_arguments("{'path': 'it\\'s.txt'}").unwrap(), json!({"path": "it's.txt"}));
+    assert_eq!(parse_arguments("```json\n{\"count\": 3}\n```").unwrap(), json!({"count": 3}));
+    assert_eq!(parse_arguments("{flag: True, other: None}").unwrap(), json!({"flag": true, "other": null}));
+    assert_eq!(parse_arguments("").unwrap(), json!({}));
+    assert_eq!(parse_arguments("\"{\\\"path\\\": \\\"x\\\"}\"").unwrap(), json!({"path": "x"}));
+    assert!(parse_arguments("{path: [").is_err());
+}
+
+#[test]
+fn test_validate_reports_precise_errors() {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "query": { "type": "string", "minLength": 1 },
+            "count": { "type": "integer", "minimum": 1, "maximum": 10 },
+            "mode": { "type": "string", "enum": ["fast", "slow"] }
+        },
+        "required": ["query"]
+    });
+
+    assert!(validate(&schema, &json!({"query": "rust", "count": 5})).is_ok());
+
+    let err = validate(&schema, &json!({"count": "5"})).unwrap_err().to_string();
+    assert!(err.contains("missing required property 'query'"));
+    assert!(err.contains("$.count: expected integer, got string"));
+
+    let err = validate(&schema, &json!({"query": "x", "count": 11, "mode": "medium"})).unwrap_err().to_string();
+    assert!(err.contains("$.count: must be <= 10"));
+    assert!(err.contains("$.mode: must be one of"));
+}
+
+#[test]
+fn test_registry_rejects_invalid_arguments() {
+    let mut registry = ToolRegistry::new();
+    registry.register(Box::new(ReadFileTool));
+    registry.register(Box::new(WebSearchTool { api_key: String::new(), max_results: 5 }));
+
+    let err = registry.execute("read_file", json!({"path": 42}), &ToolContext::default()).unwrap_err().to_string();
+    assert!(err.starts_with("Invalid arguments for read_file"));
+
+    let err = registry.execute("web_search", json!({"query": "x", "count": 0}), &ToolContext::default()).unwrap_err().to_string();
+    assert!(err.contains("must be >= 1"));
+}
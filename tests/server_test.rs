@@ -0,0 +1,187 @@
+use redclaw::config::{Config, AgentsConfig, AgentDefaults, ProvidersConfig, ToolsConfig, WebToolsConfig, WebSearchConfig, ProviderConfig};
+use redclaw::agent::Agent;
+use redclaw::agent::http;
+use redclaw::agent::llm::LLMClient;
+use redclaw::agent::server::ApiServer;
+use redclaw::tools::registry::ToolRegistry;
+use std::io::{BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+/// An LLM provider stub that always answers "Hello from stub", streamed on request.
+/// Asked to "take your time", it waits half a second first.
+fn provider() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let request = match http::read_request(&mut BufReader::new(&stream)) {
+                Ok(r) => r,
+                Err(_) => continue,
+            };
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap_or_default();
+            if body.to_string().contains("take your time") {
+                std::thread::sleep(std::time::Duration::from_millis(500));
+            }
+            if body["stream"] == true {
+                let events = concat!(
+                    "data: {\"model\":\"stub-model\",\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"Hello \"}}]}\n\n",
+                    "data: {\"choices\":[{\"delta\":{\"content\":\"from stub\"}}]}\n\n",
+                    "data: [DONE]\n\n",
+                );
+                let _ = http::write_response(&mut stream, 200, "text/event-stream", events.as_bytes());
+            } else {
+                let reply = serde_json::json!({
+                    "model": "stub-model",
+                    "choices": [{ "message": { "role": "assistant", "content": "Hello from stub" }, "finish_reason": "stop" }],
+                    "usage": { "prompt_tokens": 10, "completion_tokens": 3, "total_tokens": 13 },
+                });
+                let _ = http::write_response(&mut stream, 200, "application/json", reply.to_string().as_bytes());
+            }
+        }
+    });
+    url
+}
+
+fn agent(workspace: &std::path::Path, api_base: &str) -> Agent {
+    let config = Config {
+        agents: AgentsConfig {
+            defaults: AgentDefaults {
+                workspace: workspace.to_string_lossy().to_string(),
+                model: "stub-model".to_string(),
+                max_tokens: 100,
+                temperature: 0.7,
+                max_tool_iterations: 3,
+                vision: false,
+                memory_budget: 8192,
+                timezone: "UTC".to_string(),
+                memory_scope: Default::default(),
+                consolidation: None,
+                stream: false,
+            },
+        },
+        providers: ProvidersConfig {
+            openai: Some(ProviderConfig { api_key: "fake-key".to_string(), api_base: Some(api_base.to_string()) }),
+            gemini: None,
+            openrouter: None,
+            zhipu: None,
+            vllm: None,
+        },
+        channels: Default::default(),
+        server: None,
+        tools: ToolsConfig {
+            web: WebToolsConfig { search: WebSearchConfig { api_key: "".to_string(), max_results: 5 } },
+            enabled: None,
+            profiles: Default::default(),
+            sessions: Default::default(),
+        },
+    };
+    let client = LLMClient::new(config.providers.openai.as_ref().unwrap(), api_base, "stub-model");
+    Agent::new(&config, client, ToolRegistry::new())
+}
+
+/// Sends one request and returns the status code and body.
+fn call(addr: SocketAddr, method: &str, path: &str, token: Option<&str>, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut request = format!("{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n", method, path, body.len());
+    if let Some(t) = token {
+        request.push_str(&format!("Authorization: Bearer {}\r\n", t));
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response[9..12].parse().unwrap();
+    let body = response.split_once("\r\n\r\n").map(|(_, b)| b.to_string()).unwrap_or_default();
+    (status, body)
+}
+
+#[test]
+fn test_chat_completions_and_sessions() {
+    let workspace = std::env::temp_dir().join(format!("redclaw_server_{}", std::process::id()));
+    std::fs::remove_dir_all(&workspace).ok();
+    let agent = agent(&workspace, &provider());
+
+    assert!(ApiServer::bind("127.0.0.1:0", " ").is_err());
+    let server = ApiServer::bind("127.0.0.1:0", "s3cret").unwrap();
+    let addr = server.local_addr().unwrap();
+    std::thread::spawn(move || server.run(agent));
+
+    let ask = r#"{"model":"any","session":"svc","messages":[{"role":"system","content":"ignored"},{"role":"user","content":[{"type":"text","text":"Hi there"}]}]}"#;
+    assert_eq!(call(addr, "POST", "/v1/chat/completions", None, ask).0, 401);
+    assert_eq!(call(addr, "POST", "/v1/chat/completions", Some("wrong"), ask).0, 401);
+
+    let (status, body) = call(addr, "POST", "/v1/chat/completions", Some("s3cret"), ask);
+    assert_eq!(status, 200, "{}", body);
+    let reply: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(reply["object"], "chat.completion");
+    assert_eq!(reply["choices"][0]["message"]["content"], "Hello from stub");
+
+    let streamed = ask.replace(r#""model":"any","#, r#""model":"any","stream":true,"#);
+    let (status, body) = call(addr, "POST", "/v1/chat/completions", Some("s3cret"), &streamed);
+    assert_eq!(status, 200);
+    assert!(body.trim_end().ends_with("data: [DONE]"), "{}", body);
+    let chunks: Vec<serde_json::Value> = body.lines()
+        .filter_map(|l| l.strip_prefix("data: "))
+        .filter(|d| *d != "[DONE]")
+        .map(|d| serde_json::from_str(d).unwrap())
+        .collect();
+    let text: String = chunks.iter().filter_map(|c| c["choices"][0]["delta"]["content"].as_str()).collect();
+    assert_eq!(text, "Hello from stub");
+    assert!(chunks.iter().all(|c| c["object"] == "chat.completion.chunk"));
+    assert_eq!(chunks.last().unwrap()["choices"][0]["finish_reason"], "stop");
+
+    let bad = r#"{"session":"../etc","messages":[{"role":"user","content":"hi"}]}"#;
+    assert_eq!(call(addr, "POST", "/v1/chat/completions", Some("s3cret"), bad).0, 400);
+    assert_eq!(call(addr, "POST", "/v1/chat/completions", Some("s3cret"), r#"{"messages":[]}"#).0, 400);
+
+    let (status, body) = call(addr, "GET", "/v1/sessions", Some("s3cret"), "");
+    assert_eq!(status, 200);
+    let list: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(list["data"][0]["id"], "svc");
+    assert_eq!(list["data"][0]["messages"], 4);
+
+    let (status, body) = call(addr, "GET", "/v1/sessions/svc", Some("s3cret"), "");
+    assert_eq!(status, 200);
+    let session: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(session["messages"][0]["message"]["content"], "Hi there");
+
+    assert_eq!(call(addr, "DELETE", "/v1/sessions/svc", Some("s3cret"), "").0, 200);
+    assert_eq!(call(addr, "GET", "/v1/sessions/svc", Some("s3cret"), "").0, 404);
+    assert_eq!(call(addr, "GET", "/v1/nothing", Some("s3cret"), "").0, 404);
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
+
+#[test]
+fn test_busy_server_answers_429() {
+    let workspace = std::env::temp_dir().join(format!("redclaw_server_busy_{}", std::process::id()));
+    std::fs::remove_dir_all(&workspace).ok();
+    let agent = agent(&workspace, &provider());
+
+    let server = ApiServer::bind("127.0.0.1:0", "s3cret").unwrap().with_max_concurrent(1);
+    let addr = server.local_addr().unwrap();
+    std::thread::spawn(move || server.run(agent));
+
+    // Clients that never send a request, or send a wrong token, don't take the only place
+    let idle: Vec<TcpStream> = (0..3).map(|_| TcpStream::connect(addr).unwrap()).collect();
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert_eq!(call(addr, "GET", "/v1/models", Some("wrong"), "").0, 401);
+    assert_eq!(call(addr, "GET", "/v1/models", Some("s3cret"), "").0, 200);
+
+    // A request in progress does
+    let slow = std::thread::spawn(move || {
+        call(addr, "POST", "/v1/chat/completions", Some("s3cret"), r#"{"messages":[{"role":"user","content":"take your time"}]}"#)
+    });
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    let (status, body) = call(addr, "GET", "/v1/models", Some("s3cret"), "");
+    assert_eq!(status, 429);
+    assert!(body.contains("rate_limit_exceeded"), "{}", body);
+
+    assert_eq!(slow.join().unwrap().0, 200);
+    assert_eq!(call(addr, "GET", "/v1/models", Some("s3cret"), "").0, 200);
+    drop(idle);
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
@@ -0,0 +1,202 @@
+use redclaw::agent::session::{SessionLog, SessionStore, StoredMessage, archived_key, export_json, export_markdown};
+use redclaw::agent::llm::{Message, Usage};
+use std::io::Write;
+
+fn message(role: &str, text: &str) -> Message {
+    Message {
+        role: role.to_string(),
+        content: Some(text.into()),
+        name: None,
+        tool_call_id: None,
+        tool_calls: None,
+    }
+}
+
+fn stored(role: &str, text: &str) -> StoredMessage {
+    StoredMessage::new(message(role, text))
+}
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("redclaw_{}_{}", name, std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_append_and_read_recent_from_tail() {
+    let dir = temp_dir("jsonl");
+    let log = SessionLog::open(&dir, "chat").unwrap();
+    assert!(log.recent(10).unwrap().is_empty());
+
+    // Long messages make the tail span several read blocks
+    let filler = "x".repeat(1500);
+    for i in 0..30 {
+        log.append(&[stored("user", &format!("q{} {}", i, filler)), stored("assistant", &format!("a{}", i))]).unwrap();
+    }
+    let recent = log.recent(5).unwrap();
+    assert_eq!(recent.len(), 5);
+    assert_eq!(recent[4].message.text(), "a29");
+    assert_eq!(recent[0].message.text(), "a27");
+    assert_eq!(recent.iter().filter(|m| m.message.role == "assistant").count(), 3);
+    assert_eq!(log.all().unwrap().len(), 60);
+
+    // A torn line from a crash is skipped and doesn't swallow the next append
+    std::fs::OpenOptions::new().append(true).open(log.path()).unwrap()
+        .write_all(br#"{"role":"user","cont"#).unwrap();
+    log.append(&[stored("user", "after crash")]).unwrap();
+    let recent = log.recent(2).unwrap();
+    assert_eq!(recent.len(), 1);
+    assert_eq!(recent[0].message.text(), "after crash");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_migrates_legacy_json_and_quarantines_corrupt() {
+    let dir = temp_dir("legacy");
+    std::fs::write(dir.join("42.json"), r#"{"messages":[{"role":"user","content":"hi"},{"role":"assistant","content":"hello"}]}"#).unwrap();
+    let log = SessionLog::open(&dir, "42").unwrap();
+    assert!(!dir.join("42.json").exists());
+    let all = log.all().unwrap();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[1].message.text(), "hello");
+
+    std::fs::write(dir.join("7.json"), r#"{"messages":[{"role":"user","content":"hi"#).unwrap();
+    let log = SessionLog::open(&dir, "7").unwrap();
+    assert!(log.all().unwrap().is_empty());
+    let quarantined = std::fs::read_dir(&dir).unwrap().flatten()
+        .any(|e| e.file_name().to_string_lossy().starts_with("7.json.corrupt-"));
+    assert!(quarantined);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_rotation_archives_and_keeps_recent() {
+    let dir = temp_dir("rotate");
+    let log = SessionLog::open(&dir, "big").unwrap();
+    let filler = "y".repeat(4000);
+    for i in 0..80 {
+        log.append(&[stored("user", &format!("m{} {}", i, filler))]).unwrap();
+    }
+    let size = std::fs::metadata(log.path()).unwrap().len();
+    assert!(size <= 256 * 1024, "log was not rotated: {} bytes", size);
+    let archived = std::fs::read_dir(dir.join("archive")).unwrap().count();
+    assert!(archived >= 1);
+    assert!(log.all().unwrap().len() >= 20);
+    assert!(log.recent(1).unwrap()[0].message.text().starts_with("m79 "));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_store_list_fork_remove_and_export() {
+    let workspace = temp_dir("store");
+    let store = SessionStore::new(&workspace);
+    let log = store.open("alpha").unwrap();
+    let mut call = message("assistant", "");
+    call.content = None;
+    call.tool_calls = Some(serde_json::json!([
+        { "id": "c1", "type": "function", "function": { "name": "read_file", "arguments": "{\"path\":\"notes.txt\"}" } }
+    ]));
+    let mut result = message("tool", "buy milk");
+    result.name = Some("read_file".to_string());
+    result.tool_call_id = Some("c1".to_string());
+    log.append(&[stored("user", "what is in my notes?"), StoredMessage::new(call), StoredMessage::new(result), stored("assistant", "Buy milk.")]).unwrap();
+
+    assert!(store.open("../etc").is_err());
+    store.fork("alpha", "beta").unwrap();
+    assert!(store.fork("alpha", "beta").is_err());
+    assert!(store.fork("missing", "gamma").is_err());
+
+    let listed = store.list().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert!(listed.iter().all(|s| s.messages == 4));
+
+    let messages = store.open("beta").unwrap().all().unwrap();
+    let md = export_markdown("beta", &messages);
+    assert!(md.starts_with("# Session beta\n"));
+    assert!(md.contains("## User · "));
+    assert!(md.contains(" UTC\n\nwhat is in my notes?"));
+    assert!(md.contains("**Tool call** `read_file`:\n\n```json\n{\"path\":\"notes.txt\"}\n```"));
+    assert!(md.contains("**Tool result** `read_file`:\n\n```\nbuy milk\n```"));
+    let json: serde_json::Value = serde_json::from_str(&export_json(&messages).unwrap()).unwrap();
+    assert_eq!(json[1]["message"]["tool_calls"][0]["function"]["name"], "read_file");
+    assert_eq!(json[2]["message"]["tool_call_id"], "c1");
+
+    assert!(store.remove("alpha").unwrap());
+    assert!(!store.remove("alpha").unwrap());
+    assert_eq!(store.list().unwrap().len(), 1);
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
+
+#[test]
+fn test_envelope_metadata_roundtrip_and_bare_lines() {
+    let dir = temp_dir("envelope");
+    let log = SessionLog::open(&dir, "meta").unwrap();
+    // A line from before envelopes existed
+    std::fs::write(log.path(), "{\"role\":\"user\",\"content\":\"old\"}\n").unwrap();
+
+    let reply = StoredMessage {
+        model: Some("gpt-x".to_string()),
+        usage: Some(Usage { prompt_tokens: 90, completion_tokens: 10, total_tokens: 100 }),
+        duration_ms: Some(850),
+        ..stored("assistant", "new")
+    };
+    let question = StoredMessage {
+        channel: Some("telegram".to_string()),
+        user_id: Some("42".to_string()),
+        ..stored("user", "hi")
+    };
+    log.append(&[question, reply]).unwrap();
+
+    let all = log.all().unwrap();
+    assert_eq!(all.len(), 3);
+    assert_eq!(all[0].ts, 0);
+    assert_eq!(all[0].message.text(), "old");
+    assert_eq!(all[1].user_id.as_deref(), Some("42"));
+    assert!(all[1].ts > 0);
+    assert_eq!(all[2].usage.unwrap().total_tokens, 100);
+
+    // The wire message carries no envelope fields
+    let wire = serde_json::to_value(&all[2].message).unwrap();
+    assert_eq!(wire, serde_json::json!({ "role": "assistant", "content": "new" }));
+
+    let md = export_markdown("meta", &all);
+    assert!(md.contains("## Assistant · "));
+    assert!(md.contains(" · gpt-x · 100 tokens · 850 ms\n\nnew"));
+    assert!(md.contains("## User\n\nold"));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_remove_keeps_archives_of_keys_sharing_a_prefix() {
+    let workspace = temp_dir("remove_prefix");
+    let store = SessionStore::new(&workspace);
+    for key in ["foo", "foo-1", "-100", "-100123", "-100123-topic-5"] {
+        let log = store.open(key).unwrap();
+        log.append(&[stored("user", key)]).unwrap();
+        log.rotate().unwrap();
+        log.append(&[stored("user", key)]).unwrap();
+    }
+    let archive = workspace.join("sessions").join("archive");
+    std::fs::write(archive.join("foo-1700000000-2.jsonl"), "").unwrap();
+
+    assert_eq!(archived_key("foo-1-1700000000.jsonl"), Some("foo-1"));
+    assert_eq!(archived_key("foo-1700000000-2.jsonl"), Some("foo"));
+    assert_eq!(archived_key("-100123-1700000000.jsonl"), Some("-100123"));
+    assert_eq!(archived_key("notes.jsonl"), None);
+
+    assert!(store.remove("foo").unwrap());
+    assert!(store.remove("-100").unwrap());
+    let mut left: Vec<String> = std::fs::read_dir(&archive).unwrap()
+        .map(|e| archived_key(&e.unwrap().file_name().to_string_lossy()).unwrap().to_string())
+        .collect();
+    left.sort();
+    assert_eq!(left, vec!["-100123", "-100123-topic-5", "foo-1"]);
+
+    std::fs::remove_dir_all(&workspace).ok();
+}
@@ -0,0 +1,45 @@
+use redclaw::agent::llm::read_stream;
+
+#[test]
+fn test_read_stream_joins_text_and_tool_calls() {
+    let body = concat!(
+        ": keep-alive\n",
+        "data: {\"model\":\"gpt-x\",\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{\"content\":\"Let me \"}}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{\"content\":\"check.\"}}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"c1\",\"type\":\"function\",\"function\":{\"name\":\"exec\",\"arguments\":\"{\\\"comm\"}}]}}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":1,\"id\":\"c2\",\"function\":{\"name\":\"read_file\",\"arguments\":\"{}\"}}]}}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"and\\\":\\\"ls\\\"}\"}}]}}]}\n\n",
+        "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":5,\"total_tokens\":17}}\n\n",
+        "data: [DONE]\n\n",
+    );
+    let mut seen = Vec::new();
+    let (message, model, usage) = read_stream(body.as_bytes(), &mut |t| seen.push(t.to_string())).unwrap();
+
+    assert_eq!(seen, vec!["Let me ", "check."]);
+    assert_eq!(message.text(), "Let me check.");
+    assert_eq!(model.as_deref(), Some("gpt-x"));
+    assert_eq!(usage.unwrap().total_tokens, 17);
+    let calls = message.tool_calls.unwrap();
+    assert_eq!(calls[0]["id"], "c1");
+    assert_eq!(calls[0]["function"]["name"], "exec");
+    assert_eq!(calls[0]["function"]["arguments"], "{\"command\":\"ls\"}");
+    assert_eq!(calls[1]["function"]["name"], "read_file");
+}
+
+#[test]
+fn test_read_stream_reports_errors() {
+    let plain = "{\"error\":{\"message\":\"Invalid API key\"}}\n";
+    let err = read_stream(plain.as_bytes(), &mut |_| {}).unwrap_err();
+    assert!(err.to_string().contains("Invalid API key"), "{}", err);
+
+    let midway = "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\ndata: {\"error\":{\"message\":\"overloaded\"}}\n\n";
+    let err = read_stream(midway.as_bytes(), &mut |_| {}).unwrap_err();
+    assert!(err.to_string().contains("overloaded"), "{}", err);
+
+    // Only tool calls: no empty text content alongside them
+    let calls = "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"c1\",\"function\":{\"name\":\"exec\",\"arguments\":\"{}\"}}]}}]}\n\ndata: [DONE]\n";
+    let (message, _, usage) = read_stream(calls.as_bytes(), &mut |_| {}).unwrap();
+    assert!(message.content.is_none());
+    assert!(usage.is_none());
+}
@@ -0,0 +1,130 @@
+use redclaw::agent::channels::{Channel, Fatal, TelegramBot, read_offset};
+use redclaw::agent::http;
+use redclaw::agent::session::SessionStore;
+use serde_json::{Value, json};
+use std::io::BufReader;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+type Seen = Arc<Mutex<Vec<(String, String, Value)>>>;
+
+/// A Bot API stub. Token `busy` always gets 409 from getUpdates, as when another
+/// instance polls. Records each request as (method, query, body).
+fn bot_api() -> (String, Seen) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let seen: Seen = Arc::default();
+    let log = seen.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let request = match http::read_request(&mut BufReader::new(&stream)) {
+                Ok(r) => r,
+                Err(_) => continue,
+            };
+            let (token, method) = request.path.trim_start_matches("/bot").split_once('/').unwrap_or_default();
+            let body = serde_json::from_slice(&request.body).unwrap_or(Value::Null);
+            log.lock().unwrap().push((method.to_string(), request.query.clone(), body));
+            let result = match method {
+                "getUpdates" if token == "busy" => json!({ "ok": false, "error_code": 409, "description": "Conflict: terminated by other getUpdates request" }),
+                "getUpdates" if request.query.contains("offset=0&") => json!({ "ok": true, "result": updates() }),
+                "getUpdates" => json!({ "ok": true, "result": [] }),
+                "getMe" => json!({ "ok": true, "result": { "id": 42, "username": "redclaw_bot" } }),
+                "sendMessage" => json!({ "ok": true, "result": { "message_id": 7 } }),
+                _ => json!({ "ok": true, "result": true }),
+            };
+            let _ = http::write_response(&mut stream, 200, "application/json", result.to_string().as_bytes());
+        }
+    });
+    (url, seen)
+}
+
+fn updates() -> Value {
+    let group = json!({ "id": -100123, "type": "supergroup" });
+    let alice = json!({ "id": 1, "username": "alice" });
+    json!([
+        { "update_id": 10, "message": { "message_id": 98, "chat": group, "from": alice, "text": "hello all",
+            "message_thread_id": 5, "is_topic_message": true } },
+        { "update_id": 11, "message": { "message_id": 99, "chat": group, "from": alice, "text": "@redclaw_bot what's up?",
+            "message_thread_id": 5, "is_topic_message": true } },
+        { "update_id": 12, "message": { "message_id": 3, "chat": { "id": 555, "type": "private" },
+            "from": { "id": 2, "username": "mallory" }, "text": "/model" } },
+        { "update_id": 13, "message": { "message_id": 100, "chat": group, "from": alice, "text": "thanks",
+            "reply_to_message": { "message_id": 50, "chat": group, "from": { "id": 42, "username": "redclaw_bot" } } } },
+        { "update_id": 14, "message": { "chat": "unreadable" } },
+    ])
+}
+
+fn last_sent(seen: &Seen) -> Value {
+    seen.lock().unwrap().iter().rev().find(|(m, _, _)| m == "sendMessage").map(|(_, _, b)| b.clone()).unwrap()
+}
+
+#[test]
+fn test_telegram_groups_topics_and_offset() {
+    let (url, seen) = bot_api();
+    let dir = std::env::temp_dir().join(format!("redclaw_telegram_{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    let offset_file = dir.join("telegram_offset.json");
+
+    let mut bot = TelegramBot::new("tok".to_string(), vec!["alice".to_string()])
+        .with_api_base(&url)
+        .with_offset_file(offset_file.clone());
+    bot.connect().unwrap();
+    assert!(seen.lock().unwrap().iter().any(|(m, _, _)| m == "setMyCommands"));
+
+    // The unreadable update is skipped, but the offset still moves past it
+    let messages = bot.receive().unwrap();
+    assert_eq!(messages.len(), 4);
+    assert_eq!(read_offset(&offset_file), 15);
+
+    // In groups only mentions, commands and replies to the bot are for it
+    let addressed: Vec<bool> = messages.iter().map(|m| bot.is_addressed(m)).collect();
+    assert_eq!(addressed, [false, true, true, true]);
+
+    let question = &messages[1];
+    assert_eq!(question.text, "what's up?");
+    assert_eq!(question.user_name, "alice");
+    assert_eq!(question.conversation, "-100123-topic-5");
+    let key = bot.session_key(&question.conversation);
+    assert_eq!(key, "-100123-topic-5");
+    SessionStore::validate_key(&key).unwrap();
+    assert_eq!(bot.session_key(&messages[3].conversation), "-100123");
+
+    assert!(bot.is_allowed(question));
+    assert!(!bot.is_allowed(&messages[2]));
+
+    // Answers in a group quote the question and stay in its topic
+    bot.answer(question, "Stopping the current reply...").unwrap();
+    let sent = last_sent(&seen);
+    assert_eq!(sent["chat_id"], -100123);
+    assert_eq!(sent["message_thread_id"], 5);
+    assert_eq!(sent["reply_to_message_id"], 99);
+
+    bot.send_text(&question.conversation, "later").unwrap();
+    let sent = last_sent(&seen);
+    assert_eq!(sent["message_thread_id"], 5);
+    assert!(sent.get("reply_to_message_id").is_none());
+
+    // A restarted bot resumes after the last update it saw
+    let mut restarted = TelegramBot::new("tok".to_string(), Vec::new())
+        .with_api_base(&url)
+        .with_offset_file(offset_file);
+    restarted.connect().unwrap();
+    assert!(restarted.receive().unwrap().is_empty());
+    assert!(seen.lock().unwrap().iter().any(|(m, q, _)| m == "getUpdates" && q.starts_with("offset=15&")));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_telegram_stops_after_repeated_conflicts() {
+    let (url, _) = bot_api();
+    let mut bot = TelegramBot::new("busy".to_string(), Vec::new()).with_api_base(&url);
+    bot.connect().unwrap();
+    for _ in 0..4 {
+        let err = bot.receive().unwrap_err();
+        assert!(!err.is::<Fatal>(), "{}", err);
+    }
+    assert!(bot.receive().unwrap_err().is::<Fatal>());
+}
@@ -0,0 +1,46 @@
+use redclaw::agent::llm::{Message, MessageContent};
+use redclaw::tools::{Attachment, Tool, ToolContext, ToolOutput};
+use redclaw::tools::builtin::ExecTool;
+use serde_json::json;
+
+#[test]
+fn test_exec_failure_sets_error_flag() {
+    let exec = ExecTool { working_dir: "/tmp".to_string() };
+    let ok = exec.execute(json!({"command": "echo hi"}), &ToolContext::default()).unwrap();
+    assert!(!ok.is_error);
+    assert_eq!(ok.to_text(), "hi\n");
+
+    let failed = exec.execute(json!({"command": "exit 3"}), &ToolContext::default()).unwrap();
+    assert!(failed.is_error);
+    assert!(failed.to_text().starts_with("Error: Command exited with status 3"));
+}
+
+#[test]
+fn test_tool_output_maps_to_wire_format() {
+    let output = ToolOutput::text("done")
+        .with_data(json!({"n": 1}))
+        .with_attachment(Attachment::from_bytes("dot.png", "image/png", vec![1, 2, 3]));
+    assert_eq!(output.to_text(), "done\n{\"n\":1}\n[Attachment: dot.png (image/png, 3 bytes)]");
+
+    let part = output.attachments[0].to_image_part().unwrap();
+    let message = Message {
+        role: "user".to_string(),
+        content: Some(MessageContent::Parts(vec![part])),
+        name: None,
+        tool_call_id: None,
+        tool_calls: None,
+    };
+    assert_eq!(
+        serde_json::to_value(&message).unwrap(),
+        json!({"role": "user", "content": [{"type": "image_url", "image_url": {"url": "data:image/png;base64,AQID"}}]})
+    );
+
+    let pdf = Attachment::from_bytes("a.pdf", "application/pdf", vec![0]);
+    assert!(pdf.to_image_part().is_none());
+}
+
+#[test]
+fn test_plain_string_content_still_deserializes() {
+    let message: Message = serde_json::from_str(r#"{"role":"assistant","content":"hello"}"#).unwrap();
+    assert_eq!(message.text(), "hello");
+}
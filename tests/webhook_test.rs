@@ -0,0 +1,47 @@
+use redclaw::agent::channels::Webhook;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+
+const UPDATE: &str = r#"{"update_id":1001,"message":{"message_id":5,"chat":{"id":42,"type":"private"},"from":{"id":7,"is_bot":false,"first_name":"Ana"},"date":1700000000,"text":"hello"}}"#;
+
+fn post(addr: SocketAddr, path: &str, secret: Option<&str>, body: &str) -> std::thread::JoinHandle<String> {
+    let mut request = format!("POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n", path, body.len());
+    if let Some(s) = secret {
+        request.push_str(&format!("X-Telegram-Bot-Api-Secret-Token: {}\r\n", s));
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+    std::thread::spawn(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    })
+}
+
+#[test]
+fn test_webhook_accepts_authenticated_updates_only() {
+    let webhook = Webhook::bind("127.0.0.1:0", "/telegram", "s3cret").unwrap();
+    let addr = webhook.local_addr().unwrap();
+
+    let client = post(addr, "/telegram", Some("s3cret"), UPDATE);
+    let update: serde_json::Value = webhook.next_update().unwrap().expect("update accepted");
+    assert!(client.join().unwrap().starts_with("HTTP/1.1 200 OK\r\n"));
+    assert_eq!(update["update_id"], 1001);
+    assert_eq!(update["message"]["text"], "hello");
+
+    let cases = [
+        ("/telegram", None, UPDATE, "401"),
+        ("/telegram", Some("wrong!"), UPDATE, "401"),
+        ("/other", Some("s3cret"), UPDATE, "404"),
+        ("/telegram", Some("s3cret"), "{not json", "400"),
+    ];
+    for (path, secret, body, status) in cases {
+        let client = post(addr, path, secret, body);
+        let update: Option<serde_json::Value> = webhook.next_update().unwrap();
+        assert!(update.is_none());
+        let response = client.join().unwrap();
+        assert!(response.starts_with(&format!("HTTP/1.1 {} ", status)), "{} for {}", response, path);
+    }
+}
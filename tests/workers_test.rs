@@ -0,0 +1,50 @@
+use redclaw::agent::workers::ChatWorkers;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[test]
+fn test_tasks_keep_order_per_key() {
+    let seen: Arc<Mutex<Vec<(String, u32)>>> = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    let workers = ChatWorkers::new(3, move |key: &str, n: u32| {
+        // Later tasks are quicker, so any reordering within a key would show
+        std::thread::sleep(Duration::from_millis(20 - n as u64 * 4));
+        log.lock().unwrap().push((key.to_string(), n));
+    });
+    for n in 0..5 {
+        workers.submit("a", n).unwrap();
+        workers.submit("b", n).unwrap();
+    }
+    assert!(workers.wait_idle(Duration::from_secs(5)));
+
+    let seen = seen.lock().unwrap();
+    for key in ["a", "b"] {
+        let order: Vec<u32> = seen.iter().filter(|(k, _)| k == key).map(|(_, n)| *n).collect();
+        assert_eq!(order, vec![0, 1, 2, 3, 4]);
+    }
+}
+
+#[test]
+fn test_concurrency_is_bounded_and_idle_workers_exit() {
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (r, p) = (running.clone(), peak.clone());
+    let workers = ChatWorkers::with_idle_timeout(2, Duration::from_millis(50), move |_key: &str, _: ()| {
+        let now = r.fetch_add(1, Ordering::SeqCst) + 1;
+        p.fetch_max(now, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(30));
+        r.fetch_sub(1, Ordering::SeqCst);
+    });
+    for chat in 0..6 {
+        workers.submit(&chat.to_string(), ()).unwrap();
+    }
+    assert!(workers.wait_idle(Duration::from_secs(5)));
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(workers.active_keys(), 0);
+    // A key whose worker has exited gets a fresh one
+    workers.submit("0", ()).unwrap();
+    assert!(workers.wait_idle(Duration::from_secs(5)));
+}